@@ -0,0 +1,142 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use flowguide::{
+    hierarchy::{self, BuildOptions, IncrementalHierarchy},
+    mesh::{InputMesh, ProcessMesh},
+    orientation,
+    shapes::{icosphere, plane},
+};
+
+const SUBDIVISIONS: [usize; 3] = [3, 4, 5];
+
+fn process_mesh(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ProcessMesh::from");
+    for subdivisions in SUBDIVISIONS {
+        let mesh = icosphere(subdivisions);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(subdivisions),
+            &mesh,
+            |b, mesh| b.iter_batched(|| mesh.clone(), ProcessMesh::from, BatchSize::LargeInput),
+        );
+    }
+    group.finish();
+}
+
+fn build_hierarchy(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hierarchy::build");
+    for subdivisions in SUBDIVISIONS {
+        let mesh = icosphere(subdivisions);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(subdivisions),
+            &mesh,
+            |b, mesh| {
+                b.iter_batched(
+                    || ProcessMesh::from(mesh.clone()),
+                    hierarchy::build,
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+fn update_hierarchy(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hierarchy::update");
+    for subdivisions in SUBDIVISIONS {
+        let mesh = icosphere(subdivisions);
+        // Push out a small cap, keeping every vertex index
+        let edited = {
+            let mesh = ProcessMesh::from(mesh.clone());
+            let vertices = mesh
+                .vertices
+                .iter()
+                .map(|v| if v.x > 0.97 { *v * 1.05 } else { *v })
+                .collect();
+            InputMesh::from_triangles(vertices, mesh.tris)
+        };
+        group.bench_with_input(
+            BenchmarkId::from_parameter(subdivisions),
+            &(mesh, edited),
+            |b, (mesh, edited)| {
+                b.iter_batched(
+                    || {
+                        let options = BuildOptions::default();
+                        let hierarchy =
+                            IncrementalHierarchy::new(ProcessMesh::from(mesh.clone()), &options);
+                        (hierarchy, ProcessMesh::from(edited.clone()))
+                    },
+                    |(mut hierarchy, edited)| hierarchy.update(edited),
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+fn smoothing_sweep(c: &mut Criterion) {
+    let mut group = c.benchmark_group("extrinsic_smooth");
+    for subdivisions in SUBDIVISIONS {
+        let hierarchy = hierarchy::build(ProcessMesh::from(icosphere(subdivisions)));
+        let field = orientation::hierarchical_smoothing(&hierarchy, 0, None, &[]).unwrap();
+        let mesh = &hierarchy[hierarchy.len() - 1].mesh;
+        group.bench_function(BenchmarkId::from_parameter(subdivisions), |b| {
+            b.iter_batched_ref(
+                || field.clone(),
+                |field| orientation::extrinsic_smooth(mesh, field, None),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+/// Binary little-endian PLY of `mesh`, with positions and normals per vertex.
+fn binary_ply(mesh: &ProcessMesh) -> Vec<u8> {
+    let mut data = format!(
+        "ply\nformat binary_little_endian 1.0\nelement vertex {}\n\
+         property float x\nproperty float y\nproperty float z\n\
+         property float nx\nproperty float ny\nproperty float nz\n\
+         element face {}\nproperty list uchar int vertex_indices\nend_header\n",
+        mesh.vertices.len(),
+        mesh.tris.len()
+    )
+    .into_bytes();
+    for (v, n) in mesh.vertices.iter().zip(&mesh.normals) {
+        for x in v.to_array().into_iter().chain(n.to_array()) {
+            data.extend(x.to_le_bytes());
+        }
+    }
+    for tri in &mesh.tris {
+        data.push(3);
+        for i in tri {
+            data.extend((*i as i32).to_le_bytes());
+        }
+    }
+    data
+}
+
+fn load_ply(c: &mut Criterion) {
+    // A million vertices
+    let data = binary_ply(&ProcessMesh::from(plane(999)));
+    let mut group = c.benchmark_group("load_ply");
+    group.sample_size(10);
+    group.bench_function("plane_1m", |b| {
+        b.iter_batched(
+            || data.clone(),
+            |data| InputMesh::from_bytes(data, None, None).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    process_mesh,
+    build_hierarchy,
+    update_hierarchy,
+    smoothing_sweep,
+    load_ply
+);
+criterion_main!(benches);
@@ -0,0 +1,65 @@
+//! Run the full processing pipeline on a mesh file and report per-stage timings.
+//!
+//! Usage: `cargo run --release --example bench -- model.ply [iterations]`
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() -> anyhow::Result<()> {
+    use std::time::Instant;
+
+    use anyhow::Context;
+    use flowguide::{
+        hierarchy,
+        mesh::{InputMesh, ProcessMesh},
+        orientation,
+    };
+
+    let mut args = std::env::args().skip(1);
+    let path = args
+        .next()
+        .context("Usage: bench <model.ply> [iterations]")?;
+    let iterations = match args.next() {
+        Some(n) => n.parse().context("Iterations must be an integer")?,
+        None => 10,
+    };
+
+    let data = std::fs::read(&path).with_context(|| format!("Could not read {}", path))?;
+
+    let mut st = Instant::now();
+    let model = InputMesh::from_bytes(data, Some(path.clone()), None)
+        .map_err(|e| anyhow::anyhow!("Could not load {}: {:?}", path, e))?;
+    println!("Loaded mesh in {:?}", st.elapsed());
+
+    st = Instant::now();
+    let processed = ProcessMesh::from(model);
+    println!(
+        "Processed {} vertices, {} tris in {:?}",
+        processed.vertices.len(),
+        processed.tris.len(),
+        st.elapsed()
+    );
+
+    st = Instant::now();
+    let hierarchy = hierarchy::build(processed);
+    println!(
+        "Built {} hierarchy levels in {:?}",
+        hierarchy.len(),
+        st.elapsed()
+    );
+    for (i, level) in hierarchy.iter().enumerate() {
+        println!("  level {}: {} vertices", i, level.mesh.vertices.len());
+    }
+
+    st = Instant::now();
+    let o_field = orientation::hierarchical_smoothing(&hierarchy, iterations, None, &[])?;
+    println!(
+        "Oriented {} vertices with {} iterations per level in {:?}",
+        o_field.len(),
+        iterations,
+        st.elapsed()
+    );
+
+    Ok(())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn main() {}
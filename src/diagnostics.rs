@@ -0,0 +1,88 @@
+//! Warnings raised while loading and processing a mesh, collected alongside the results so
+//! that apps can show them and tests can check them, and logged as well.
+
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// Something was changed as asked, such as holes filled.
+    Info,
+    /// The input was unusual or damaged and was worked around.
+    Warning,
+}
+
+/// One warning, optionally pointing at the vertex or file line it concerns. The message reads on
+/// its own, mentioning the vertex or line too, so it can be shown as is.
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    severity: Severity,
+    message: String,
+    vertex: Option<usize>,
+    line: Option<usize>,
+}
+
+impl Diagnostic {
+    pub fn info(message: impl Into<String>) -> Self {
+        Self::new(Severity::Info, message.into())
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, message.into())
+    }
+
+    fn new(severity: Severity, message: String) -> Self {
+        Self {
+            severity,
+            message,
+            vertex: None,
+            line: None,
+        }
+    }
+
+    /// Point the diagnostic at a vertex of the mesh.
+    pub fn at_vertex(mut self, vertex: usize) -> Self {
+        self.vertex = Some(vertex);
+        self
+    }
+
+    /// Point the diagnostic at a line of the source file, counting from 1.
+    pub fn at_line(mut self, line: usize) -> Self {
+        self.line = Some(line);
+        self
+    }
+
+    /// Log the diagnostic and add it to `diagnostics`.
+    pub(crate) fn report(self, diagnostics: &mut Vec<Diagnostic>) {
+        let level = match self.severity {
+            Severity::Info => log::Level::Info,
+            Severity::Warning => log::Level::Warn,
+        };
+        log::log!(level, "{}", self.message);
+        diagnostics.push(self);
+    }
+}
+
+#[wasm_bindgen]
+impl Diagnostic {
+    #[wasm_bindgen(getter)]
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn message(&self) -> String {
+        self.message.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn vertex(&self) -> Option<usize> {
+        self.vertex
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn line(&self) -> Option<usize> {
+        self.line
+    }
+}
@@ -0,0 +1,276 @@
+use anyhow::{bail, Result};
+use byteorder::{ByteOrder, LittleEndian};
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    mesh::{check_crease_angle, InputMesh, NonFinitePolicy},
+    obj::load_obj,
+    ply::{load_ply_progressive, LoadProgress},
+    stl::{load_ascii_stl, load_binary_stl, FACET_SIZE, HEADER_SIZE},
+    stream::{AsyncStreamReader, DEFAULT_MIN_FILL},
+};
+
+/// Settings for loading a mesh, both for [`load_mesh_progressive`] and the [`InputMesh`]
+/// loaders. The defaults load any well-formed file.
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoadOptions {
+    /// Reject PLY and binary STL files with data left over after their declared elements, as
+    /// with [`load_ply_progressive`]. OBJ and ASCII STL have no declared counts to check against.
+    pub(crate) strict: bool,
+    /// Names of the PLY vertex properties holding the x, y and z coordinates.
+    pub(crate) position_names: [String; 3],
+    /// Names of the PLY vertex properties holding the normal, which is computed from the faces
+    /// if the file has none.
+    pub(crate) normal_names: [String; 3],
+    /// What to do with vertices that have NaN or infinite coordinates.
+    pub(crate) non_finite: NonFinitePolicy,
+    /// Bytes gathered from the browser's stream before parsing continues.
+    pub(crate) min_chunk_size: usize,
+    /// Crease angle given to the loaded mesh, as with [`InputMesh::set_crease_angle`].
+    pub(crate) crease_angle: Option<f32>,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            strict: false,
+            position_names: ["x", "y", "z"].map(String::from),
+            normal_names: ["nx", "ny", "nz"].map(String::from),
+            non_finite: NonFinitePolicy::Drop,
+            min_chunk_size: DEFAULT_MIN_FILL,
+            crease_angle: None,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl LoadOptions {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> LoadOptions {
+        Default::default()
+    }
+
+    /// Make PLY and binary STL files with data left over after their declared elements fail to
+    /// load instead of being silently cut short.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// PLY vertex properties to read x, y and z from, if not those.
+    pub fn set_position_names(&mut self, names: Vec<String>) -> Result<(), JsValue> {
+        self.position_names = names
+            .try_into()
+            .map_err(|_| JsValue::from("Position names must have 3 elements"))?;
+        Ok(())
+    }
+
+    /// PLY vertex properties to read the normal from, if not nx, ny and nz. Normals read from the
+    /// file are taken to point outward, as is usual.
+    pub fn set_normal_names(&mut self, names: Vec<String>) -> Result<(), JsValue> {
+        self.normal_names = names
+            .try_into()
+            .map_err(|_| JsValue::from("Normal names must have 3 elements"))?;
+        Ok(())
+    }
+
+    /// What to do with vertices that have NaN or infinite coordinates. They are dropped by
+    /// default.
+    pub fn set_non_finite(&mut self, non_finite: NonFinitePolicy) {
+        self.non_finite = non_finite;
+    }
+
+    /// Gather small chunks from the browser's stream until at least `min_chunk_size` bytes are
+    /// available before parsing continues, 64 KiB by default.
+    pub fn set_min_chunk_size(&mut self, min_chunk_size: usize) {
+        self.min_chunk_size = min_chunk_size;
+    }
+
+    /// Draw edges between faces meeting at more than `crease_angle` degrees hard, as with
+    /// [`InputMesh::set_crease_angle`].
+    pub fn set_crease_angle(&mut self, crease_angle: Option<f32>) -> Result<(), JsValue> {
+        check_crease_angle(crease_angle)?;
+        self.crease_angle = crease_angle;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MeshFormat {
+    Ply,
+    Obj,
+    AsciiStl,
+    BinaryStl,
+}
+
+impl MeshFormat {
+    fn from_extension(file_name: &str) -> Option<Self> {
+        let (_, ext) = file_name.rsplit_once('.')?;
+        match ext.to_ascii_lowercase().as_str() {
+            "ply" => Some(Self::Ply),
+            "obj" => Some(Self::Obj),
+            "stl" => Some(Self::BinaryStl),
+            _ => None,
+        }
+    }
+}
+
+async fn sniff(reader: &mut AsyncStreamReader) -> Option<MeshFormat> {
+    let len = reader.remaining();
+    let prefix = reader.read_up_to(HEADER_SIZE).await;
+
+    // Binary STL is the one format without a signature, but it is exactly as long as its facet
+    // count says, which needs neither a file name nor any particular header
+    if let (Some(len), Some(count)) = (len, prefix.get(HEADER_SIZE - 4..HEADER_SIZE)) {
+        let count = LittleEndian::read_u32(count) as u64;
+        if HEADER_SIZE as u64 + FACET_SIZE as u64 * count == len as u64 {
+            return Some(MeshFormat::BinaryStl);
+        }
+    }
+
+    let first = prefix.split(|b| *b == b'\n').next()?;
+    let first = String::from_utf8_lossy(first);
+    let first = first.trim();
+
+    if first == "ply" {
+        return Some(MeshFormat::Ply);
+    }
+
+    if first.starts_with("solid") {
+        // Binary STL headers are free-form and sometimes start with "solid" as well. Those not
+        // caught by their length are given away by bytes that aren't text, such as NUL padding
+        // or the top byte of any facet count under 16 million. The prefix may cut off the last
+        // character of text.
+        let utf8 = std::str::from_utf8(prefix).map_or_else(|e| e.error_len().is_none(), |_| true);
+        let text = utf8
+            && !prefix
+                .iter()
+                .any(|b| b.is_ascii_control() && !b.is_ascii_whitespace());
+        return if text {
+            Some(MeshFormat::AsciiStl)
+        } else {
+            Some(MeshFormat::BinaryStl)
+        };
+    }
+
+    let keyword = first.split_whitespace().next()?;
+    match keyword {
+        "#" | "v" | "vn" | "vt" | "f" | "o" | "g" | "s" | "mtllib" | "usemtl" => {
+            Some(MeshFormat::Obj)
+        }
+        _ if keyword.starts_with('#') => Some(MeshFormat::Obj),
+        _ => None,
+    }
+}
+
+/// Work out the format of the stream from its first bytes and its length, falling back to the extension of
+/// `file_name` if the contents are ambiguous. The reader is left at the start of the stream.
+pub async fn detect_format(
+    reader: &mut AsyncStreamReader,
+    file_name: Option<&str>,
+) -> Result<MeshFormat> {
+    reader.mark();
+    let sniffed = sniff(reader).await;
+    let rewound = reader.reset_to_mark();
+    reader.clear_mark();
+    rewound?;
+
+    match sniffed.or_else(|| file_name.and_then(MeshFormat::from_extension)) {
+        Some(format) => Ok(format),
+        None => bail!("Unrecognized mesh format (tried PLY, OBJ, and STL)"),
+    }
+}
+
+/// Load a mesh of any supported format, reporting the partially parsed mesh to `on_batch` as it
+/// goes. Only PLY is parsed progressively; other formats report once, with the complete mesh.
+///
+/// The property names in `options` only apply to PLY, and `strict` to PLY and binary STL. Its
+/// remaining settings are for the caller to apply, as [`InputMesh`]'s loaders do, since they
+/// don't depend on the format.
+pub async fn load_mesh_progressive(
+    reader: &mut AsyncStreamReader,
+    file_name: Option<&str>,
+    options: &LoadOptions,
+    on_batch: &mut dyn FnMut(LoadProgress),
+) -> Result<InputMesh> {
+    let mesh = match detect_format(reader, file_name).await? {
+        MeshFormat::Ply => return load_ply_progressive(reader, options, on_batch).await,
+        MeshFormat::Obj => load_obj(reader).await?,
+        MeshFormat::AsciiStl => load_ascii_stl(reader).await?,
+        MeshFormat::BinaryStl => load_binary_stl(reader, options).await?,
+    };
+    on_batch(LoadProgress {
+        mesh: &mesh,
+        num_vertices: mesh.vertices.len(),
+        num_faces: mesh.tris.len(),
+    });
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(data: Vec<u8>, file_name: Option<&str>) -> Option<MeshFormat> {
+        let mut reader = AsyncStreamReader::from_bytes(data);
+        futures::executor::block_on(detect_format(&mut reader, file_name)).ok()
+    }
+
+    /// A binary STL of `count` empty facets under `header`.
+    fn binary_stl(header: &[u8], count: usize) -> Vec<u8> {
+        let mut data = header.to_vec();
+        data.resize(HEADER_SIZE - 4, b' ');
+        data.extend((count as u32).to_le_bytes());
+        data.resize(HEADER_SIZE + FACET_SIZE * count, 0);
+        data
+    }
+
+    #[test]
+    fn binary_stl_is_recognized_by_its_length() {
+        // Neither a name nor a header to go by, and far more than the mark limit without a newline
+        let data = binary_stl(b"", 30_000);
+        assert!(data.len() > 1 << 20);
+        assert_eq!(detect(data, None), Some(MeshFormat::BinaryStl));
+
+        let data = binary_stl(b"solid exported by a tool that writes text headers", 2);
+        assert_eq!(detect(data, None), Some(MeshFormat::BinaryStl));
+
+        // Off by a byte is not a binary STL, and has nothing else to go by
+        let mut data = binary_stl(b"", 2);
+        data.push(0);
+        assert_eq!(detect(data, None), None);
+    }
+
+    #[test]
+    fn text_formats_are_recognized_by_their_first_line() {
+        let ascii = b"solid cube\nfacet normal 0 0 1\nendfacet\nendsolid cube\n";
+        assert_eq!(detect(ascii.to_vec(), None), Some(MeshFormat::AsciiStl));
+        assert_eq!(
+            detect(b"ply\r\nformat ascii 1.0\n".to_vec(), None),
+            Some(MeshFormat::Ply)
+        );
+        assert_eq!(
+            detect(b"# comment\nv 0 0 0".to_vec(), None),
+            Some(MeshFormat::Obj)
+        );
+        assert_eq!(
+            detect(b"v 0 0 0".to_vec(), Some("mesh.ply")),
+            Some(MeshFormat::Obj)
+        );
+        assert_eq!(
+            detect(b"unknown".to_vec(), Some("mesh.PLY")),
+            Some(MeshFormat::Ply)
+        );
+        assert_eq!(detect(Vec::new(), None), None);
+    }
+
+    #[test]
+    fn binary_header_starting_with_solid_is_binary_stl() {
+        let mut data = b"solid header\n".to_vec();
+        data.extend([0xff, 0xfe, 0x80, b'\n']);
+        let mut reader = AsyncStreamReader::from_bytes(data);
+
+        let format = futures::executor::block_on(detect_format(&mut reader, None)).unwrap();
+        assert_eq!(format, MeshFormat::BinaryStl);
+    }
+}
@@ -0,0 +1,261 @@
+use anyhow::{bail, Result};
+use glam::Vec3;
+
+use crate::mesh::{flat_indices, interleaved_vertices, ProcessMesh};
+
+const GLB_MAGIC: u32 = 0x4654_6c67;
+const CHUNK_JSON: u32 = 0x4e4f_534a;
+const CHUNK_BIN: u32 = 0x004e_4942;
+
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+/// Binary buffer under construction, along with the JSON for its views and accessors.
+#[derive(Default)]
+struct Builder {
+    bin: Vec<u8>,
+    views: Vec<String>,
+    accessors: Vec<String>,
+}
+
+impl Builder {
+    /// Append `data` as a new buffer view, with elements `stride` bytes apart if it interleaves
+    /// several attributes, returning the view index.
+    fn push_view(&mut self, data: &[u8], target: u32, stride: Option<usize>) -> usize {
+        let offset = self.bin.len();
+        self.bin.extend_from_slice(data);
+        let stride = stride.map_or(String::new(), |s| format!(r#","byteStride":{}"#, s));
+        self.views.push(format!(
+            r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":{}{}}}"#,
+            offset,
+            data.len(),
+            target,
+            stride
+        ));
+        self.views.len() - 1
+    }
+
+    /// Add an accessor over `view` starting `offset` bytes into each element, returning the
+    /// accessor index.
+    fn push_accessor(
+        &mut self,
+        view: usize,
+        offset: usize,
+        component_type: u32,
+        count: usize,
+        ty: &str,
+        bounds: Option<(Vec3, Vec3)>,
+    ) -> usize {
+        let bounds = bounds.map_or(String::new(), |(min, max)| {
+            format!(
+                r#","min":[{},{},{}],"max":[{},{},{}]"#,
+                min.x, min.y, min.z, max.x, max.y, max.z
+            )
+        });
+        self.accessors.push(format!(
+            r#"{{"bufferView":{},"byteOffset":{},"componentType":{},"count":{},"type":"{}"{}}}"#,
+            view, offset, component_type, count, ty, bounds
+        ));
+        self.accessors.len() - 1
+    }
+
+    /// Append `data` as a new buffer view with an accessor over all of it, returning the
+    /// accessor index.
+    fn push(
+        &mut self,
+        data: &[u8],
+        target: u32,
+        component_type: u32,
+        count: usize,
+        ty: &str,
+    ) -> usize {
+        let view = self.push_view(data, target, None);
+        self.push_accessor(view, 0, component_type, count, ty, None)
+    }
+}
+
+fn vec3_bytes(v: &[Vec3]) -> Vec<u8> {
+    v.iter()
+        .flat_map(|v| v.to_array())
+        .flat_map(f32::to_le_bytes)
+        .collect()
+}
+
+/// Write `mesh` as a binary glTF, with the orientation field stored in a custom `_ORIENTATION`
+/// vertex attribute and optionally the dual areas in `_DUAL_AREA`. Fails on a mesh without
+/// triangles, which glTF has no valid way to hold, or if `o_field` doesn't have one entry per
+/// vertex.
+///
+/// Positions and normals are interleaved and the indices laid out as for the renderer's
+/// buffers. Triangles keep their winding, counter-clockwise from outside as glTF expects, and the
+/// crate's inward normals are flipped to match it (see [`face_normal`](crate::mesh::face_normal)).
+pub fn write_glb(mesh: &ProcessMesh, o_field: &[Vec3], include_dual_area: bool) -> Result<Vec<u8>> {
+    if mesh.tris.is_empty() {
+        bail!("Cannot export a mesh without triangles to glTF");
+    }
+    if o_field.len() != mesh.vertices.len() {
+        bail!(
+            "Field has {} entries for a mesh of {} vertices",
+            o_field.len(),
+            mesh.vertices.len()
+        );
+    }
+    let bounds = mesh.vertices.first().map(|first| {
+        mesh.vertices
+            .iter()
+            .fold((*first, *first), |(min, max), v| (min.min(*v), max.max(*v)))
+    });
+
+    // The buffers are written in native byte order, which is little-endian as glTF requires on
+    // every target the crate is built for
+    let mut builder = Builder::default();
+    let n = mesh.vertices.len();
+    let outward = mesh.normals.iter().map(|n| -*n).collect::<Vec<_>>();
+    let vertices = interleaved_vertices(&mesh.vertices, &outward).collect::<Vec<_>>();
+    let stride = std::mem::size_of_val(&vertices[0]);
+    let view = builder.push_view(bytemuck::cast_slice(&vertices), ARRAY_BUFFER, Some(stride));
+    let position = builder.push_accessor(view, 0, FLOAT, n, "VEC3", bounds);
+    let normal = builder.push_accessor(
+        view,
+        std::mem::size_of::<[f32; 3]>(),
+        FLOAT,
+        n,
+        "VEC3",
+        None,
+    );
+    let orientation = builder.push(&vec3_bytes(o_field), ARRAY_BUFFER, FLOAT, n, "VEC3");
+
+    let mut attributes = format!(
+        r#""POSITION":{},"NORMAL":{},"_ORIENTATION":{}"#,
+        position, normal, orientation
+    );
+    if include_dual_area {
+        let dual_area = builder.push(
+            bytemuck::cast_slice(&mesh.dual_area),
+            ARRAY_BUFFER,
+            FLOAT,
+            n,
+            "SCALAR",
+        );
+        attributes += &format!(r#","_DUAL_AREA":{}"#, dual_area);
+    }
+
+    let indices = flat_indices(&mesh.tris).collect::<Vec<_>>();
+    let indices = builder.push(
+        bytemuck::cast_slice(&indices),
+        ELEMENT_ARRAY_BUFFER,
+        UNSIGNED_INT,
+        mesh.tris.len() * 3,
+        "SCALAR",
+    );
+
+    let mut json = format!(
+        concat!(
+            r#"{{"asset":{{"version":"2.0","generator":"flowguide"}},"#,
+            r#""scene":0,"scenes":[{{"nodes":[0]}}],"nodes":[{{"mesh":0}}],"#,
+            r#""meshes":[{{"primitives":[{{"attributes":{{{}}},"indices":{},"mode":4}}]}}],"#,
+            r#""buffers":[{{"byteLength":{}}}],"bufferViews":[{}],"accessors":[{}]}}"#
+        ),
+        attributes,
+        indices,
+        builder.bin.len(),
+        builder.views.join(","),
+        builder.accessors.join(",")
+    )
+    .into_bytes();
+
+    // Both chunks must be 4-byte aligned
+    while json.len() % 4 != 0 {
+        json.push(b' ');
+    }
+    while builder.bin.len() % 4 != 0 {
+        builder.bin.push(0);
+    }
+
+    let length = 12 + 8 + json.len() + 8 + builder.bin.len();
+    let mut glb = Vec::with_capacity(length);
+    for word in [GLB_MAGIC, 2, length as u32, json.len() as u32, CHUNK_JSON] {
+        glb.extend_from_slice(&word.to_le_bytes());
+    }
+    glb.extend_from_slice(&json);
+    for word in [builder.bin.len() as u32, CHUNK_BIN] {
+        glb.extend_from_slice(&word.to_le_bytes());
+    }
+    glb.extend_from_slice(&builder.bin);
+    Ok(glb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mesh::InputMesh, shapes};
+
+    #[test]
+    fn glb_of_a_cube() {
+        let mesh = ProcessMesh::from(shapes::cube());
+        let o_field = vec![Vec3::X; mesh.vertices.len()];
+        let glb = write_glb(&mesh, &o_field, true).unwrap();
+
+        let word = |i: usize| u32::from_le_bytes(glb[4 * i..4 * i + 4].try_into().unwrap());
+        assert_eq!(word(0), GLB_MAGIC);
+        assert_eq!(word(2) as usize, glb.len());
+        let json_len = word(3) as usize;
+        let json = std::str::from_utf8(&glb[20..20 + json_len]).unwrap();
+        assert!(json.contains(r#""byteStride":24"#));
+        assert!(json.contains(r#""_DUAL_AREA":"#));
+
+        // Positions, normals, orientations, dual areas and indices, padded to 4 bytes
+        let bin_len = u32::from_le_bytes(glb[20 + json_len..24 + json_len].try_into().unwrap());
+        assert_eq!(bin_len as usize, 8 * (24 + 12 + 4) + 12 * 12);
+    }
+
+    #[test]
+    fn reparsed_glb_has_outward_normals_and_winding() {
+        let mesh = ProcessMesh::from(shapes::icosphere(2));
+        let o_field = vec![Vec3::X; mesh.vertices.len()];
+        let glb = write_glb(&mesh, &o_field, false).unwrap();
+
+        // The interleaved positions and normals are the first view, at the start of the binary
+        // chunk, and the indices the last
+        let json_len = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+        let bin = &glb[28 + json_len..];
+        let float = |i: usize| f32::from_le_bytes(bin[4 * i..4 * i + 4].try_into().unwrap());
+        let vertex = |v: usize| {
+            let field = |k: usize| Vec3::from_array([0, 1, 2].map(|j| float(6 * v + 3 * k + j)));
+            (field(0), field(1))
+        };
+        for v in 0..mesh.vertices.len() {
+            let (position, normal) = vertex(v);
+            assert_eq!(position, mesh.vertices[v]);
+            assert!(normal.dot(position) > 0.9, "vertex {}", v);
+        }
+
+        let indices = &bin[bin.len() - 12 * mesh.tris.len()..];
+        for tri in indices.chunks_exact(12) {
+            let [a, b, c] = [0, 1, 2]
+                .map(|k| u32::from_le_bytes(tri[4 * k..4 * k + 4].try_into().unwrap()) as usize)
+                .map(|v| vertex(v).0);
+            // Counter-clockwise seen from outside
+            assert!((b - a).cross(c - a).dot(a + b + c) > 0.0);
+        }
+    }
+
+    #[test]
+    fn glb_of_an_empty_mesh_is_rejected() {
+        let mesh = ProcessMesh::from(InputMesh::default());
+        assert!(write_glb(&mesh, &[], false).is_err());
+    }
+
+    #[test]
+    fn glb_with_a_field_of_the_wrong_length_is_rejected() {
+        let mesh = ProcessMesh::from(shapes::cube());
+        let o_field = vec![Vec3::X; mesh.vertices.len() - 1];
+        let err = write_glb(&mesh, &o_field, false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Field has 7 entries for a mesh of 8 vertices"
+        );
+    }
+}
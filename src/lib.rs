@@ -2,11 +2,22 @@ use std::panic;
 
 use wasm_bindgen::prelude::*;
 
-mod hierarchy;
-mod mesh;
-mod orientation;
+pub mod diagnostics;
+mod format;
+mod gltf;
+pub mod hierarchy;
+pub mod mesh;
+mod obj;
+pub mod orientation;
+pub mod pipeline;
 mod ply;
+pub mod position;
+pub mod remesh;
 mod renderer;
+pub mod rng;
+#[cfg(any(test, feature = "shapes"))]
+pub mod shapes;
+mod stl;
 mod stream;
 
 /*
@@ -0,0 +1,46 @@
+// Shared by the line shaders, which prepend it to their own source and declare `uniforms` with
+// `lines`: x the width of lines in pixels, yz the size of the viewport in pixels
+
+// One vertex of a line widened to a quad, in clip space
+struct LineCorner {
+    position: vec4<f32>,
+    // 0 at the start of the line and 1 at its end
+    along: f32,
+    // Signed distance from the middle of the line, in pixels
+    across: f32,
+}
+
+// Each segment is a screen-aligned quad of two triangles around the line between its ends, since
+// line primitives are only ever a single aliased pixel wide. The quad reaches a pixel past the
+// line on either side, room for the fragment shader to fade out its edges.
+fn expand_line(start: vec4<f32>, end: vec4<f32>, index: u32) -> LineCorner {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(0.0, -1.0),
+    );
+    let corner = corners[index];
+
+    let viewport = uniforms.lines.yz;
+    let direction = (end.xy / end.w - start.xy / start.w) * viewport;
+    let pixels = length(direction);
+    let along = select(vec2<f32>(1.0, 0.0), direction / pixels, pixels > 1e-6);
+    let half_width = 0.5 * uniforms.lines.x + 1.0;
+
+    let position = mix(start, end, corner.x);
+    let offset = vec2<f32>(-along.y, along.x) * corner.y * half_width * 2.0 / viewport;
+
+    var result: LineCorner;
+    result.position = vec4<f32>(position.xy + offset * position.w, position.zw);
+    result.along = corner.x;
+    result.across = corner.y * half_width;
+    return result;
+}
+
+// Opacity of a line at `across` pixels from its middle, fading out over the pixel at its edge
+fn line_coverage(across: f32) -> f32 {
+    return clamp(0.5 * uniforms.lines.x + 0.5 - abs(across), 0.0, 1.0);
+}
@@ -1,33 +1,791 @@
 use futures::FutureExt;
-use glam::Vec3;
+use glam::{DVec3, Vec3};
 use wasm_bindgen::prelude::*;
 
-use crate::{ply::load_ply, stream::AsyncStreamReader};
+use crate::{
+    diagnostics::Diagnostic,
+    format::{load_mesh_progressive, LoadOptions},
+    hierarchy, obj,
+    ply::LoadProgress,
+    stream::AsyncStreamReader,
+};
+
+/// What [`InputMesh::sanitize`] does with vertices that have NaN or infinite coordinates.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NonFinitePolicy {
+    /// Fail, reporting the first offending vertex.
+    Reject,
+    /// Remove the offending vertices along with every triangle using them.
+    Drop,
+}
+
+/// Unit normal of the triangle `v0, v1, v2`, or zero if it is degenerate.
+///
+/// Triangles are kept exactly as the file wound them, which for a well-formed mesh is
+/// counter-clockwise seen from outside. The normals derived from them point the opposite way,
+/// into the surface, and every part of the crate that relates normals to faces (vertex normals,
+/// crease splitting, field smoothing, the viewer's lighting) relies on that. Exporters that
+/// expect outward normals, like glTF, flip them on the way out instead of reordering triangles.
+pub(crate) fn face_normal(v0: Vec3, v1: Vec3, v2: Vec3) -> Vec3 {
+    (v2 - v0).cross(v1 - v0).normalize_or_zero()
+}
+
+/// Area of each of `tris`, as from [`ProcessMesh::face_areas`].
+pub(crate) fn triangle_areas(vertices: &[Vec3], tris: &[[usize; 3]]) -> Vec<f32> {
+    tris.iter()
+        .map(|&[a, b, c]| {
+            let v0 = vertices[a];
+            0.5 * (vertices[b] - v0).cross(vertices[c] - v0).length()
+        })
+        .collect()
+}
+
+/// Each position followed by its normal, the flat vertex layout shared by the renderer's buffers
+/// and the glTF export.
+pub(crate) fn interleaved_vertices<'a>(
+    vertices: &'a [Vec3],
+    normals: &'a [Vec3],
+) -> impl Iterator<Item = [[f32; 3]; 2]> + 'a {
+    vertices
+        .iter()
+        .zip(normals)
+        .map(|(v, n)| [v.to_array(), n.to_array()])
+}
+
+/// Triangles as 32-bit indices, the flat index layout shared by the renderer's buffers and the
+/// glTF export.
+pub(crate) fn flat_indices(tris: &[[usize; 3]]) -> impl Iterator<Item = [u32; 3]> + '_ {
+    tris.iter().map(|t| t.map(|v| v as u32))
+}
 
 #[derive(Clone, Default)]
 #[wasm_bindgen]
 pub struct InputMesh {
     pub(crate) vertices: Vec<Vec3>,
+    /// Vertex normals, pointing inward as from [`face_normal`].
     pub(crate) normals: Vec<Vec3>,
+    /// Triangles in the winding of the source file; see [`face_normal`].
     pub(crate) tris: Vec<[usize; 3]>,
+    pub(crate) texture_files: Vec<String>,
+    /// Material or segment id of each triangle, or empty if the file had none.
+    pub(crate) material_ids: Vec<u32>,
+    /// Angle in degrees between faces above which their shared edge is drawn hard, if any.
+    pub(crate) crease_angle: Option<f32>,
+    /// Warnings raised while loading and cleaning up the mesh, oldest first.
+    pub(crate) diagnostics: Vec<Diagnostic>,
+}
+
+/// Stream the contents of `file`, gathering at least `min_chunk_size` bytes per refill. Fails if
+/// the browser won't open a reader on the file.
+fn file_reader(file: &web_sys::File, min_chunk_size: usize) -> Result<AsyncStreamReader, JsValue> {
+    let js_reader = web_sys::ReadableStreamDefaultReader::new(&file.stream())?;
+    let mut reader = AsyncStreamReader::new(move || {
+        wasm_bindgen_futures::JsFuture::from(js_reader.read()).map(|r| {
+            r.ok()
+                .and_then(|v| js_sys::Reflect::get(&v, &"value".into()).ok())
+                .and_then(|v| v.dyn_into::<js_sys::Uint8Array>().ok())
+                .map(|a| a.to_vec())
+        })
+    });
+    reader.set_min_fill(min_chunk_size);
+    reader.set_len(file.size() as usize);
+    Ok(reader)
+}
+
+/// Check that `crease_angle`, if set, is an angle between faces in degrees.
+pub(crate) fn check_crease_angle(crease_angle: Option<f32>) -> Result<(), JsValue> {
+    if crease_angle.map_or(false, |a| !(0.0..=180.0).contains(&a)) {
+        return Err("Crease angle must be between 0 and 180 degrees".into());
+    }
+    Ok(())
 }
 
 #[wasm_bindgen]
 impl InputMesh {
+    /// Load a mesh from `file`, with the settings in `options` or the defaults if it is omitted.
+    #[allow(deprecated)]
     #[wasm_bindgen(constructor)]
-    pub async fn new(file: &web_sys::File) -> Result<InputMesh, JsValue> {
-        let js_reader = web_sys::ReadableStreamDefaultReader::new(&file.stream())
-            .expect("Could not open file reader");
-        let mut reader = AsyncStreamReader::new(move || {
-            wasm_bindgen_futures::JsFuture::from(js_reader.read()).map(|r| {
-                r.ok()
-                    .and_then(|v| js_sys::Reflect::get(&v, &"value".into()).ok())
-                    .and_then(|v| v.dyn_into::<js_sys::Uint8Array>().ok())
-                    .map(|a| a.to_vec())
+    pub async fn new(
+        file: &web_sys::File,
+        options: Option<LoadOptions>,
+    ) -> Result<InputMesh, JsValue> {
+        let options = options.unwrap_or_default();
+        let mut reader = file_reader(file, options.min_chunk_size)?;
+        let mesh = Self::load(&mut reader, Some(&file.name()), &options, &mut |_| ()).await;
+        Ok(mesh.map_err(|e| format!("{}", e))?)
+    }
+
+    /// Like the constructor, but calls `on_chunk` with a [`MeshChunk`] each time another batch
+    /// of faces has been parsed, so the mesh can be shown while it loads. Only PLY files load
+    /// progressively; other formats produce a single chunk once they are done.
+    pub async fn load_progressive(
+        file: &web_sys::File,
+        on_chunk: &js_sys::Function,
+        options: Option<LoadOptions>,
+    ) -> Result<InputMesh, JsValue> {
+        let options = options.unwrap_or_default();
+        let mut reader = file_reader(file, options.min_chunk_size)?;
+
+        let mut chunks = ChunkBuilder::default();
+        let mut on_batch = |progress: LoadProgress| {
+            if let Some(chunk) = chunks.next(&progress) {
+                if let Err(e) = on_chunk.call1(&JsValue::NULL, &chunk.into()) {
+                    log::warn!("Mesh chunk callback failed: {:?}", e);
+                }
+            }
+        };
+        let mesh = Self::load(&mut reader, Some(&file.name()), &options, &mut on_batch).await;
+        Ok(mesh.map_err(|e| format!("{}", e))?)
+    }
+
+    /// Load a mesh from bytes already in memory, such as a fetched or generated buffer, without
+    /// wrapping them in a `File`. Reading from memory never has to wait, so this parses in one
+    /// call. `file_name` is only consulted if the format can't be told from the contents.
+    pub fn from_bytes(
+        data: Vec<u8>,
+        file_name: Option<String>,
+        options: Option<LoadOptions>,
+    ) -> Result<InputMesh, JsValue> {
+        let mut reader = AsyncStreamReader::from_bytes(data);
+        let options = options.unwrap_or_default();
+        let mesh = futures::executor::block_on(Self::load(
+            &mut reader,
+            file_name.as_deref(),
+            &options,
+            &mut |_| (),
+        ));
+        Ok(mesh.map_err(|e| format!("{}", e))?)
+    }
+
+    /// Vertex indices of every triangle, three per triangle, in the same winding that is drawn
+    /// and exported.
+    #[wasm_bindgen(getter)]
+    pub fn indices_flat(&self) -> Vec<u32> {
+        self.tris.iter().flatten().map(|i| *i as u32).collect()
+    }
+
+    /// The mesh as Wavefront OBJ text, as from [`obj::write_obj`].
+    pub fn to_obj(&self) -> String {
+        obj::write_obj(self)
+    }
+
+    /// Material or segment id of each triangle, from a `material_index` (or `material_id`)
+    /// property of the PLY face element. Empty if the file had none.
+    #[wasm_bindgen(getter)]
+    pub fn material_ids(&self) -> Vec<u32> {
+        self.material_ids.clone()
+    }
+
+    /// Draw edges between faces meeting at more than `crease_angle` degrees hard, with the
+    /// normals on either side kept apart, or smooth everything if `None`. Only affects drawing;
+    /// the vertex normals used for processing stay averaged over every face.
+    pub fn set_crease_angle(&mut self, crease_angle: Option<f32>) -> Result<(), JsValue> {
+        check_crease_angle(crease_angle)?;
+        self.crease_angle = crease_angle;
+        Ok(())
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn crease_angle(&self) -> Option<f32> {
+        self.crease_angle
+    }
+
+    /// Texture images referenced by `comment TextureFile` lines in the PLY header.
+    #[wasm_bindgen(getter)]
+    pub fn texture_files(&self) -> Vec<String> {
+        self.texture_files.clone()
+    }
+
+    /// Warnings raised while loading and cleaning up the mesh, oldest first.
+    #[wasm_bindgen(getter)]
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.diagnostics.clone()
+    }
+
+    /// Extract the connected component with the most triangles, dropping everything else.
+    pub fn largest_component(&self) -> InputMesh {
+        self.connected_components()
+            .into_iter()
+            .next()
+            .unwrap_or_default()
+    }
+
+    /// Coarsen the mesh the way smoothing does, returning every level of the hierarchy from the
+    /// coarsest to this mesh itself, with faces rebuilt so each level can be drawn.
+    pub fn hierarchy_levels(&self) -> Vec<InputMesh> {
+        hierarchy::build_with_faces(ProcessMesh::from(self.clone()))
+            .into_iter()
+            .map(|level| InputMesh {
+                vertices: level.mesh.vertices,
+                normals: level.mesh.normals,
+                tris: level.mesh.tris,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    /// Merge vertices with bitwise identical positions, as left behind by formats that store
+    /// each face's corners separately, and recompute normals. Returns how many were merged away.
+    pub fn weld_vertices(&mut self) -> usize {
+        let mut indices = std::collections::HashMap::with_capacity(self.vertices.len());
+        let mut remap = Vec::with_capacity(self.vertices.len());
+        let mut vertices = Vec::new();
+        for v in &self.vertices {
+            remap.push(
+                *indices
+                    .entry(v.to_array().map(f32::to_bits))
+                    .or_insert_with(|| {
+                        vertices.push(*v);
+                        vertices.len() - 1
+                    }),
+            );
+        }
+
+        let merged = self.vertices.len() - vertices.len();
+        if merged > 0 {
+            self.vertices = vertices;
+            for t in &mut self.tris {
+                *t = t.map(|v| remap[v]);
+            }
+            self.compute_normals();
+        }
+        merged
+    }
+
+    /// The mesh's [`centroid`](InputMesh::centroid) as `[x, y, z]`.
+    #[wasm_bindgen(js_name = centroid)]
+    pub fn centroid_xyz(&self) -> Vec<f32> {
+        self.centroid().to_array().to_vec()
+    }
+
+    /// Translate the mesh so its centroid is at the origin, leaving its scale alone so that
+    /// measurements stay in the original units. Meshes far from the origin lose precision in
+    /// later `f32` computations, so this is worth doing early.
+    pub fn recenter(&mut self) {
+        let centroid = self.centroid();
+        for v in &mut self.vertices {
+            *v -= centroid;
+        }
+    }
+
+    /// Relax the mesh for `iterations` rounds, moving each vertex along its normal by `strength`
+    /// times the mean offset of its neighbors projected onto that normal, and recompute normals.
+    /// `strength` is clamped to `[0, 1]`.
+    ///
+    /// Only the component along the normal is kept, so bumps flatten while vertices keep their
+    /// place across the surface, rather than drifting inward from boundaries and features as
+    /// with plain Laplacian smoothing. Curvature still reads as a bump, so curved parts contract
+    /// slightly with every round; a few iterations are usually enough to remove scanner noise.
+    pub fn normal_smooth(&mut self, iterations: usize, strength: f32) {
+        let strength = if strength.is_nan() {
+            0.0
+        } else {
+            strength.clamp(0.0, 1.0)
+        };
+
+        let mut neighbors = vec![Vec::new(); self.vertices.len()];
+        for tri in &self.tris {
+            for k in 0..3 {
+                let (a, b) = (tri[k], tri[(k + 1) % 3]);
+                neighbors[a].push(b);
+                neighbors[b].push(a);
+            }
+        }
+        for n in &mut neighbors {
+            n.sort_unstable();
+            n.dedup();
+        }
+
+        for _ in 0..iterations {
+            self.compute_normals();
+            self.vertices = self
+                .vertices
+                .iter()
+                .zip(&self.normals)
+                .zip(&neighbors)
+                .map(|((v, n), neighbors)| {
+                    if neighbors.is_empty() {
+                        return *v;
+                    }
+                    let offset = neighbors
+                        .iter()
+                        .map(|j| self.vertices[*j] - *v)
+                        .sum::<Vec3>()
+                        / neighbors.len() as f32;
+                    *v + strength * offset.dot(*n) * *n
+                })
+                .collect();
+        }
+        self.compute_normals();
+    }
+
+    /// Close holes bounded by at most `max_boundary_edges` edges, leaving larger openings alone,
+    /// and recompute normals. Returns how many holes were filled.
+    ///
+    /// Holes are the loops of edges used by exactly one face. Each is filled with a fan of
+    /// triangles from one of its corners, wound to match the surrounding faces and given the
+    /// material of the face along its first edge. That is fine for the small, roughly convex
+    /// holes left by scanning, but can fold over on long or twisted loops. Loops passing
+    /// through a vertex with several boundary edges leaving it are skipped.
+    pub fn fill_holes(&mut self, max_boundary_edges: usize) -> usize {
+        use std::collections::HashMap;
+
+        let mut edge_faces = HashMap::with_capacity(3 * self.tris.len() / 2);
+        for (f, tri) in self.tris.iter().enumerate() {
+            for k in 0..3 {
+                let (a, b) = (tri[k], tri[(k + 1) % 3]);
+                edge_faces
+                    .entry((a.min(b), a.max(b)))
+                    .or_insert_with(Vec::new)
+                    .push((a, b, f));
+            }
+        }
+
+        // Boundary edges keep the direction of their face; each hole is a loop of them
+        let mut boundary = edge_faces
+            .into_values()
+            .filter_map(|faces| (faces.len() == 1).then(|| faces[0]))
+            .collect::<Vec<_>>();
+        boundary.sort_unstable();
+        let mut outgoing = HashMap::<usize, Vec<(usize, usize)>>::new();
+        for &(a, b, f) in &boundary {
+            outgoing.entry(a).or_default().push((b, f));
+        }
+
+        let mut visited = std::collections::HashSet::new();
+        let mut filled = 0;
+        for &(start, _, face) in &boundary {
+            if visited.contains(&start) {
+                continue;
+            }
+
+            let mut hole = vec![start];
+            let closed = loop {
+                let v = *hole.last().unwrap();
+                if !visited.insert(v) {
+                    break false;
+                }
+                match outgoing[&v].as_slice() {
+                    [(next, _)] if *next == start => break true,
+                    [(next, _)] if outgoing.contains_key(next) => hole.push(*next),
+                    _ => break false,
+                }
+            };
+            if !closed || hole.len() < 3 || hole.len() > max_boundary_edges {
+                continue;
+            }
+
+            let material = self.material_ids.get(face).copied();
+            for i in 1..hole.len() - 1 {
+                self.tris.push([hole[0], hole[i + 1], hole[i]]);
+                if let Some(material) = material {
+                    self.material_ids.push(material);
+                }
+            }
+            filled += 1;
+        }
+
+        if filled > 0 {
+            Diagnostic::info(format!("Filled {} holes", filled)).report(&mut self.diagnostics);
+            self.compute_normals();
+        }
+        filled
+    }
+
+    /// Remove triangles that use the same three vertices as an earlier one, in any order, and
+    /// recompute normals. Returns how many were removed.
+    pub fn dedup_faces(&mut self) -> usize {
+        let mut seen = std::collections::HashSet::with_capacity(self.tris.len());
+        let keep = self
+            .tris
+            .iter()
+            .map(|t| {
+                let mut key = *t;
+                key.sort_unstable();
+                seen.insert(key)
             })
-        });
+            .collect::<Vec<_>>();
+        let before = self.tris.len();
+        self.retain_tris(&keep);
 
-        Ok(load_ply(&mut reader).await.map_err(|e| format!("{}", e))?)
+        let removed = before - self.tris.len();
+        if removed > 0 {
+            Diagnostic::warning(format!("Removed {} duplicate faces", removed))
+                .report(&mut self.diagnostics);
+            self.compute_normals();
+        }
+        removed
+    }
+}
+
+impl InputMesh {
+    /// Load a mesh of any format from `reader` and prepare it as `options` say, reporting
+    /// progress to `on_batch` as [`load_mesh_progressive`] does.
+    async fn load(
+        reader: &mut AsyncStreamReader,
+        file_name: Option<&str>,
+        options: &LoadOptions,
+        on_batch: &mut dyn FnMut(LoadProgress),
+    ) -> anyhow::Result<InputMesh> {
+        let mut mesh = load_mesh_progressive(reader, file_name, options, on_batch).await?;
+        mesh.sanitize(options.non_finite)?;
+        // Already checked when it was set on the options
+        mesh.crease_angle = options.crease_angle;
+        Ok(mesh)
+    }
+
+    /// Build a mesh from raw triangle soup, computing vertex normals.
+    pub fn from_triangles(vertices: Vec<Vec3>, tris: Vec<[usize; 3]>) -> InputMesh {
+        let mut mesh = InputMesh {
+            vertices,
+            tris,
+            ..Default::default()
+        };
+        mesh.compute_normals();
+        mesh
+    }
+
+    /// Recompute vertex normals by averaging the normals of incident faces. Vertices without
+    /// any non-degenerate faces get a zero normal.
+    pub fn compute_normals(&mut self) {
+        self.normals.clear();
+        self.normals.resize(self.vertices.len(), Vec3::ZERO);
+
+        // Accumulate in sorted order so the sums don't depend on the order of the triangles
+        let mut sorted = self.tris.iter().collect::<Vec<_>>();
+        sorted.sort_unstable();
+        for &&[a, b, c] in &sorted {
+            let n = face_normal(self.vertices[a], self.vertices[b], self.vertices[c]);
+
+            self.normals[a] += n;
+            self.normals[b] += n;
+            self.normals[c] += n;
+        }
+
+        for n in &mut self.normals {
+            *n = n.normalize_or_zero();
+        }
+    }
+
+    /// Normal at each corner of each triangle, averaged only over the faces around the corner's
+    /// vertex that connect to it without crossing a crease, or `None` if the mesh has no crease
+    /// angle set. Faces connect across an edge they share if their normals are within the crease
+    /// angle of each other.
+    pub fn corner_normals(&self) -> Option<Vec<[Vec3; 3]>> {
+        let max_cos = self.crease_angle?.to_radians().cos();
+        let face_normals = self
+            .tris
+            .iter()
+            .map(|&[a, b, c]| face_normal(self.vertices[a], self.vertices[b], self.vertices[c]))
+            .collect::<Vec<_>>();
+
+        // Union the corners of a vertex across each smooth edge, forming smoothing groups
+        let mut parent = (0..3 * self.tris.len()).collect::<Vec<_>>();
+        fn find(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+        let mut edge_corners = std::collections::HashMap::with_capacity(3 * self.tris.len() / 2);
+        for (f, tri) in self.tris.iter().enumerate() {
+            for k in 0..3 {
+                let (a, b) = (tri[k], tri[(k + 1) % 3]);
+                edge_corners
+                    .entry((a.min(b), a.max(b)))
+                    .or_insert_with(Vec::new)
+                    .push((f, [(a, 3 * f + k), (b, 3 * f + (k + 1) % 3)]));
+            }
+        }
+        for corners in edge_corners.values() {
+            let [(f, ends_f), (g, ends_g)] = corners.as_slice() else {
+                continue;
+            };
+            if face_normals[*f].dot(face_normals[*g]) < max_cos {
+                continue;
+            }
+            for (v, corner) in ends_f {
+                let (_, other) = ends_g.iter().find(|(w, _)| w == v).unwrap();
+                let (x, y) = (find(&mut parent, *corner), find(&mut parent, *other));
+                parent[x] = y;
+            }
+        }
+
+        let mut group_normals = vec![Vec3::ZERO; parent.len()];
+        for corner in 0..parent.len() {
+            let root = find(&mut parent, corner);
+            group_normals[root] += face_normals[corner / 3];
+        }
+        Some(
+            (0..self.tris.len())
+                .map(|f| {
+                    [0, 1, 2]
+                        .map(|k| group_normals[find(&mut parent, 3 * f + k)].normalize_or_zero())
+                })
+                .collect(),
+        )
+    }
+
+    /// Axis-aligned bounding box as `(min, max)`, or `None` for a mesh without vertices.
+    pub fn bounds(&self) -> Option<(Vec3, Vec3)> {
+        let first = *self.vertices.first()?;
+        Some(
+            self.vertices
+                .iter()
+                .fold((first, first), |(min, max), v| (min.min(*v), max.max(*v))),
+        )
+    }
+
+    /// Center of mass of the surface: the area-weighted mean of the triangle centroids. Falls back
+    /// to the mean of the vertices if the mesh has no area, and is zero for an empty mesh.
+    /// Accumulated in `f64` so large coordinate offsets don't swamp the sum.
+    pub fn centroid(&self) -> Vec3 {
+        let (mut sum, mut area) = (DVec3::ZERO, 0.0);
+        for (&[a, b, c], tri_area) in self
+            .tris
+            .iter()
+            .zip(triangle_areas(&self.vertices, &self.tris))
+        {
+            let [v0, v1, v2] = [a, b, c].map(|i| self.vertices[i].as_dvec3());
+            let tri_area = f64::from(tri_area);
+            sum += tri_area * (v0 + v1 + v2) / 3.0;
+            area += tri_area;
+        }
+        if area > 0.0 {
+            return (sum / area).as_vec3();
+        }
+
+        if self.vertices.is_empty() {
+            return Vec3::ZERO;
+        }
+        let sum = self
+            .vertices
+            .iter()
+            .fold(DVec3::ZERO, |sum, v| sum + v.as_dvec3());
+        (sum / self.vertices.len() as f64).as_vec3()
+    }
+
+    /// Check every vertex for NaN or infinite coordinates, handling them according to `policy`.
+    /// Returns how many vertices were dropped.
+    pub fn sanitize(&mut self, policy: NonFinitePolicy) -> anyhow::Result<usize> {
+        let keep = self
+            .vertices
+            .iter()
+            .map(|v| v.is_finite())
+            .collect::<Vec<_>>();
+        let dropped = keep.iter().filter(|k| !**k).count();
+        if dropped == 0 {
+            return Ok(0);
+        }
+
+        match policy {
+            NonFinitePolicy::Reject => {
+                let i = keep.iter().position(|k| !k).unwrap();
+                anyhow::bail!("Vertex {} has a non-finite coordinate", i)
+            }
+            NonFinitePolicy::Drop => {
+                Diagnostic::warning(format!(
+                    "Dropped {} vertices with non-finite coordinates",
+                    dropped
+                ))
+                .report(&mut self.diagnostics);
+                self.retain_vertices(&keep);
+                self.compute_normals();
+                Ok(dropped)
+            }
+        }
+    }
+
+    /// Remove every vertex whose entry in `keep` is false, along with any triangle using it.
+    pub(crate) fn retain_vertices(&mut self, keep: &[bool]) {
+        let mut remap = vec![usize::MAX; self.vertices.len()];
+        let mut next = 0;
+        for (i, k) in keep.iter().enumerate() {
+            if *k {
+                remap[i] = next;
+                self.vertices[next] = self.vertices[i];
+                self.normals[next] = self.normals[i];
+                next += 1;
+            }
+        }
+        self.vertices.truncate(next);
+        self.normals.truncate(next);
+
+        let keep_tris = self
+            .tris
+            .iter()
+            .map(|t| t.iter().all(|v| keep[*v]))
+            .collect::<Vec<_>>();
+        self.retain_tris(&keep_tris);
+        for t in &mut self.tris {
+            *t = t.map(|v| remap[v]);
+        }
+    }
+
+    /// Remove the triangles not marked in `keep`, along with their material ids.
+    fn retain_tris(&mut self, keep: &[bool]) {
+        let mut keep_iter = keep.iter();
+        self.tris.retain(|_| *keep_iter.next().unwrap());
+        if !self.material_ids.is_empty() {
+            let mut keep_iter = keep.iter();
+            self.material_ids.retain(|_| *keep_iter.next().unwrap());
+        }
+    }
+
+    /// Split the mesh into its edge-connected components, sorted by descending triangle count.
+    ///
+    /// Each component is returned as a standalone mesh with its vertex indices remapped.
+    /// Vertices not referenced by any triangle end up as their own single-vertex components.
+    pub fn connected_components(&self) -> Vec<InputMesh> {
+        let mut adjacency = vec![Vec::new(); self.vertices.len()];
+        for &[a, b, c] in &self.tris {
+            adjacency[a].extend([b, c]);
+            adjacency[b].extend([c, a]);
+            adjacency[c].extend([a, b]);
+        }
+
+        // Flood fill vertex labels
+        let mut component = vec![usize::MAX; self.vertices.len()];
+        let mut num_components = 0;
+        let mut stack = Vec::new();
+        for seed in 0..self.vertices.len() {
+            if component[seed] != usize::MAX {
+                continue;
+            }
+
+            component[seed] = num_components;
+            stack.push(seed);
+            while let Some(v) = stack.pop() {
+                for &n in &adjacency[v] {
+                    if component[n] == usize::MAX {
+                        component[n] = num_components;
+                        stack.push(n);
+                    }
+                }
+            }
+            num_components += 1;
+        }
+
+        // Scatter vertices and triangles into their components
+        let mut meshes = vec![
+            InputMesh {
+                texture_files: self.texture_files.clone(),
+                crease_angle: self.crease_angle,
+                ..Default::default()
+            };
+            num_components
+        ];
+        let mut remap = vec![0; self.vertices.len()];
+        for (i, c) in component.iter().enumerate() {
+            let mesh = &mut meshes[*c];
+            remap[i] = mesh.vertices.len();
+            mesh.vertices.push(self.vertices[i]);
+            mesh.normals.push(self.normals[i]);
+        }
+        for (i, &[a, b, c]) in self.tris.iter().enumerate() {
+            let mesh = &mut meshes[component[a]];
+            mesh.tris.push([remap[a], remap[b], remap[c]]);
+            if let Some(id) = self.material_ids.get(i) {
+                mesh.material_ids.push(*id);
+            }
+        }
+
+        meshes.sort_by_key(|m| std::cmp::Reverse(m.tris.len()));
+        meshes
+    }
+}
+
+/// Faces parsed since the previous chunk of a progressively loaded mesh, along with the vertices
+/// that are new or whose normals they change. Normals are only averaged over the faces seen so
+/// far.
+#[wasm_bindgen]
+pub struct MeshChunk {
+    pub(crate) total_vertices: usize,
+    /// Declared face count, which the triangles may end up exceeding once polygons and
+    /// triangle strips are split up.
+    pub(crate) total_tris: usize,
+    /// Indices of `vertices` and `normals` in the whole mesh, in increasing order.
+    pub(crate) vertex_ids: Vec<usize>,
+    pub(crate) vertices: Vec<Vec3>,
+    pub(crate) normals: Vec<Vec3>,
+    pub(crate) first_tri: usize,
+    pub(crate) tris: Vec<[usize; 3]>,
+    pub(crate) bounds: Option<(Vec3, Vec3)>,
+}
+
+/// Turns successive [`LoadProgress`] reports into [`MeshChunk`]s holding only what changed.
+///
+/// Faces that use vertices not parsed yet, as when a file lists its faces first, are held back
+/// until those vertices arrive.
+#[derive(Default)]
+pub(crate) struct ChunkBuilder {
+    normals: Vec<Vec3>,
+    vertices_sent: usize,
+    tris_sent: usize,
+    /// Number of faces whose vertex indices have been folded into `max_index`.
+    tris_checked: usize,
+    /// Highest vertex index used by the faces not sent yet.
+    max_index: Option<usize>,
+}
+
+impl ChunkBuilder {
+    pub(crate) fn next(&mut self, progress: &LoadProgress) -> Option<MeshChunk> {
+        let mesh = progress.mesh;
+        if mesh.tris.len() < self.tris_checked {
+            // The parser started over
+            *self = Self::default();
+        }
+
+        let new_tris = &mesh.tris[self.tris_sent..];
+        let checked = mesh.tris[self.tris_checked..]
+            .iter()
+            .flatten()
+            .copied()
+            .max();
+        self.max_index = self.max_index.max(checked);
+        self.tris_checked = mesh.tris.len();
+        if new_tris.is_empty() || self.max_index.map_or(false, |v| v >= mesh.vertices.len()) {
+            return None;
+        }
+
+        self.normals.resize(mesh.vertices.len(), Vec3::ZERO);
+        let mut vertex_ids = Vec::new();
+        for &[a, b, c] in new_tris {
+            let n = face_normal(mesh.vertices[a], mesh.vertices[b], mesh.vertices[c]);
+            for v in [a, b, c] {
+                self.normals[v] += n;
+                if v < self.vertices_sent {
+                    vertex_ids.push(v);
+                }
+            }
+        }
+        vertex_ids.sort_unstable();
+        vertex_ids.dedup();
+        vertex_ids.extend(self.vertices_sent..mesh.vertices.len());
+
+        let chunk = MeshChunk {
+            total_vertices: progress.num_vertices.max(mesh.vertices.len()),
+            total_tris: progress.num_faces.max(mesh.tris.len()),
+            vertices: vertex_ids.iter().map(|v| mesh.vertices[*v]).collect(),
+            normals: vertex_ids
+                .iter()
+                .map(|v| self.normals[*v].normalize_or_zero())
+                .collect(),
+            vertex_ids,
+            first_tri: self.tris_sent,
+            tris: new_tris.to_vec(),
+            bounds: if self.tris_sent == 0 {
+                mesh.bounds()
+            } else {
+                None
+            },
+        };
+        self.vertices_sent = mesh.vertices.len();
+        self.tris_sent = mesh.tris.len();
+        self.max_index = None;
+        Some(chunk)
     }
 }
 
@@ -35,62 +793,818 @@ pub struct ProcessMesh {
     pub vertices: Vec<Vec3>,
     pub normals: Vec<Vec3>,
     pub tris: Vec<[usize; 3]>,
+    /// Neighbors of each vertex, sorted by neighbor then face.
+    ///
+    /// On a mesh built from triangles there is one entry per outgoing half-edge: each face
+    /// around vertex `i` contributes `(j, face)`, where `j` follows `i` in that face's winding.
+    /// A neighbor only reached by an incoming half-edge, across a boundary, is therefore absent,
+    /// and a neighbor repeats only where faces disagree on winding or share an edge
+    /// non-manifoldly. Coarse hierarchy levels instead list every neighbor exactly once, in
+    /// both directions, with a face of `usize::MAX`.
     pub adjacency_face: Vec<Vec<(usize, usize)>>,
     pub dual_area: Vec<f32>,
+    /// Faces around each vertex and across each edge, for [`ProcessMesh::vertex_faces`] and
+    /// [`ProcessMesh::face_neighbors`]. Like the other adjacency, it is built from `tris` and
+    /// must be rebuilt with [`FaceAdjacency::new`] if they change.
+    pub face_adjacency: FaceAdjacency,
+}
+
+/// Faces around each vertex and across each edge of a [`ProcessMesh`].
+pub struct FaceAdjacency {
+    /// The faces around vertex `v` are `faces[offsets[v]..offsets[v + 1]]`.
+    offsets: Vec<usize>,
+    faces: Vec<usize>,
+    neighbors: Vec<[Option<usize>; 3]>,
+}
+
+impl FaceAdjacency {
+    pub fn new(num_vertices: usize, tris: &[[usize; 3]]) -> Self {
+        let corners = |tri: &[usize; 3]| {
+            let [a, b, c] = *tri;
+            [
+                Some(a),
+                (b != a).then_some(b),
+                (c != a && c != b).then_some(c),
+            ]
+        };
+
+        let mut offsets = vec![0; num_vertices + 1];
+        for v in tris.iter().flat_map(corners).flatten() {
+            offsets[v + 1] += 1;
+        }
+        for v in 0..num_vertices {
+            offsets[v + 1] += offsets[v];
+        }
+        let mut faces = vec![0; offsets[num_vertices]];
+        let mut next = offsets.clone();
+        for (f, tri) in tris.iter().enumerate() {
+            for v in corners(tri).into_iter().flatten() {
+                faces[next[v]] = f;
+                next[v] += 1;
+            }
+        }
+
+        // Group the edges of every face by their endpoints, ignoring direction. An edge joins
+        // two faces only if exactly those two share it.
+        let mut edges = tris
+            .iter()
+            .enumerate()
+            .flat_map(|(f, tri)| {
+                (0..3).map(move |k| {
+                    let (a, b) = (tri[k], tri[(k + 1) % 3]);
+                    ((a.min(b), a.max(b)), f, k)
+                })
+            })
+            .collect::<Vec<_>>();
+        edges.sort_unstable();
+
+        let mut neighbors = vec![[None; 3]; tris.len()];
+        // Runs of the sorted edges share an edge between them
+        let mut start = 0;
+        while start < edges.len() {
+            let edge = edges[start].0;
+            let end = start + edges[start..].iter().take_while(|e| e.0 == edge).count();
+            if let [(_, f0, k0), (_, f1, k1)] = edges[start..end] {
+                if f0 != f1 {
+                    neighbors[f0][k0] = Some(f1);
+                    neighbors[f1][k1] = Some(f0);
+                }
+            }
+            start = end;
+        }
+
+        Self {
+            offsets,
+            faces,
+            neighbors,
+        }
+    }
 }
 
 impl From<InputMesh> for ProcessMesh {
     fn from(input: InputMesh) -> Self {
+        Self::build(input).0
+    }
+}
+
+impl ProcessMesh {
+    /// Faces that use vertex `v`, in ascending order. Each face is listed once, even if it is
+    /// degenerate and uses `v` more than once.
+    pub fn vertex_faces(&self, v: usize) -> &[usize] {
+        let adjacency = &self.face_adjacency;
+        &adjacency.faces[adjacency.offsets[v]..adjacency.offsets[v + 1]]
+    }
+
+    /// The face across each edge of face `f`, where edge `k` runs from corner `k` to corner
+    /// `k + 1`. An edge has a neighbor only if exactly one other face shares it, in either
+    /// direction; boundary edges and edges shared by three or more faces have `None`.
+    pub fn face_neighbors(&self, f: usize) -> [Option<usize>; 3] {
+        self.face_adjacency.neighbors[f]
+    }
+
+    /// Every vertex within `k` edges of `vertex`, in either direction, in order of distance
+    /// starting with `vertex` itself.
+    pub fn k_ring(&self, vertex: usize, k: usize) -> Vec<usize> {
+        let mut ring = Vec::new();
+        self.k_ring_into(vertex, k, &mut ring, &mut vec![false; self.vertices.len()]);
+        ring
+    }
+
+    /// [`ProcessMesh::k_ring`] into `ring`, replacing its contents, for gathering many rings
+    /// without allocating for each. `visited` must have an entry per vertex, all false, and is
+    /// left that way.
+    pub fn k_ring_into(
+        &self,
+        vertex: usize,
+        k: usize,
+        ring: &mut Vec<usize>,
+        visited: &mut [bool],
+    ) {
+        ring.clear();
+        ring.push(vertex);
+        visited[vertex] = true;
+
+        // Each pass adds the vertices one edge further out than the previous one
+        let mut start = 0;
+        for _ in 0..k {
+            let end = ring.len();
+            for n in start..end {
+                let v = ring[n];
+                let faces = self.vertex_faces(v).iter().flat_map(|f| self.tris[*f]);
+                for j in self.adjacency_face[v].iter().map(|(j, _)| *j).chain(faces) {
+                    if !visited[j] {
+                        visited[j] = true;
+                        ring.push(j);
+                    }
+                }
+            }
+            if ring.len() == end {
+                break;
+            }
+            start = end;
+        }
+
+        for v in ring.iter() {
+            visited[*v] = false;
+        }
+    }
+
+    /// Number of distinct neighbors of each vertex, counting edges in either direction so that
+    /// boundary vertices aren't undercounted.
+    pub fn valences(&self) -> Vec<usize> {
+        let mut neighbors = vec![Vec::new(); self.vertices.len()];
+        for (i, adjacency) in self.adjacency_face.iter().enumerate() {
+            for (j, _) in adjacency {
+                neighbors[i].push(*j);
+                neighbors[*j].push(i);
+            }
+        }
+        neighbors
+            .into_iter()
+            .map(|mut n| {
+                n.sort_unstable();
+                n.dedup();
+                n.len()
+            })
+            .collect()
+    }
+
+    /// Whether the triangles close up with no boundary or non-manifold edges and consistent
+    /// winding, that is, every edge is used once in each direction by two faces. A mesh without
+    /// faces is not watertight.
+    pub fn is_watertight(&self) -> bool {
+        let mut half_edges = std::collections::HashMap::with_capacity(3 * self.tris.len());
+        for tri in &self.tris {
+            for k in 0..3 {
+                *half_edges.entry((tri[k], tri[(k + 1) % 3])).or_insert(0) += 1;
+            }
+        }
+        !half_edges.is_empty()
+            && half_edges
+                .iter()
+                .all(|(&(a, b), n)| *n == 1 && half_edges.get(&(b, a)) == Some(&1))
+    }
+
+    /// Direction of the open boundary through each vertex, or `None` for vertices off it. The
+    /// boundary is made of the edges used by only one face; where a vertex has several, their
+    /// directions along the winding of their faces are averaged.
+    pub fn boundary_tangents(&self) -> Vec<Option<Vec3>> {
+        let edge = |tri: &[usize; 3], k: usize| (tri[k], tri[(k + 1) % 3]);
+        let mut edge_faces = std::collections::HashMap::with_capacity(3 * self.tris.len() / 2);
+        for tri in &self.tris {
+            for k in 0..3 {
+                let (a, b) = edge(tri, k);
+                *edge_faces.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+            }
+        }
+
+        let mut tangents = vec![Vec3::ZERO; self.vertices.len()];
+        for tri in &self.tris {
+            for k in 0..3 {
+                let (a, b) = edge(tri, k);
+                if a != b && edge_faces[&(a.min(b), a.max(b))] == 1 {
+                    let d = (self.vertices[b] - self.vertices[a]).normalize_or_zero();
+                    tangents[a] += d;
+                    tangents[b] += d;
+                }
+            }
+        }
+        tangents
+            .into_iter()
+            .map(|t| Some(t.normalize_or_zero()).filter(|t| *t != Vec3::ZERO))
+            .collect()
+    }
+
+    /// Area of each triangle, in the order of `tris`. Coarse hierarchy levels built without
+    /// faces have none.
+    pub fn face_areas(&self) -> Vec<f32> {
+        triangle_areas(&self.vertices, &self.tris)
+    }
+
+    /// Quality of each triangle, in the order of `tris`: twice its inradius over its circumradius,
+    /// which is 1 for an equilateral triangle and falls to 0 as it degenerates into a sliver or
+    /// a needle.
+    pub fn triangle_quality(&self) -> Vec<f32> {
+        self.tris
+            .iter()
+            .zip(self.face_areas())
+            .map(|(&[a, b, c], area)| {
+                let [ab, bc, ca] = [(a, b), (b, c), (c, a)]
+                    .map(|(i, j)| self.vertices[i].distance(self.vertices[j]));
+                let denominator = ab * bc * ca * (ab + bc + ca);
+                if denominator > 0.0 {
+                    (16.0 * area * area / denominator).min(1.0)
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+
+    /// Build the mesh along with the list of vertices whose face fan could not be walked, which
+    /// are given a placeholder dual area of 1.
+    pub(crate) fn build(input: InputMesh) -> (Self, Vec<usize>) {
         let mut adjacency_face = vec![Vec::new(); input.vertices.len()];
         for (i, [a, b, c]) in input.tris.iter().enumerate() {
             adjacency_face[*a].push((*b, i));
             adjacency_face[*b].push((*c, i));
             adjacency_face[*c].push((*a, i));
         }
+        for adjacency in &mut adjacency_face {
+            adjacency.sort_unstable();
+        }
 
-        let mut dual_area = vec![0f32; input.vertices.len()];
-        'outer: for i in 0..input.vertices.len() {
-            let src = i;
-            let (mut dest, face) = adjacency_face[src][0];
-            let mut tri = &input.tris[face];
-
-            let mut circumcenters = Vec::new();
-
-            // im tired
-            while {
-                dest = tri[(tri.iter().position(|v| *v == dest).unwrap() + 1) % 3];
-                dest
-            } != adjacency_face[src][0].0
-            {
-                let a = input.vertices[tri[0]] - input.vertices[tri[2]];
-                let b = input.vertices[tri[1]] - input.vertices[tri[2]];
-                let axb = a.cross(b);
-                circumcenters.push((a.dot(a) * b - b.dot(b) * a).cross(axb) / (2.0 * axb.dot(axb)));
-
-                if let Some((_, next_face)) =
-                    adjacency_face[src].iter().filter(|v| v.0 == dest).next()
-                {
-                    tri = &input.tris[*next_face];
-                } else {
+        // Vertices are independent, so this parallelizes trivially where threads are available
+        #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+        let areas = {
+            use rayon::prelude::*;
+            (0..input.vertices.len())
+                .into_par_iter()
+                .map(|i| fan_dual_area(&input, &adjacency_face, i))
+                .collect::<Vec<_>>()
+        };
+        #[cfg(not(all(feature = "parallel", not(target_arch = "wasm32"))))]
+        let areas = (0..input.vertices.len())
+            .map(|i| fan_dual_area(&input, &adjacency_face, i))
+            .collect::<Vec<_>>();
+
+        let mut non_manifold = Vec::new();
+        let dual_area = areas
+            .into_iter()
+            .enumerate()
+            .map(|(i, area)| {
+                area.unwrap_or_else(|| {
                     log::warn!("non manifold vertex {}", i);
-                    dual_area[i] = 1.0;
-                    continue 'outer;
+                    non_manifold.push(i);
+                    1.0
+                })
+            })
+            .collect();
+
+        (
+            Self {
+                face_adjacency: FaceAdjacency::new(input.vertices.len(), &input.tris),
+                vertices: input.vertices,
+                normals: input.normals,
+                tris: input.tris,
+                adjacency_face,
+                dual_area,
+            },
+            non_manifold,
+        )
+    }
+}
+
+/// Area of the polygon joining the circumcenters of the faces around `src`, or `None` if those
+/// faces don't form a closed fan, including when the walk around them goes in circles without
+/// getting back to where it started. Isolated vertices have no area.
+fn fan_dual_area(
+    input: &InputMesh,
+    adjacency_face: &[Vec<(usize, usize)>],
+    src: usize,
+) -> Option<f32> {
+    // Start from the lowest neighbor so the sum doesn't depend on the order of the triangles
+    let Some(&(start, face)) = adjacency_face[src].first() else {
+        return Some(0.0);
+    };
+    // Two half-edges to the same neighbor, as from a duplicated face or an edge shared by more
+    // than two faces, leave the walk no single way round. They sort next to each other.
+    if adjacency_face[src].windows(2).any(|w| w[0].0 == w[1].0) {
+        return None;
+    }
+    let origin = input.vertices[src];
+    let (mut dest, mut face) = (start, face);
+
+    let mut circumcenters = Vec::new();
+    loop {
+        // A closed fan gets back to the start within one step per face
+        if circumcenters.len() == adjacency_face[src].len() {
+            return None;
+        }
+
+        let tri = &input.tris[face];
+        let next = tri[(tri.iter().position(|v| *v == dest).unwrap() + 1) % 3];
+        circumcenters.push(circumcenter_offset(
+            input.vertices[dest] - origin,
+            input.vertices[next] - origin,
+        ));
+        if next == start {
+            break;
+        }
+
+        dest = next;
+        face = adjacency_face[src].iter().find(|v| v.0 == dest)?.1;
+    }
+
+    let mut v = Vec3::ZERO;
+    for i in 0..circumcenters.len() {
+        v += circumcenters[i].cross(circumcenters[(i + 1) % circumcenters.len()]);
+    }
+    Some(0.5 * v.length())
+}
+
+/// Squared sine of the angle between two edges below which their triangle counts as
+/// degenerate, and its circumcenter as too far off to be trusted.
+const DEGENERATE_SIN_SQUARED: f32 = 1e-8;
+
+/// Circumcenter of the triangle with edges `a` and `b` from a shared corner, relative to that
+/// corner. Collinear triangles have no circumcenter, so the centroid stands in for theirs
+/// rather than dividing by zero.
+fn circumcenter_offset(a: Vec3, b: Vec3) -> Vec3 {
+    let axb = a.cross(b);
+    let axb2 = axb.dot(axb);
+    if axb2 <= DEGENERATE_SIN_SQUARED * a.dot(a) * b.dot(b) {
+        return (a + b) / 3.0;
+    }
+    (a.dot(a) * b - b.dot(b) * a).cross(axb) / (2.0 * axb2)
+}
+
+/// Per-vertex quality measures of a mesh, as computed for smoothing, for inspection before
+/// committing to the full pipeline.
+#[wasm_bindgen]
+pub struct MeshDiagnostics {
+    pub(crate) mesh: ProcessMesh,
+    non_manifold: Vec<usize>,
+}
+
+#[wasm_bindgen]
+impl MeshDiagnostics {
+    #[wasm_bindgen(constructor)]
+    pub fn new(mesh: &InputMesh) -> MeshDiagnostics {
+        let (mesh, non_manifold) = ProcessMesh::build(mesh.clone());
+        MeshDiagnostics { mesh, non_manifold }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn dual_area(&self) -> Vec<f32> {
+        self.mesh.dual_area.clone()
+    }
+
+    /// Whether every edge is shared by exactly two consistently wound faces, as from
+    /// [`ProcessMesh::is_watertight`].
+    #[wasm_bindgen(getter)]
+    pub fn is_watertight(&self) -> bool {
+        self.mesh.is_watertight()
+    }
+
+    /// Area of each triangle, for visualizing mesh quality.
+    #[wasm_bindgen(getter)]
+    pub fn face_areas(&self) -> Vec<f32> {
+        self.mesh.face_areas()
+    }
+
+    /// Quality of each triangle, from 1 for equilateral down to 0 for slivers, as from
+    /// [`ProcessMesh::triangle_quality`].
+    #[wasm_bindgen(getter)]
+    pub fn triangle_quality(&self) -> Vec<f32> {
+        self.mesh.triangle_quality()
+    }
+
+    /// Number of distinct vertices sharing an edge with each vertex.
+    #[wasm_bindgen(getter)]
+    pub fn neighbor_counts(&self) -> Vec<u32> {
+        self.mesh.valences().into_iter().map(|v| v as u32).collect()
+    }
+
+    /// How many vertices have each valence, indexed by valence.
+    #[wasm_bindgen(getter)]
+    pub fn valence_histogram(&self) -> Vec<u32> {
+        let valences = self.mesh.valences();
+        let mut histogram = vec![0; valences.iter().max().map_or(0, |v| v + 1)];
+        for v in valences {
+            histogram[v] += 1;
+        }
+        histogram
+    }
+
+    /// Vertices whose surrounding faces don't form a single closed fan, such as boundary or
+    /// pinched vertices. Their dual area is a placeholder.
+    #[wasm_bindgen(getter)]
+    pub fn non_manifold_vertices(&self) -> Vec<usize> {
+        self.non_manifold.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::vec3;
+
+    use super::*;
+    use crate::shapes;
+
+    /// `meshes` combined into one, with their vertices in order.
+    fn concat(meshes: &[InputMesh]) -> InputMesh {
+        let mut vertices = Vec::new();
+        let mut tris = Vec::new();
+        for mesh in meshes {
+            let offset = vertices.len();
+            vertices.extend(&mesh.vertices);
+            tris.extend(mesh.tris.iter().map(|t| t.map(|v| v + offset)));
+        }
+        InputMesh::from_triangles(vertices, tris)
+    }
+
+    /// `mesh` moved by `offset`.
+    fn translated(mut mesh: InputMesh, offset: Vec3) -> InputMesh {
+        for v in &mut mesh.vertices {
+            *v += offset;
+        }
+        mesh
+    }
+
+    #[test]
+    fn connected_components_of_two_cubes() {
+        let sliver = InputMesh::from_triangles(
+            vec![
+                vec3(5.0, 0.0, 0.0),
+                vec3(6.0, 0.0, 0.0),
+                vec3(5.0, 1.0, 0.0),
+            ],
+            vec![[0, 1, 2]],
+        );
+        let mesh = concat(&[
+            sliver,
+            shapes::cube(),
+            translated(shapes::cube(), vec3(3.0, 0.0, 0.0)),
+        ]);
+
+        let components = mesh.connected_components();
+        assert_eq!(
+            components
+                .iter()
+                .map(|c| (c.vertices.len(), c.tris.len()))
+                .collect::<Vec<_>>(),
+            [(8, 12), (8, 12), (3, 1)]
+        );
+        for c in &components {
+            assert!(c.tris.iter().flatten().all(|v| *v < c.vertices.len()));
+        }
+        assert!(ProcessMesh::from(components[0].clone()).is_watertight());
+
+        let largest = mesh.largest_component();
+        assert_eq!(largest.tris.len(), 12);
+        assert_eq!(largest.vertices[..], shapes::cube().vertices[..]);
+    }
+    #[test]
+    fn sanitize_non_finite_vertices() {
+        let mut mesh = shapes::plane(4);
+        mesh.vertices[6].y = f32::NAN;
+        mesh.vertices[12].x = f32::INFINITY;
+        let mut rejected = mesh.clone();
+
+        let err = rejected.sanitize(NonFinitePolicy::Reject).unwrap_err();
+        assert_eq!(err.to_string(), "Vertex 6 has a non-finite coordinate");
+
+        let count = mesh.vertices.len();
+        assert_eq!(mesh.sanitize(NonFinitePolicy::Drop).unwrap(), 2);
+        assert_eq!(mesh.vertices.len(), count - 2);
+        assert!(mesh.vertices.iter().all(|v| v.is_finite()));
+        assert!(mesh.tris.iter().flatten().all(|v| *v < mesh.vertices.len()));
+
+        // The hierarchy ranks vertices by area, which NaN used to make panic
+        let levels = crate::hierarchy::build(ProcessMesh::from(mesh));
+        assert!(!levels.is_empty());
+    }
+
+    #[test]
+    fn dedup_faces_removes_duplicated_triangles() {
+        let plane = shapes::plane(2);
+        let mut mesh = plane.clone();
+        mesh.tris.push(mesh.tris[3]);
+        // The same corners listed from another one, and wound the other way
+        let [a, b, c] = mesh.tris[5];
+        mesh.tris.push([b, c, a]);
+        mesh.tris.push([a, c, b]);
+        mesh.compute_normals();
+
+        assert_eq!(mesh.dedup_faces(), 3);
+        assert_eq!(mesh.tris, plane.tris);
+        assert_eq!(mesh.normals, plane.normals);
+        assert_eq!(mesh.dedup_faces(), 0);
+    }
+
+    #[test]
+    fn chunks_only_resend_vertices_whose_normals_changed() {
+        let plane = shapes::plane(2);
+        let mut chunks = ChunkBuilder::default();
+        let mut partial = plane.clone();
+        partial.tris.truncate(2);
+        let progress = |mesh| LoadProgress {
+            mesh,
+            num_vertices: 9,
+            num_faces: 8,
+        };
+
+        let first = chunks.next(&progress(&partial)).unwrap();
+        assert_eq!(first.vertex_ids, (0..9).collect::<Vec<_>>());
+
+        // Vertex 0 is only used by the first two triangles
+        let second = chunks.next(&progress(&plane)).unwrap();
+        assert_eq!(second.vertex_ids, (1..9).collect::<Vec<_>>());
+        assert_eq!(second.first_tri, 2);
+        assert_eq!(second.normals, plane.normals[1..]);
+    }
+
+    #[test]
+    fn grid_dual_areas_are_the_squares_around_each_vertex() {
+        // Both triangles of a grid cell share its center as their circumcenter, so each interior
+        // vertex owns the h by h square around it. Boundary vertices have no closed fan.
+        let n = 4;
+        let h = 1.0 / n as f32;
+        let (mesh, non_manifold) = ProcessMesh::build(shapes::plane(n));
+
+        let on_boundary = |v: usize| {
+            let (i, j) = (v % (n + 1), v / (n + 1));
+            i == 0 || j == 0 || i == n || j == n
+        };
+        let boundary = (0..mesh.vertices.len())
+            .filter(|v| on_boundary(*v))
+            .collect::<Vec<_>>();
+        assert_eq!(non_manifold, boundary);
+
+        for (v, area) in mesh.dual_area.iter().enumerate() {
+            if !on_boundary(v) {
+                assert!((area - h * h).abs() < 1e-6, "vertex {}: {}", v, area);
+            }
+        }
+    }
+
+    #[test]
+    fn adjacency_lists_outgoing_half_edges_in_order() {
+        let quad = vec![Vec3::ZERO, Vec3::X, vec3(1.0, 1.0, 0.0), Vec3::Y];
+        let mesh = ProcessMesh::from(InputMesh::from_triangles(
+            quad.clone(),
+            vec![[0, 2, 3], [0, 1, 2]],
+        ));
+        assert_eq!(
+            mesh.adjacency_face,
+            [
+                vec![(1, 1), (2, 0)],
+                vec![(2, 1)],
+                vec![(0, 1), (3, 0)],
+                vec![(0, 0)],
+            ]
+        );
+
+        // Faces wound against each other both leave vertex 0 toward vertex 2
+        let mesh = ProcessMesh::from(InputMesh::from_triangles(quad, vec![[0, 2, 3], [0, 2, 1]]));
+        assert_eq!(mesh.adjacency_face[0], [(2, 0), (2, 1)]);
+        assert_eq!(mesh.adjacency_face[2], [(1, 1), (3, 0)]);
+    }
+
+    #[test]
+    fn watertight_needs_closed_consistently_wound_faces() {
+        assert!(ProcessMesh::from(shapes::icosphere(1)).is_watertight());
+        assert!(!ProcessMesh::from(shapes::plane(4)).is_watertight());
+
+        let mut flipped = shapes::icosphere(1);
+        flipped.tris[0].swap(1, 2);
+        assert!(!ProcessMesh::from(flipped).is_watertight());
+    }
+
+    #[test]
+    fn face_adjacency_of_a_quad_and_odd_edges() {
+        let quad = vec![Vec3::ZERO, Vec3::X, vec3(1.0, 1.0, 0.0), Vec3::Y];
+        let mesh = ProcessMesh::from(InputMesh::from_triangles(
+            quad.clone(),
+            vec![[0, 1, 2], [0, 2, 3]],
+        ));
+        assert_eq!(mesh.vertex_faces(0), [0, 1]);
+        assert_eq!(mesh.vertex_faces(1), [0]);
+        assert_eq!(mesh.vertex_faces(3), [1]);
+        assert_eq!(mesh.face_neighbors(0), [None, None, Some(1)]);
+        assert_eq!(mesh.face_neighbors(1), [Some(0), None, None]);
+
+        // Faces wound against each other are still neighbors, but not three sharing an edge,
+        // and a degenerate face is listed once at its repeated vertex
+        let mesh = ProcessMesh::from(InputMesh::from_triangles(
+            quad.clone(),
+            vec![[0, 1, 2], [0, 1, 3], [1, 0, 3], [2, 2, 3]],
+        ));
+        assert_eq!(mesh.face_neighbors(0), [None, None, None]);
+        assert_eq!(mesh.face_neighbors(1)[1], Some(2));
+        assert_eq!(mesh.vertex_faces(2), [0, 3]);
+
+        // Rebuilding after an edit picks up the new triangles
+        let mut mesh = ProcessMesh::from(InputMesh::from_triangles(quad, vec![[0, 1, 2]]));
+        mesh.tris.push([0, 2, 3]);
+        mesh.face_adjacency = FaceAdjacency::new(mesh.vertices.len(), &mesh.tris);
+        assert_eq!(mesh.face_neighbors(0), [None, None, Some(1)]);
+    }
+
+    #[test]
+    fn normal_smoothing_flattens_a_noisy_plane_without_shrinking_it() {
+        use rand::Rng;
+
+        let plane = shapes::plane(16);
+        let mut rng = crate::rng::seeded_rng(0);
+        let mut mesh = plane.clone();
+        for v in &mut mesh.vertices {
+            v.z = rng.gen_range(-0.01..0.01);
+        }
+        mesh.compute_normals();
+        let mean_height = |mesh: &InputMesh| {
+            mesh.vertices.iter().map(|v| v.z.abs()).sum::<f32>() / mesh.vertices.len() as f32
+        };
+        let noise = mean_height(&mesh);
+
+        mesh.normal_smooth(10, 0.5);
+        let height = mean_height(&mesh);
+        assert!(height < 0.5 * noise, "{} of {}", height, noise);
+        // Vertices stay near where they were across the plane, and the boundary doesn't pull in
+        let edge = 1.0 / 16.0;
+        for (v, original) in mesh.vertices.iter().zip(&plane.vertices) {
+            let drift = v.truncate().distance(original.truncate());
+            assert!(drift < 0.05 * edge, "{} moved to {}", original, v);
+        }
+        let bounds = mesh.bounds().unwrap();
+        assert!(bounds.0.truncate().max_element() <= -0.5 + 1e-4);
+        assert!(bounds.1.truncate().min_element() >= 0.5 - 1e-4);
+    }
+
+    #[test]
+    fn chunks_of_mixed_faces_cover_more_triangles_than_declared() {
+        // Enough face rows for more than one batch, alternating a quad with two triangles
+        let n: i32 = 256;
+        let mut data = format!(
+            "ply\nformat binary_little_endian 1.0\nelement vertex {}\nproperty float x\n\
+             property float y\nproperty float z\nelement face {}\n\
+             property list uchar int vertex_indices\nend_header\n",
+            (n + 1) * (n + 1),
+            n * n / 2 * 3
+        )
+        .into_bytes();
+        for j in 0..=n {
+            for i in 0..=n {
+                for x in [i as f32, j as f32, 0.0] {
+                    data.extend(x.to_le_bytes());
                 }
             }
+        }
+        for cell in 0..n * n {
+            let a = cell / n * (n + 1) + cell % n;
+            let (b, c, d) = (a + 1, a + n + 2, a + n + 1);
+            let rows = if cell % 2 == 0 {
+                vec![vec![a, b, c, d]]
+            } else {
+                vec![vec![a, b, c], vec![a, c, d]]
+            };
+            for row in rows {
+                data.push(row.len() as u8);
+                data.extend(row.iter().flat_map(|v| v.to_le_bytes()));
+            }
+        }
+
+        let mut chunks = Vec::new();
+        let mut builder = ChunkBuilder::default();
+        let mut reader = AsyncStreamReader::from_bytes(data);
+        let mesh = futures::executor::block_on(crate::ply::load_ply_progressive(
+            &mut reader,
+            &LoadOptions::default(),
+            &mut |progress| chunks.extend(builder.next(&progress)),
+        ))
+        .unwrap();
+
+        assert!(chunks.len() > 1);
+        assert!(chunks[0].total_tris < mesh.tris.len());
+        let mut tris = Vec::<[usize; 3]>::new();
+        for chunk in &chunks {
+            assert_eq!(chunk.first_tri, tris.len());
+            tris.extend(&chunk.tris);
+        }
+        assert_eq!(tris, mesh.tris);
+    }
+
+    #[test]
+    fn triangle_quality_of_equilateral_and_sliver_triangles() {
+        let mesh = ProcessMesh::from(InputMesh::from_triangles(
+            vec![
+                Vec3::ZERO,
+                Vec3::X,
+                vec3(0.5, 3.0f32.sqrt() / 2.0, 0.0),
+                vec3(0.5, 1e-4, 0.0),
+            ],
+            vec![[0, 1, 2], [0, 3, 1]],
+        ));
+        let quality = mesh.triangle_quality();
+        assert!((quality[0] - 1.0).abs() < 1e-5, "{}", quality[0]);
+        assert!(quality[1] < 1e-3, "{}", quality[1]);
+    }
+
+    #[test]
+    fn fan_walk_through_a_reversed_duplicate_face_gives_up() {
+        // Faces 1 and 2 are the same triangle both ways round, so the walk around vertex 0 goes
+        // back and forth between vertices 2 and 3 without ever returning to 1
+        let mesh = InputMesh::from_triangles(
+            vec![Vec3::ZERO, Vec3::X, Vec3::Y, vec3(-1.0, 0.5, 0.0)],
+            vec![[0, 1, 2], [0, 2, 3], [0, 3, 2]],
+        );
+        let (mesh, non_manifold) = ProcessMesh::build(mesh);
+        assert!(non_manifold.contains(&0), "{:?}", non_manifold);
+        assert_eq!(mesh.dual_area[0], 1.0);
+    }
 
-            let mut v = Vec3::ZERO;
-            for i in 0..circumcenters.len() {
-                v += circumcenters[i].cross(circumcenters[(i + 1) % circumcenters.len()]);
+    #[test]
+    fn duplicated_face_makes_its_corners_non_manifold() {
+        let mesh = InputMesh::from_triangles(
+            vec![Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::Z],
+            vec![[0, 2, 1], [0, 1, 3], [1, 2, 3], [2, 0, 3], [0, 2, 1]],
+        );
+        let (_, non_manifold) = ProcessMesh::build(mesh);
+        assert_eq!(non_manifold, [0, 1, 2]);
+    }
+
+    #[test]
+    fn near_collinear_triangles_keep_dual_areas_finite() {
+        // A tetrahedron with one corner pushed toward the middle of the opposite edge of its
+        // base, flattening the base to a sliver
+        for height in [1e-2, 1e-4, 1e-6, 0.0] {
+            let mesh = InputMesh::from_triangles(
+                vec![
+                    Vec3::ZERO,
+                    Vec3::X,
+                    vec3(0.5, height, 0.0),
+                    vec3(0.5, 0.5, 1.0),
+                ],
+                vec![[0, 1, 2], [0, 3, 1], [1, 3, 2], [2, 3, 0]],
+            );
+            let (mesh, non_manifold) = ProcessMesh::build(mesh);
+            assert!(non_manifold.is_empty());
+            for a in &mesh.dual_area {
+                assert!(a.is_finite(), "{:?} at height {}", mesh.dual_area, height);
             }
-            dual_area[i] = 0.5 * v.length();
         }
+        assert_eq!(
+            circumcenter_offset(Vec3::X, 2.0 * Vec3::X),
+            vec3(1.0, 0.0, 0.0)
+        );
+    }
 
-        Self {
-            vertices: input.vertices,
-            normals: input.normals,
-            tris: input.tris,
-            adjacency_face,
-            dual_area,
+    #[test]
+    fn k_rings_of_a_regular_grid() {
+        let mesh = ProcessMesh::from(shapes::plane(8));
+        let (corner, center) = (0, 4 * 9 + 4);
+        let mut ring = Vec::new();
+        let mut visited = vec![false; mesh.vertices.len()];
+        for k in 0..4 {
+            // Every interior vertex has six neighbors, so ring k adds 6k vertices
+            mesh.k_ring_into(center, k, &mut ring, &mut visited);
+            assert_eq!(ring.len(), 1 + 3 * k * (k + 1), "{}-ring", k);
+            assert!(visited.iter().all(|v| !v));
+            // The corner is on one diagonal, so its rings fill squares of the grid
+            assert_eq!(
+                mesh.k_ring(corner, k).len(),
+                (k + 1) * (k + 1),
+                "{}-ring",
+                k
+            );
         }
+
+        // Nearer rings come first
+        let ring = mesh.k_ring(center, 3);
+        assert_eq!(ring[..7], mesh.k_ring(center, 1));
+        assert_eq!(ring[0], center);
+        // Past the edge of the grid there is nothing more to add
+        assert_eq!(mesh.k_ring(corner, 20).len(), mesh.vertices.len());
     }
 }
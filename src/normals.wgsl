@@ -0,0 +1,59 @@
+struct Uniforms {
+    view_transform: mat4x4<f32>,
+    model_transform: mat4x4<f32>,
+    output: vec4<f32>,
+    // x: length of a normal line in scene units
+    normals: vec4<f32>,
+    // x: width of overlay lines in pixels
+    // yz: size of the viewport in pixels
+    lines: vec4<f32>,
+}
+
+struct MeshUniforms {
+    transform: mat4x4<f32>,
+    // Inverse transpose of the transform, for carrying normals under non-uniform scaling
+    normal_transform: mat4x4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tip: f32,
+    // Signed distance from the middle of the line, in pixels
+    @location(1) across: f32,
+};
+
+@group(0)
+@binding(0)
+var<uniform> uniforms: Uniforms;
+
+@group(1)
+@binding(0)
+var<uniform> mesh: MeshUniforms;
+
+// Each line runs from a vertex outward, against the inward normal the mesh stores, so a flipped
+// normal shows up as a line into the surface
+@vertex
+fn vs_main(
+    @builtin(vertex_index) index: u32,
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+) -> VertexOutput {
+    let start = uniforms.model_transform * mesh.transform * vec4<f32>(position, 1.0);
+    let direction = (uniforms.model_transform * mesh.normal_transform * vec4<f32>(normal, 0.0)).xyz;
+    let unit = select(vec3<f32>(0.0), normalize(direction), dot(direction, direction) > 0.0);
+    let end = vec4<f32>(start.xyz - uniforms.normals.x * unit, 1.0);
+    let corner = expand_line(uniforms.view_transform * start, uniforms.view_transform * end, index);
+
+    var result: VertexOutput;
+    result.position = corner.position;
+    result.tip = corner.along;
+    result.across = corner.across;
+    return result;
+}
+
+// Fade from dark at the vertex to bright at the tip, showing which way each line points
+@fragment
+fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    let color = mix(vec3<f32>(0.0, 0.15, 0.3), vec3<f32>(0.3, 0.8, 1.0), vertex.tip);
+    return vec4<f32>(color, line_coverage(vertex.across));
+}
@@ -0,0 +1,115 @@
+use std::fmt::Write;
+
+use anyhow::{bail, Context, Result};
+use glam::vec3;
+
+use crate::{mesh::InputMesh, stream::AsyncStreamReader};
+
+fn parse_index(token: &str, num_vertices: usize) -> Result<usize> {
+    let index: i64 = token
+        .split('/')
+        .next()
+        .context("Missing face vertex index")?
+        .parse()?;
+
+    // OBJ indices are 1-based, and negative indices count back from the latest vertex
+    let resolved = if index < 0 {
+        num_vertices as i64 + index
+    } else {
+        index - 1
+    };
+    if resolved < 0 || resolved as usize >= num_vertices {
+        bail!("Face vertex index {} out of range", index);
+    }
+    Ok(resolved as usize)
+}
+
+pub async fn load_obj(reader: &mut AsyncStreamReader) -> Result<InputMesh> {
+    let mut vertices = Vec::new();
+    let mut tris = Vec::new();
+
+    while let Ok(line) = reader.read_line_utf8().await {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("v") => {
+                let mut coord = || -> Result<f32> {
+                    Ok(tokens
+                        .next()
+                        .context("Missing vertex coordinate")?
+                        .parse()?)
+                };
+                vertices.push(vec3(coord()?, coord()?, coord()?));
+            }
+            Some("f") => {
+                let face = tokens
+                    .map(|t| parse_index(t, vertices.len()))
+                    .collect::<Result<Vec<_>>>()?;
+                if face.len() < 3 {
+                    bail!("Face with fewer than 3 vertices");
+                }
+
+                // Fan triangulate polygons
+                for i in 1..face.len() - 1 {
+                    tris.push([face[0], face[i], face[i + 1]]);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Ok(InputMesh::from_triangles(vertices, tris))
+}
+
+/// Write `mesh` as Wavefront OBJ text with a normal per vertex, which [`load_obj`] reads back to
+/// the same mesh. Normals are written pointing outward, as other tools expect, rather than inward
+/// as [`InputMesh`] keeps them.
+pub fn write_obj(mesh: &InputMesh) -> String {
+    let mut obj = String::with_capacity(40 * (2 * mesh.vertices.len() + mesh.tris.len()));
+    for v in &mesh.vertices {
+        writeln!(obj, "v {} {} {}", v.x, v.y, v.z).unwrap();
+    }
+    for n in &mesh.normals {
+        writeln!(obj, "vn {} {} {}", -n.x, -n.y, -n.z).unwrap();
+    }
+    for t in &mesh.tris {
+        let [a, b, c] = t.map(|v| v + 1);
+        writeln!(obj, "f {a}//{a} {b}//{b} {c}//{c}").unwrap();
+    }
+    obj
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes;
+
+    #[test]
+    fn written_mesh_loads_back_the_same() {
+        let mesh = shapes::torus(6, 9, 1.0, 0.3);
+        let obj = write_obj(&mesh);
+        let mut reader = AsyncStreamReader::from_bytes(obj.clone().into_bytes());
+        let loaded = futures::executor::block_on(load_obj(&mut reader)).unwrap();
+
+        assert_eq!(loaded.vertices, mesh.vertices);
+        assert_eq!(loaded.tris, mesh.tris);
+        for (a, b) in loaded.normals.iter().zip(&mesh.normals) {
+            assert!(a.abs_diff_eq(*b, 1e-6), "{} != {}", a, b);
+        }
+
+        // Written normals point away from the torus' core circle
+        let normals = obj.lines().filter_map(|l| l.strip_prefix("vn "));
+        for (line, v) in normals.zip(&mesh.vertices) {
+            let n = line
+                .split(' ')
+                .map(|c| c.parse().unwrap())
+                .collect::<Vec<f32>>();
+            let core = (*v * vec3(1.0, 1.0, 0.0)).normalize();
+            assert!(
+                vec3(n[0], n[1], n[2]).dot(*v - core) > 0.0,
+                "{} at {}",
+                line,
+                v
+            );
+        }
+    }
+}
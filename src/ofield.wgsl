@@ -1,22 +1,70 @@
+struct Uniforms {
+    view_transform: mat4x4<f32>,
+    model_transform: mat4x4<f32>,
+}
+
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+    // Position within the bar, from -1 to 1 along and across it
+    @location(1) bar: vec2<f32>,
 };
 
 @group(0)
 @binding(0)
-var<uniform> transform: mat4x4<f32>;
+var<uniform> uniforms: Uniforms;
+
+// Each cross is two bars of six vertices, one along o and one along its tangent
+const O_COLOR: vec3<f32> = vec3<f32>(1.0, 0.3, 0.2);
+const T_COLOR: vec3<f32> = vec3<f32>(0.2, 0.9, 0.3);
 
 @vertex
 fn vs_main(
-    @location(0) position: vec3<f32>,
+    @builtin(vertex_index) index: u32,
+    @location(0) position_arm: vec4<f32>,
+    @location(1) o: vec3<f32>,
+    @location(2) normal: vec3<f32>,
 ) -> VertexOutput {
+    var corners = array<u32, 6>(0u, 1u, 2u, 2u, 3u, 1u);
+    let corner = corners[index % 6u];
+
+    let t = cross(normal, o);
+    var a = o;
+    var b = t;
+    var color = O_COLOR;
+    if index >= 6u {
+        a = t;
+        b = o;
+        color = T_COLOR;
+    }
+
+    let arm = position_arm.w;
+    let width = arm / 30.0;
+    let along = select(-1.0, 1.0, corner % 2u == 0u);
+    let across = select(-1.0, 1.0, corner >= 2u);
+    let position = position_arm.xyz + along * arm * a + across * width * b;
+
     var result: VertexOutput;
-    result.position = transform * vec4<f32>(position, 1.0);
+    result.position = uniforms.view_transform * uniforms.model_transform * vec4<f32>(position, 1.0);
+    result.color = color;
+    result.bar = vec2<f32>(along, across);
 
     return result;
 }
 
+// Opacity fading out over the last pixel before each edge of the bar, so that thin or distant
+// bars don't alias
+fn bar_coverage(bar: vec2<f32>) -> f32 {
+    let edge = (1.0 - abs(bar)) / max(fwidth(bar), vec2<f32>(1e-6));
+    return clamp(min(edge.x, edge.y), 0.0, 1.0);
+}
+
 @fragment
 fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(0.0, 1.0, 0.0, 1.0);
+    return vec4<f32>(vertex.color, bar_coverage(vertex.bar));
+}
+
+@fragment
+fn fs_hidden(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(vertex.color, 0.3 * bar_coverage(vertex.bar));
 }
@@ -1,81 +1,1593 @@
+use std::{collections::HashMap, mem};
+
 use glam::{vec3, Vec3};
-use rand::{rngs::SmallRng, seq::SliceRandom, Rng, SeedableRng};
+use rand::Rng;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    gltf,
+    hierarchy::{self, HierarchyLevel},
+    mesh::{InputMesh, ProcessMesh},
+    position,
+    remesh::{self, QuadMesh},
+    rng,
+};
 
-use crate::{hierarchy::HierarchyLevel, mesh::ProcessMesh};
+/// How close the alignments of two candidate pairs in [`extrinsic_compat`] must be to count as a
+/// tie.
+const COMPAT_TIE_EPSILON: f32 = 1e-5;
 
+/// The pair of representatives of the crosses `(o0, n0)` and `(o1, n1)` that are best aligned.
+///
+/// Ties, such as crosses exactly 45 degrees apart, go to the pair with the least rotation: `o1`
+/// is left as is if possible, and `o0` is turned by as few quarter turns as possible, turning
+/// positively about `n0` first. Which pair wins a tie therefore depends only on the two crosses,
+/// so symmetric regions of the field settle the same way every time.
 fn extrinsic_compat(o0: Vec3, n0: Vec3, o1: Vec3, n1: Vec3) -> (Vec3, Vec3) {
-    let p0 = n0.cross(o0);
-    let p1 = n1.cross(o1);
-
-    let a = [
-        (o0, o1),
-        (p0, o1),
-        (-o0, o1),
-        (-p0, o1),
-        (o0, p1),
-        (p0, p1),
-        (-o0, p1),
-        (-p0, p1),
+    let r0 = quarter_turns(o0, n0);
+    let r1 = quarter_turns(o1, n1);
+    let (k0, k1) = compat_turns(&r0, &r1);
+    (r0[k0], r1[k1])
+}
+
+/// `o` turned about `n` by zero to three quarter turns.
+fn quarter_turns(o: Vec3, n: Vec3) -> [Vec3; 4] {
+    let p = n.cross(o);
+    [o, p, -o, -p]
+}
+
+/// Quarter turns of the best aligned pair among the representatives `r0` and `r1` of two
+/// crosses, breaking ties as described for [`extrinsic_compat`].
+fn compat_turns(r0: &[Vec3; 4], r1: &[Vec3; 4]) -> (usize, usize) {
+    // Ordered by how far the pair is rotated from (o0, o1)
+    const CANDIDATES: [(usize, usize); 8] = [
+        (0, 0),
+        (1, 0),
+        (3, 0),
+        (2, 0),
+        (0, 1),
+        (1, 1),
+        (3, 1),
+        (2, 1),
+    ];
+
+    let best = CANDIDATES
+        .iter()
+        .map(|&(k0, k1)| r0[k0].dot(r1[k1]))
+        .fold(f32::NEG_INFINITY, f32::max);
+    CANDIDATES
+        .into_iter()
+        .find(|&(k0, k1)| r0[k0].dot(r1[k1]) >= best - COMPAT_TIE_EPSILON)
+        .unwrap_or((0, 0))
+}
+
+/// Run a single Jacobi sweep over every vertex of `mesh`: each vertex is updated from its
+/// neighbors' directions as they were before the sweep, folding them in by increasing index. The
+/// result is therefore independent of vertex visiting order and of the order of the adjacency
+/// lists, and so of the order of the input triangles.
+///
+/// If `active` is given, only vertices marked `true` are updated. The rest keep their current
+/// direction but still pull on their active neighbors. Fails if `o_field` or `active` doesn't
+/// have an entry for every vertex.
+pub fn extrinsic_smooth(
+    mesh: &ProcessMesh,
+    o_field: &mut [Vec3],
+    active: Option<&[bool]>,
+) -> anyhow::Result<()> {
+    check_sweep_inputs(mesh, o_field, active, &[])?;
+    smooth_sweeps(mesh, o_field, active, SweepWeights::default(), 1);
+    Ok(())
+}
+
+/// [`extrinsic_smooth`], scaling the pull of each neighbor by its `confidence`, such as a
+/// scanner's per-vertex confidence. Vertices with low confidence then follow their more certain
+/// neighbors, rather than dragging them along. Vertices with zero confidence pull on nothing.
+/// Fails if `confidence` doesn't have an entry for every vertex either.
+pub fn extrinsic_smooth_weighted(
+    mesh: &ProcessMesh,
+    o_field: &mut [Vec3],
+    active: Option<&[bool]>,
+    confidence: &[f32],
+) -> anyhow::Result<()> {
+    check_sweep_inputs(mesh, o_field, active, &[("Confidence", confidence.len())])?;
+    let weights = SweepWeights {
+        confidence: Some(confidence),
+        ..Default::default()
+    };
+    smooth_sweeps(mesh, o_field, active, weights, 1);
+    Ok(())
+}
+
+/// [`extrinsic_smooth`], also pulling each vertex with a tangent in `boundary_tangents` toward
+/// it as strongly as `weight` neighbors would. Vertices on an open boundary are missing half
+/// their neighbors, so without this the field along a border is skewed by the interior alone;
+/// aligning it to the border, as with
+/// [`ProcessMesh::boundary_tangents`](crate::mesh::ProcessMesh::boundary_tangents), gives
+/// crosses that run along and across it. Fails if `boundary_tangents` doesn't have an entry for
+/// every vertex either.
+pub fn extrinsic_smooth_aligned(
+    mesh: &ProcessMesh,
+    o_field: &mut [Vec3],
+    active: Option<&[bool]>,
+    boundary_tangents: &[Option<Vec3>],
+    weight: f32,
+) -> anyhow::Result<()> {
+    let tangents = ("Boundary tangents", boundary_tangents.len());
+    check_sweep_inputs(mesh, o_field, active, &[tangents])?;
+    let weights = SweepWeights {
+        boundary: Some((boundary_tangents, weight)),
+        ..Default::default()
+    };
+    smooth_sweeps(mesh, o_field, active, weights, 1);
+    Ok(())
+}
+
+/// Fail unless `o_field`, `active` if given, and each of the `other` per-vertex inputs, named
+/// along with their lengths, have an entry for every vertex of `mesh`.
+fn check_sweep_inputs(
+    mesh: &ProcessMesh,
+    o_field: &[Vec3],
+    active: Option<&[bool]>,
+    other: &[(&str, usize)],
+) -> anyhow::Result<()> {
+    let num_vertices = mesh.vertices.len();
+    let inputs = [
+        ("Field", Some(o_field.len())),
+        ("Region mask", active.map(<[bool]>::len)),
     ];
+    let other = other.iter().map(|&(name, len)| (name, Some(len)));
+    for (name, len) in inputs.into_iter().chain(other) {
+        if let Some(len) = len.filter(|len| *len != num_vertices) {
+            anyhow::bail!(
+                "{} has {} entries for a mesh of {} vertices",
+                name,
+                len,
+                num_vertices
+            );
+        }
+    }
+    Ok(())
+}
+
+/// What [`smooth_sweep`] weighs the pull on each vertex by, beyond counting every neighbor once.
+#[derive(Clone, Copy, Default)]
+struct SweepWeights<'a> {
+    /// Weight each neighbor by how well its normal agrees with that of the vertex being updated.
+    normal_weighting: bool,
+    /// Scale each neighbor by its confidence, as in [`extrinsic_smooth_weighted`].
+    confidence: Option<&'a [f32]>,
+    /// Boundary tangents and how strongly to pull toward them, as in
+    /// [`extrinsic_smooth_aligned`].
+    boundary: Option<(&'a [Option<Vec3>], f32)>,
+}
 
-    a.into_iter()
-        .max_by(|(a, b), (c, d)| a.dot(*b).partial_cmp(&c.dot(*d)).unwrap())
-        .unwrap()
+/// Run `iterations` sweeps of [`smooth_sweep`] on `o_field`, passing the field back and forth
+/// between it and a single scratch copy rather than copying it for every sweep.
+fn smooth_sweeps(
+    mesh: &ProcessMesh,
+    o_field: &mut [Vec3],
+    active: Option<&[bool]>,
+    weights: SweepWeights,
+    iterations: usize,
+) {
+    if iterations == 0 {
+        return;
+    }
+
+    let mut scratch = o_field.to_vec();
+    // Whether the latest field is in the scratch copy rather than in `o_field`
+    let mut in_scratch = true;
+    for _ in 0..iterations {
+        if in_scratch {
+            smooth_sweep(mesh, &scratch, o_field, active, weights);
+        } else {
+            smooth_sweep(mesh, o_field, &mut scratch, active, weights);
+        }
+        in_scratch = !in_scratch;
+    }
+    if in_scratch {
+        o_field.copy_from_slice(&scratch);
+    }
 }
 
-fn extrinsic_smooth<R: Rng>(mesh: &ProcessMesh, o_field: &mut [Vec3], rng: &mut R) {
-    let mut indices = (0..mesh.vertices.len()).collect::<Vec<_>>();
-    indices.shuffle(rng);
+/// [`extrinsic_smooth`] from the field `previous` into `o_field`, with each vertex pulled on as
+/// set out in `weights`. Vertices outside `active` are copied over unchanged.
+fn smooth_sweep(
+    mesh: &ProcessMesh,
+    previous: &[Vec3],
+    o_field: &mut [Vec3],
+    active: Option<&[bool]>,
+    weights: SweepWeights,
+) {
+    for (i, o) in o_field.iter_mut().enumerate() {
+        if active.map_or(false, |active| !active[i]) {
+            *o = previous[i];
+            continue;
+        }
 
-    for i in indices {
-        let mut o_i = o_field[i];
+        let mut o_i = previous[i];
         let n_i = mesh.normals[i];
 
-        for (weight, (j, _)) in mesh.adjacency_face[i].iter().enumerate() {
-            let o_j = o_field[*j];
+        // The adjacency is sorted by neighbor, which fixes the order they are folded in
+        let mut weight_sum = 0.0;
+        for (j, _) in &mesh.adjacency_face[i] {
+            let o_j = previous[*j];
             let n_j = mesh.normals[*j];
 
+            let mut weight = if weights.normal_weighting {
+                n_i.dot(n_j).max(0.0)
+            } else {
+                1.0
+            };
+            if let Some(confidence) = weights.confidence {
+                weight *= confidence[*j].max(0.0);
+            }
+            if weight <= 0.0 {
+                continue;
+            }
+
             let (compat_0, compat_1) = extrinsic_compat(o_i, n_i, o_j, n_j);
 
-            o_i = (weight as f32) * compat_0 + compat_1;
+            o_i = weight_sum * compat_0 + weight * compat_1;
             o_i -= n_i * o_i.dot(n_i);
             o_i = o_i.normalize();
+            weight_sum += weight;
         }
 
-        o_field[i] = o_i;
+        // The boundary is folded in last, as one more neighbor sharing the vertex's normal
+        if let Some((Some(t), weight)) = weights
+            .boundary
+            .map(|(tangents, weight)| (tangents[i], weight))
+        {
+            let t = (t - n_i * t.dot(n_i)).normalize_or_zero();
+            if t != Vec3::ZERO && weight > 0.0 {
+                let (compat_0, compat_1) = extrinsic_compat(o_i, n_i, t, n_i);
+
+                o_i = weight_sum * compat_0 + weight * compat_1;
+                o_i -= n_i * o_i.dot(n_i);
+                o_i = o_i.normalize();
+            }
+        }
+
+        *o = o_i;
     }
 }
 
-pub fn hierarchical_smoothing(hierarchy: &[HierarchyLevel], iterations: usize) -> Vec<Vec3> {
-    let mut rng = SmallRng::seed_from_u64(0); // todo do this better
+/// Re-smooth part of an existing field on a single level, such as a region painted with a brush,
+/// holding every vertex outside `active` fixed. Fails if `active` or `o_field` doesn't have an
+/// entry for every vertex.
+pub fn smooth_region(
+    mesh: &ProcessMesh,
+    o_field: &mut [Vec3],
+    active: &[bool],
+    iterations: usize,
+) -> anyhow::Result<()> {
+    check_sweep_inputs(mesh, o_field, Some(active), &[])?;
+
+    smooth_sweeps(
+        mesh,
+        o_field,
+        Some(active),
+        SweepWeights::default(),
+        iterations,
+    );
+    Ok(())
+}
 
-    let mut field = if hierarchy.len() > 1 {
-        let coarse_field = hierarchical_smoothing(&hierarchy[0..hierarchy.len() - 1], iterations);
-        let mut init = vec![Vec3::ZERO; hierarchy[hierarchy.len() - 1].mesh.vertices.len()];
-        for (i, v) in init.iter_mut().enumerate() {
-            *v = coarse_field[hierarchy[hierarchy.len() - 1].up_mapping[i]];
+/// Smooth a field from random tangent directions on `mesh` alone, without building a hierarchy.
+///
+/// Gives the same field as [`hierarchical_smoothing_with`] on a single-level hierarchy with the
+/// same `seed`. For small meshes this is quicker and free of coarsening artifacts, and it makes a
+/// baseline to compare the hierarchical result against; on large meshes it needs far more
+/// sweeps to settle.
+pub fn smooth_flat(mesh: &ProcessMesh, iterations: usize, seed: u64) -> Vec<Vec3> {
+    let mut rng = rng::seeded_rng(seed);
+    let mut o_field = initial_field(mesh, None, &mut rng);
+    smooth_sweeps(
+        mesh,
+        &mut o_field,
+        None,
+        SweepWeights::default(),
+        iterations,
+    );
+    o_field
+}
+
+/// Orthonormal tangent vectors for the plane perpendicular to the unit vector `n`.
+fn tangent_frame(n: Vec3) -> (Vec3, Vec3) {
+    let sign = if n.z < 0.0 { -1.0 } else { 1.0 };
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+    let x = vec3(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x);
+    let y = vec3(b, sign + n.y * n.y * a, -n.y);
+    (x, y)
+}
+
+/// Estimate the direction of strongest principal curvature at every vertex of `mesh`.
+///
+/// The shape operator is fit by least squares to the change in normal along each edge of the
+/// one-ring. Where it has no dominant direction, in flat or umbilic regions, an arbitrary tangent
+/// is returned instead. Useful as a reference to compare a smoothed field against.
+pub fn principal_directions(mesh: &ProcessMesh) -> Vec<Vec3> {
+    let mut directions = Vec::with_capacity(mesh.vertices.len());
+    for (i, adjacency) in mesh.adjacency_face.iter().enumerate() {
+        let n_i = mesh.normals[i];
+        let (t1, t2) = tangent_frame(n_i);
+
+        // Accumulate the normal equations of dn = S e in the tangent frame
+        let mut eet = [0.0f32; 3];
+        let mut det = [[0.0f32; 2]; 2];
+        for (j, _) in adjacency {
+            let e = mesh.vertices[*j] - mesh.vertices[i];
+            let dn = mesh.normals[*j] - n_i;
+            let (eu, ev) = (e.dot(t1), e.dot(t2));
+            let (du, dv) = (dn.dot(t1), dn.dot(t2));
+
+            eet[0] += eu * eu;
+            eet[1] += eu * ev;
+            eet[2] += ev * ev;
+            det[0][0] += du * eu;
+            det[0][1] += du * ev;
+            det[1][0] += dv * eu;
+            det[1][1] += dv * ev;
+        }
+
+        let denom = eet[0] * eet[2] - eet[1] * eet[1];
+        if !denom.is_normal() || denom.abs() <= f32::EPSILON * (eet[0] + eet[2]).powi(2) {
+            directions.push(t1);
+            continue;
         }
-        init
-    } else {
-        let mut init = vec![Vec3::ZERO; hierarchy[0].mesh.vertices.len()];
-        for (i, v) in init.iter_mut().enumerate() {
-            let n = hierarchy[0].mesh.normals[i];
 
-            let sign = if n.z < 0.0 { -1.0 } else { 1.0 };
-            let a = -1.0 / (sign + n.z);
-            let b = n.x * n.y * a;
-            let x = vec3(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x);
-            let y = vec3(b, sign + n.y * n.y * a, -n.y);
-            let theta = rng.gen::<f32>() * std::f32::consts::TAU;
+        // S = (D E^T)(E E^T)^-1, then symmetrized
+        let inv = [eet[2] / denom, -eet[1] / denom, eet[0] / denom];
+        let s00 = det[0][0] * inv[0] + det[0][1] * inv[1];
+        let s01 = det[0][0] * inv[1] + det[0][1] * inv[2];
+        let s10 = det[1][0] * inv[0] + det[1][1] * inv[1];
+        let s11 = det[1][0] * inv[1] + det[1][1] * inv[2];
+        let (a, b, c) = (s00, 0.5 * (s01 + s10), s11);
 
-            *v = x * theta.cos() + y * theta.sin();
+        let mean = 0.5 * (a + c);
+        let disc = (0.25 * (a - c) * (a - c) + b * b).sqrt();
+        if !disc.is_finite() || disc <= 1e-3 * (a.abs() + b.abs() + c.abs()) {
+            directions.push(t1);
+            continue;
         }
-        init
+
+        let k = if mean >= 0.0 {
+            mean + disc
+        } else {
+            mean - disc
+        };
+        let (u, v) = if (k - c).abs() >= (k - a).abs() {
+            (k - c, b)
+        } else {
+            (b, k - a)
+        };
+        let d = (u * t1 + v * t2).normalize_or_zero();
+        directions.push(if d == Vec3::ZERO { t1 } else { d });
+    }
+    directions
+}
+
+/// Settings for [`hierarchical_smoothing_with`].
+#[derive(Clone, Debug)]
+pub struct SmoothingOptions {
+    /// Sweeps run on every level not covered by `schedule`.
+    pub iterations: usize,
+    /// Sweeps run on each level, indexed from the coarsest. Coarse levels are cheap and settle
+    /// the global structure, so they usually deserve more sweeps than the fine ones.
+    pub schedule: Option<Vec<usize>>,
+    /// Direction the coarsest level starts from, projected into each tangent plane.
+    pub seed_direction: Option<Vec3>,
+    /// Seed for the random initial directions. The same seed gives the same field on every
+    /// platform; see [`rng`](crate::rng).
+    pub seed: u64,
+    /// Weight each neighbor by how closely its normal agrees, so that creases pull less.
+    pub normal_weighting: bool,
+    /// How strongly vertices on an open boundary are pulled to align with it, counted in
+    /// neighbors, as in [`extrinsic_smooth_aligned`]. Zero leaves the boundary free.
+    pub boundary_alignment: f32,
+    /// Confidence of each vertex of the finest level, as in [`extrinsic_smooth_weighted`], or
+    /// `None` to trust every vertex equally. A coarse vertex takes the mean confidence of the
+    /// vertices merged into it. Ignored, with a warning, unless there is one value per vertex;
+    /// [`run_pipeline`](crate::pipeline::run_pipeline) reports that among its diagnostics.
+    pub confidence: Option<Vec<f32>>,
+}
+
+impl Default for SmoothingOptions {
+    fn default() -> Self {
+        Self {
+            iterations: 10,
+            schedule: None,
+            seed_direction: None,
+            seed: 0,
+            normal_weighting: false,
+            boundary_alignment: 0.0,
+            confidence: None,
+        }
+    }
+}
+
+impl SmoothingOptions {
+    /// Sweeps to run on `level`, counted from the coarsest.
+    pub fn iterations_for(&self, level: usize) -> usize {
+        self.schedule
+            .as_ref()
+            .and_then(|schedule| schedule.get(level).copied())
+            .unwrap_or(self.iterations)
+    }
+}
+
+/// Smooth an orientation field coarse-to-fine over `hierarchy`, running `iterations` sweeps per
+/// level.
+///
+/// The coarsest level starts from `seed_direction` projected into each tangent plane, or from
+/// random tangent directions if it is `None` (or parallel to a vertex normal).
+///
+/// Each `(vertex, direction)` in `constraints` pins a vertex of the finest level to `direction`
+/// projected into its tangent plane. Pinned vertices are never overwritten; the rest of the field
+/// relaxes around them. Constraints are carried down to the coarser levels through `up_mapping`.
+/// Fails if a constraint is on a vertex the finest level doesn't have.
+pub fn hierarchical_smoothing(
+    hierarchy: &[HierarchyLevel],
+    iterations: usize,
+    seed_direction: Option<Vec3>,
+    constraints: &[(usize, Vec3)],
+) -> anyhow::Result<Vec<Vec3>> {
+    let options = SmoothingOptions {
+        iterations,
+        seed_direction,
+        ..Default::default()
     };
+    check_constraints(hierarchy, constraints)?;
+    Ok(smooth_all_levels(
+        hierarchy,
+        &options,
+        constraints,
+        None,
+        &mut 0,
+    ))
+}
 
-    for _ in 0..iterations {
-        extrinsic_smooth(&hierarchy[hierarchy.len() - 1].mesh, &mut field, &mut rng);
+/// [`hierarchical_smoothing`] with every setting spelled out in `options`. Also returns the
+/// total number of sweeps run over all levels.
+pub fn hierarchical_smoothing_with(
+    hierarchy: &[HierarchyLevel],
+    options: &SmoothingOptions,
+    constraints: &[(usize, Vec3)],
+) -> anyhow::Result<(Vec<Vec3>, usize)> {
+    check_constraints(hierarchy, constraints)?;
+    let mut sweeps = 0;
+    let field = smooth_all_levels(hierarchy, options, constraints, None, &mut sweeps);
+    Ok((field, sweeps))
+}
+
+/// Like [`hierarchical_smoothing`], but also returns the [`field_energy`] after every sweep,
+/// ordered from the coarsest level to the finest.
+pub fn hierarchical_smoothing_with_energy(
+    hierarchy: &[HierarchyLevel],
+    iterations: usize,
+    seed_direction: Option<Vec3>,
+    constraints: &[(usize, Vec3)],
+) -> anyhow::Result<(Vec<Vec3>, Vec<f32>)> {
+    let options = SmoothingOptions {
+        iterations,
+        seed_direction,
+        ..Default::default()
+    };
+    check_constraints(hierarchy, constraints)?;
+    let mut energy = Vec::with_capacity(hierarchy.len() * iterations);
+    let field = smooth_all_levels(hierarchy, &options, constraints, Some(&mut energy), &mut 0);
+    Ok((field, energy))
+}
+
+/// Fail if any of `constraints` is on a vertex that the finest level of `hierarchy` doesn't
+/// have, which [`level_constraints`] can't carry down.
+fn check_constraints(
+    hierarchy: &[HierarchyLevel],
+    constraints: &[(usize, Vec3)],
+) -> anyhow::Result<()> {
+    let num_vertices = hierarchy[hierarchy.len() - 1].mesh.vertices.len();
+    if let Some((i, _)) = constraints.iter().find(|(i, _)| *i >= num_vertices) {
+        anyhow::bail!(
+            "Constraint on vertex {} of a mesh with {} vertices",
+            i,
+            num_vertices
+        );
+    }
+    Ok(())
+}
+
+/// Total misalignment of `o_field` over the edges of `mesh`, as the sum of `1 - dot` between the
+/// best matching representatives of each pair of neighboring crosses.
+///
+/// Edges are visited from both endpoints where the adjacency is symmetric, so the sum is halved.
+pub fn field_energy(mesh: &ProcessMesh, o_field: &[Vec3]) -> f32 {
+    let mut energy = 0.0;
+    for (i, adjacency) in mesh.adjacency_face.iter().enumerate() {
+        for (j, _) in adjacency {
+            let (compat_0, compat_1) =
+                extrinsic_compat(o_field[i], mesh.normals[i], o_field[*j], mesh.normals[*j]);
+            energy += 1.0 - compat_0.dot(compat_1);
+        }
     }
+    0.5 * energy
+}
 
-    field
+/// Local misalignment of `o_field` at each vertex of `mesh`: the mean of `1 - dot` between the
+/// best matching representatives of its cross and each neighboring one, as in [`field_energy`].
+/// High values mark singularities and other poorly aligned regions. Vertices without neighbors
+/// get zero.
+pub fn residuals(mesh: &ProcessMesh, o_field: &[Vec3]) -> Vec<f32> {
+    let mut sum = vec![0.0; mesh.vertices.len()];
+    let mut count = vec![0; mesh.vertices.len()];
+    for (i, adjacency) in mesh.adjacency_face.iter().enumerate() {
+        for (j, _) in adjacency {
+            let (compat_0, compat_1) =
+                extrinsic_compat(o_field[i], mesh.normals[i], o_field[*j], mesh.normals[*j]);
+            let misalignment = 1.0 - compat_0.dot(compat_1);
+            for v in [i, *j] {
+                sum[v] += misalignment;
+                count[v] += 1;
+            }
+        }
+    }
+    sum.into_iter()
+        .zip(count)
+        .map(|(sum, count)| if count > 0 { sum / count as f32 } else { 0.0 })
+        .collect()
+}
+
+/// Faces of `mesh` around which `o_field` does not come back to itself, meaning they hold a
+/// singularity of the field.
+///
+/// Going around each face, the crosses at neighboring corners are matched as in
+/// [`extrinsic_compat`]. The quarter turns this takes add up to a multiple of four on a regular
+/// face.
+pub fn singularities(mesh: &ProcessMesh, o_field: &[Vec3]) -> Vec<usize> {
+    let turns = o_field
+        .iter()
+        .zip(&mesh.normals)
+        .map(|(o, n)| quarter_turns(*o, *n))
+        .collect::<Vec<_>>();
+    mesh.tris
+        .iter()
+        .enumerate()
+        .filter(|(_, [a, b, c])| {
+            let index = [(*a, *b), (*b, *c), (*c, *a)]
+                .into_iter()
+                .map(|(i, j)| {
+                    let (ki, kj) = compat_turns(&turns[i], &turns[j]);
+                    kj as isize - ki as isize
+                })
+                .sum::<isize>();
+            index.rem_euclid(4) != 0
+        })
+        .map(|(face, _)| face)
+        .collect()
+}
+
+/// Orthonormal tangent frame `[o, n × o, n]` at every vertex, with the field direction projected
+/// onto the tangent plane. Vertices without a normal get a zero bitangent.
+pub fn tangent_frames(normals: &[Vec3], o_field: &[Vec3]) -> Vec<[Vec3; 3]> {
+    normals
+        .iter()
+        .zip(o_field)
+        .map(|(n, o)| {
+            let t = (*o - *n * o.dot(*n)).normalize_or_zero();
+            [t, n.cross(t), *n]
+        })
+        .collect()
+}
+
+/// The field as CSV text with a header row and one `vertex_index,x,y,z,ox,oy,oz` row per
+/// vertex, for analysis in other tools.
+pub fn field_to_csv(vertices: &[Vec3], o_field: &[Vec3]) -> String {
+    use std::fmt::Write;
+
+    // Rows are written straight into one buffer sized up front, rather than formatted apart
+    let mut csv = String::with_capacity(64 * vertices.len() + 32);
+    csv.push_str("vertex_index,x,y,z,ox,oy,oz\n");
+    for (i, (v, o)) in vertices.iter().zip(o_field).enumerate() {
+        writeln!(csv, "{},{},{},{},{},{},{}", i, v.x, v.y, v.z, o.x, o.y, o.z).unwrap();
+    }
+    csv
+}
+
+/// Most steps a streamline takes in each direction from its seed.
+const MAX_STREAMLINE_STEPS: usize = 1000;
+
+/// Trace `count` streamlines of `o_field` over the faces of `mesh`, each seeded at the centroid
+/// of a random face and integrated both ways in steps of length `step`. Within a face the field
+/// is the average of the corner crosses' arms closest to the current heading, so streamlines
+/// follow one arm smoothly instead of jumping between them. A streamline ends where it leaves the
+/// mesh through a boundary edge or the field vanishes.
+pub fn trace_streamlines(
+    mesh: &ProcessMesh,
+    o_field: &[Vec3],
+    count: usize,
+    step: f32,
+) -> Vec<Vec<Vec3>> {
+    trace_streamlines_on_faces(mesh, o_field, count, step)
+        .into_iter()
+        .map(|line| line.into_iter().map(|(p, _)| p).collect())
+        .collect()
+}
+
+/// [`trace_streamlines`], also returning the face each point lies on.
+pub(crate) fn trace_streamlines_on_faces(
+    mesh: &ProcessMesh,
+    o_field: &[Vec3],
+    count: usize,
+    step: f32,
+) -> Vec<Vec<(Vec3, usize)>> {
+    if mesh.tris.is_empty() || !step.is_finite() || step <= 0.0 {
+        return Vec::new();
+    }
+
+    let mut edge_faces = HashMap::new();
+    for (f, tri) in mesh.tris.iter().enumerate() {
+        for k in 0..3 {
+            edge_faces.insert((tri[k], tri[(k + 1) % 3]), f);
+        }
+    }
+
+    let mut rng = rng::seeded_rng(0);
+    (0..count)
+        .map(|_| {
+            let face = rng.gen_range(0..mesh.tris.len());
+            let start = mesh.tris[face]
+                .iter()
+                .map(|v| mesh.vertices[*v])
+                .sum::<Vec3>()
+                / 3.0;
+            let Some(d) = face_direction(mesh, o_field, face, o_field[mesh.tris[face][0]]) else {
+                return vec![(start, face)];
+            };
+
+            let mut line = trace_from(mesh, o_field, &edge_faces, start, face, -d, step);
+            line.reverse();
+            line.pop();
+            line.extend(trace_from(mesh, o_field, &edge_faces, start, face, d, step));
+            line
+        })
+        .collect()
+}
+
+/// Field direction within `face` closest to `heading`, projected into the face's plane.
+fn face_direction(
+    mesh: &ProcessMesh,
+    o_field: &[Vec3],
+    face: usize,
+    heading: Vec3,
+) -> Option<Vec3> {
+    let [a, b, c] = mesh.tris[face].map(|v| mesh.vertices[v]);
+    let n = (b - a).cross(c - a).normalize_or_zero();
+
+    let mut sum = Vec3::ZERO;
+    for v in mesh.tris[face] {
+        let o = o_field[v];
+        let p = mesh.normals[v].cross(o);
+        sum += [o, p, -o, -p]
+            .into_iter()
+            .max_by(|x, y| x.dot(heading).total_cmp(&y.dot(heading)))
+            .unwrap();
+    }
+
+    let d = (sum - n * sum.dot(n)).normalize_or_zero();
+    (d != Vec3::ZERO).then_some(d)
+}
+
+/// Barycentric coordinates of `p` projected into the plane of `corners`, or `None` for a
+/// degenerate triangle.
+fn barycentric([a, b, c]: [Vec3; 3], p: Vec3) -> Option<[f32; 3]> {
+    let (e0, e1, e2) = (b - a, c - a, p - a);
+    let (d00, d01, d11) = (e0.dot(e0), e0.dot(e1), e1.dot(e1));
+    let (d20, d21) = (e2.dot(e0), e2.dot(e1));
+    let denom = d00 * d11 - d01 * d01;
+    if denom <= 0.0 {
+        return None;
+    }
+
+    let v = (d11 * d20 - d01 * d21) / denom;
+    let w = (d00 * d21 - d01 * d20) / denom;
+    Some([1.0 - v - w, v, w])
+}
+
+/// Follow the field from `p` on `face`, starting along `heading`, until a boundary, a vanishing
+/// field or the step limit.
+fn trace_from(
+    mesh: &ProcessMesh,
+    o_field: &[Vec3],
+    edge_faces: &HashMap<(usize, usize), usize>,
+    mut p: Vec3,
+    mut face: usize,
+    mut heading: Vec3,
+    step: f32,
+) -> Vec<(Vec3, usize)> {
+    let mut line = vec![(p, face)];
+    for _ in 0..MAX_STREAMLINE_STEPS {
+        let Some(d) = face_direction(mesh, o_field, face, heading) else {
+            break;
+        };
+        heading = d;
+
+        let tri = mesh.tris[face];
+        let corners = tri.map(|v| mesh.vertices[v]);
+        let target = p + step * d;
+        let (Some(from), Some(to)) = (barycentric(corners, p), barycentric(corners, target)) else {
+            break;
+        };
+
+        // Stop at the first edge the step would cross and continue on the face beyond it
+        let exit = (0..3)
+            .filter(|k| to[*k] < 0.0)
+            .map(|k| (k, from[k] / (from[k] - to[k])))
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        match exit {
+            None => {
+                p = target;
+                line.push((p, face));
+            }
+            Some((k, t)) => {
+                p += t.clamp(0.0, 1.0) * step * d;
+                line.push((p, face));
+                match edge_faces.get(&(tri[(k + 2) % 3], tri[(k + 1) % 3])) {
+                    Some(next) => face = *next,
+                    None => break,
+                }
+            }
+        }
+    }
+    line
+}
+
+/// Starting field for the coarsest level: `seed_direction` projected into each tangent plane, or
+/// a random tangent direction where that is unset or degenerate.
+fn initial_field<R: Rng>(
+    mesh: &ProcessMesh,
+    seed_direction: Option<Vec3>,
+    rng: &mut R,
+) -> Vec<Vec3> {
+    let mut init = vec![Vec3::ZERO; mesh.vertices.len()];
+    for (i, v) in init.iter_mut().enumerate() {
+        let n = mesh.normals[i];
+
+        if let Some(d) = seed_direction {
+            let projected = (d - n * d.dot(n)).normalize_or_zero();
+            if projected != Vec3::ZERO {
+                *v = projected;
+                continue;
+            }
+        }
+
+        let (x, y) = tangent_frame(n);
+        let theta = rng.gen::<f32>() * std::f32::consts::TAU;
+
+        *v = x * theta.cos() + y * theta.sin();
+    }
+    init
+}
+
+/// Copy a field from the next coarser level onto the vertices of `level`.
+fn prolong(level: &HierarchyLevel, coarse_field: &[Vec3]) -> Vec<Vec3> {
+    level.up_mapping.iter().map(|i| coarse_field[*i]).collect()
+}
+
+/// A smoothed field on one hierarchy level, along with the settings it was smoothed with, so that
+/// a later solve can tell whether it still holds.
+#[derive(Clone)]
+struct LevelField {
+    key: LevelKey,
+    field: Vec<Vec3>,
+}
+
+/// Everything that determines the smoothed field of a level, given the field of the level below.
+#[derive(Clone, PartialEq)]
+struct LevelKey {
+    iterations: usize,
+    constraints: Vec<(usize, Vec3)>,
+    seed: u64,
+    seed_direction: Option<Vec3>,
+    normal_weighting: bool,
+    boundary_alignment: f32,
+    confidence: Option<Vec<f32>>,
+}
+
+/// Carry `constraints` on the finest level down through `up_mapping`, returning the constraints
+/// of every level ordered from the coarsest. Each must be on a vertex of the finest level, as
+/// [`check_constraints`] makes sure.
+fn level_constraints(
+    hierarchy: &[HierarchyLevel],
+    constraints: &[(usize, Vec3)],
+) -> Vec<Vec<(usize, Vec3)>> {
+    let mut levels = vec![constraints.to_vec()];
+    for level in hierarchy[1..].iter().rev() {
+        let finer = levels.last().unwrap();
+        let coarser = finer
+            .iter()
+            .map(|(i, d)| (level.up_mapping[*i], *d))
+            .collect();
+        levels.push(coarser);
+    }
+    levels.reverse();
+    levels
+}
+
+/// Carry the per-vertex `confidence` of the finest level of `hierarchy` down through
+/// `up_mapping`, averaging over the vertices merged into each coarse one. Returns the confidence
+/// of every level ordered from the coarsest.
+fn level_confidence(hierarchy: &[HierarchyLevel], confidence: &[f32]) -> Vec<Vec<f32>> {
+    let mut levels = vec![confidence.to_vec()];
+    for (k, level) in hierarchy.iter().enumerate().skip(1).rev() {
+        let finer = levels.last().unwrap();
+        let num_coarse = hierarchy[k - 1].mesh.vertices.len();
+        let (mut sum, mut count) = (vec![0.0; num_coarse], vec![0; num_coarse]);
+        for (i, c) in finer.iter().enumerate() {
+            sum[level.up_mapping[i]] += c;
+            count[level.up_mapping[i]] += 1;
+        }
+        let coarser = sum
+            .into_iter()
+            .zip(count)
+            .map(|(sum, count)| if count > 0 { sum / count as f32 } else { 1.0 })
+            .collect();
+        levels.push(coarser);
+    }
+    levels.reverse();
+    levels
+}
+
+/// Boundary tangents of the finest level of `hierarchy`, carried down through `up_mapping` so
+/// that a coarse vertex takes the tangent of its first fine vertex on the boundary. Returns the
+/// tangents of every level ordered from the coarsest.
+fn level_boundaries(hierarchy: &[HierarchyLevel]) -> Vec<Vec<Option<Vec3>>> {
+    let mut levels = vec![hierarchy[hierarchy.len() - 1].mesh.boundary_tangents()];
+    for (k, level) in hierarchy.iter().enumerate().skip(1).rev() {
+        let finer = levels.last().unwrap();
+        let mut coarser = vec![None; hierarchy[k - 1].mesh.vertices.len()];
+        for (i, t) in finer.iter().enumerate() {
+            let c = &mut coarser[level.up_mapping[i]];
+            if c.is_none() {
+                *c = *t;
+            }
+        }
+        levels.push(coarser);
+    }
+    levels.reverse();
+    levels
+}
+
+/// Smooth every level of `hierarchy` coarse-to-fine, leaving one [`LevelField`] per level in
+/// `cache`.
+///
+/// Levels already in `cache` are reused, up to the first one whose [`LevelKey`] has changed;
+/// that level and every finer one are smoothed again. Passing an empty cache runs every level.
+fn smooth_levels(
+    hierarchy: &[HierarchyLevel],
+    options: &SmoothingOptions,
+    constraints: &[(usize, Vec3)],
+    mut energy: Option<&mut Vec<f32>>,
+    sweeps: &mut usize,
+    cache: &mut Vec<LevelField>,
+) {
+    let mut runner = LevelSweeps::new(hierarchy, options, constraints, std::mem::take(cache));
+    while runner.step() {
+        *sweeps += 1;
+        if let (Some(energy), Some(e)) = (energy.as_deref_mut(), runner.energy()) {
+            energy.push(e);
+        }
+    }
+    *cache = runner.levels;
+}
+
+/// [`smooth_levels`] without a cache, returning the field of the finest level.
+fn smooth_all_levels(
+    hierarchy: &[HierarchyLevel],
+    options: &SmoothingOptions,
+    constraints: &[(usize, Vec3)],
+    energy: Option<&mut Vec<f32>>,
+    sweeps: &mut usize,
+) -> Vec<Vec3> {
+    let mut levels = Vec::with_capacity(hierarchy.len());
+    smooth_levels(hierarchy, options, constraints, energy, sweeps, &mut levels);
+    levels.pop().unwrap().field
+}
+
+/// A level [`LevelSweeps`] is partway through.
+struct ActiveLevel {
+    key: LevelKey,
+    field: Vec<Vec3>,
+    /// Where the next sweep writes its result, before it is swapped with `field`.
+    scratch: Vec<Vec3>,
+    /// Vertices free to move, or `None` if all are.
+    active: Option<Vec<bool>>,
+    sweeps_left: usize,
+}
+
+/// [`smooth_levels`] run one sweep at a time, so that long solves can be interleaved with other
+/// work.
+pub(crate) struct LevelSweeps<'a> {
+    hierarchy: &'a [HierarchyLevel],
+    /// Boundary tangents of every level, from the coarsest, or empty if the boundary is free.
+    boundaries: Vec<Vec<Option<Vec3>>>,
+    /// Keys of the levels still to start, the next one last.
+    pending: Vec<LevelKey>,
+    /// Levels finished so far, from the coarsest.
+    levels: Vec<LevelField>,
+    current: Option<ActiveLevel>,
+}
+
+impl<'a> LevelSweeps<'a> {
+    /// Prepare to smooth `hierarchy`, reusing the levels of `cache` as [`smooth_levels`] does.
+    fn new(
+        hierarchy: &'a [HierarchyLevel],
+        options: &SmoothingOptions,
+        constraints: &[(usize, Vec3)],
+        mut cache: Vec<LevelField>,
+    ) -> Self {
+        let num_vertices = hierarchy[hierarchy.len() - 1].mesh.vertices.len();
+        let confidence = match &options.confidence {
+            Some(confidence) if confidence.len() == num_vertices => {
+                level_confidence(hierarchy, confidence)
+                    .into_iter()
+                    .map(Some)
+                    .collect()
+            }
+            Some(confidence) => {
+                log::warn!(
+                    "Ignoring {} confidence values for a mesh with {} vertices",
+                    confidence.len(),
+                    num_vertices
+                );
+                vec![None; hierarchy.len()]
+            }
+            None => vec![None; hierarchy.len()],
+        };
+
+        let mut keys = level_constraints(hierarchy, constraints)
+            .into_iter()
+            .zip(confidence)
+            .enumerate()
+            .map(|(level, (constraints, confidence))| LevelKey {
+                iterations: options.iterations_for(level),
+                constraints,
+                seed: options.seed,
+                seed_direction: options.seed_direction,
+                normal_weighting: options.normal_weighting,
+                boundary_alignment: options.boundary_alignment,
+                confidence,
+            })
+            .collect::<Vec<_>>();
+
+        let reused = cache
+            .iter()
+            .zip(&keys)
+            .take_while(|(cached, key)| cached.key == **key)
+            .count();
+        cache.truncate(reused);
+
+        let mut pending = keys.split_off(reused);
+        pending.reverse();
+        let boundaries = if options.boundary_alignment > 0.0 && !pending.is_empty() {
+            level_boundaries(hierarchy)
+        } else {
+            Vec::new()
+        };
+        Self {
+            hierarchy,
+            boundaries,
+            pending,
+            levels: cache,
+            current: None,
+        }
+    }
+
+    /// Prepare to smooth `hierarchy` from scratch.
+    pub(crate) fn start(hierarchy: &'a [HierarchyLevel], options: &SmoothingOptions) -> Self {
+        Self::new(hierarchy, options, &[], Vec::with_capacity(hierarchy.len()))
+    }
+
+    /// Run the next sweep, first moving on to the next level if the current one is done.
+    /// Returns false once every level is done.
+    pub(crate) fn step(&mut self) -> bool {
+        loop {
+            let level = self.levels.len();
+            let current = match &mut self.current {
+                Some(current) => current,
+                None => {
+                    let Some(key) = self.pending.pop() else {
+                        return false;
+                    };
+                    self.current.insert(self.start_level(level, key))
+                }
+            };
+
+            if current.sweeps_left == 0 {
+                let done = self.current.take().unwrap();
+                self.levels.push(LevelField {
+                    key: done.key,
+                    field: done.field,
+                });
+                continue;
+            }
+
+            smooth_sweep(
+                &self.hierarchy[level].mesh,
+                &current.field,
+                &mut current.scratch,
+                current.active.as_deref(),
+                SweepWeights {
+                    normal_weighting: current.key.normal_weighting,
+                    confidence: current.key.confidence.as_deref(),
+                    boundary: self
+                        .boundaries
+                        .get(level)
+                        .map(|tangents| (tangents.as_slice(), current.key.boundary_alignment)),
+                },
+            );
+            mem::swap(&mut current.field, &mut current.scratch);
+            current.sweeps_left -= 1;
+            return true;
+        }
+    }
+
+    /// The starting field of `level`, with its constrained vertices pinned.
+    fn start_level(&self, level: usize, key: LevelKey) -> ActiveLevel {
+        let mesh = &self.hierarchy[level].mesh;
+        let mut field = if level == 0 {
+            let mut rng = rng::seeded_rng(key.seed);
+            initial_field(mesh, key.seed_direction, &mut rng)
+        } else {
+            prolong(&self.hierarchy[level], &self.levels[level - 1].field)
+        };
+
+        let active = if key.constraints.is_empty() {
+            None
+        } else {
+            let mut active = vec![true; mesh.vertices.len()];
+            for (i, d) in &key.constraints {
+                let n = mesh.normals[*i];
+                let projected = (*d - n * d.dot(n)).normalize_or_zero();
+                if projected != Vec3::ZERO {
+                    field[*i] = projected;
+                    active[*i] = false;
+                }
+            }
+            Some(active)
+        };
+
+        ActiveLevel {
+            sweeps_left: key.iterations,
+            key,
+            scratch: vec![Vec3::ZERO; field.len()],
+            field,
+            active,
+        }
+    }
+
+    /// [`field_energy`] of the level being smoothed, or `None` between levels.
+    fn energy(&self) -> Option<f32> {
+        let current = self.current.as_ref()?;
+        Some(field_energy(
+            &self.hierarchy[self.levels.len()].mesh,
+            &current.field,
+        ))
+    }
+
+    /// The field of the finest level, once [`LevelSweeps::step`] has returned false.
+    pub(crate) fn into_field(mut self) -> Vec<Vec3> {
+        self.levels.pop().unwrap().field
+    }
+}
+
+/// An orientation field smoothed a few sweeps at a time, so an app can show it converging. It
+/// starts on the coarsest level of the hierarchy and moves to finer levels only when told to.
+#[wasm_bindgen]
+pub struct Smoother {
+    hierarchy: Vec<HierarchyLevel>,
+    level: usize,
+    field: Vec<Vec3>,
+    sweeps: usize,
+}
+
+#[wasm_bindgen]
+impl Smoother {
+    /// Build the hierarchy for `mesh` and start from a random field on its coarsest level, drawn
+    /// from `seed` (0 if unset).
+    #[wasm_bindgen(constructor)]
+    pub fn new(mesh: &InputMesh, seed: Option<u64>) -> Smoother {
+        let hierarchy = hierarchy::build(ProcessMesh::from(mesh.clone()));
+        let mut rng = rng::seeded_rng(seed.unwrap_or(0));
+        let field = initial_field(&hierarchy[0].mesh, None, &mut rng);
+        Smoother {
+            hierarchy,
+            level: 0,
+            field,
+            sweeps: 0,
+        }
+    }
+
+    /// Run `sweeps` smoothing sweeps on the current level.
+    pub fn step(&mut self, sweeps: usize) {
+        let mesh = &self.hierarchy[self.level].mesh;
+        smooth_sweeps(mesh, &mut self.field, None, SweepWeights::default(), sweeps);
+        self.sweeps += sweeps;
+    }
+
+    /// Carry the field to the next finer level. Returns false, changing nothing, if the current
+    /// level is already the finest.
+    pub fn promote(&mut self) -> bool {
+        if self.level + 1 >= self.hierarchy.len() {
+            return false;
+        }
+
+        self.level += 1;
+        self.field = prolong(&self.hierarchy[self.level], &self.field);
+        true
+    }
+
+    /// Index of the current level, counting up from the coarsest.
+    #[wasm_bindgen(getter)]
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn num_levels(&self) -> usize {
+        self.hierarchy.len()
+    }
+
+    /// Sweeps run so far, over all levels.
+    #[wasm_bindgen(getter)]
+    pub fn sweeps(&self) -> usize {
+        self.sweeps
+    }
+
+    /// [`field_energy`] of the current level.
+    #[wasm_bindgen(getter)]
+    pub fn energy(&self) -> f32 {
+        field_energy(&self.hierarchy[self.level].mesh, &self.field)
+    }
+
+    /// The field so far as packed `x, y, z` triples, one per vertex of the input mesh. Until the
+    /// finest level is reached, each vertex takes the direction of its coarse representative.
+    #[wasm_bindgen(getter)]
+    pub fn field(&self) -> Vec<f32> {
+        self.fine_field()
+            .iter()
+            .flat_map(|o| o.to_array())
+            .collect()
+    }
+}
+
+impl Smoother {
+    /// The finest hierarchy level, which [`Smoother::fine_field`] is defined on.
+    pub(crate) fn mesh(&self) -> &ProcessMesh {
+        &self.hierarchy[self.hierarchy.len() - 1].mesh
+    }
+
+    /// The current field carried down to the finest level.
+    pub(crate) fn fine_field(&self) -> Vec<Vec3> {
+        let mut field = self.field.clone();
+        for level in &self.hierarchy[self.level + 1..] {
+            field = prolong(level, &field);
+        }
+        field
+    }
+}
+
+/// An orientation field solved over a cached hierarchy, which can be re-solved cheaply as the
+/// user pins vertices to hand-picked directions.
+#[wasm_bindgen]
+pub struct FieldSolver {
+    hierarchy: Vec<HierarchyLevel>,
+    iterations: usize,
+    schedule: Option<Vec<usize>>,
+    seed: u64,
+    constraints: Vec<(usize, Vec3)>,
+    levels: Vec<LevelField>,
+    field: Vec<Vec3>,
+    sweeps: usize,
+}
+
+#[wasm_bindgen]
+impl FieldSolver {
+    /// Build the hierarchy for `mesh` and solve an unconstrained field on it.
+    #[wasm_bindgen(constructor)]
+    pub fn new(mesh: &InputMesh, iterations: usize) -> FieldSolver {
+        let hierarchy = hierarchy::build(ProcessMesh::from(mesh.clone()));
+        let mut solver = FieldSolver {
+            hierarchy,
+            iterations,
+            schedule: None,
+            seed: 0,
+            constraints: Vec::new(),
+            levels: Vec::new(),
+            field: Vec::new(),
+            sweeps: 0,
+        };
+        solver.solve();
+        solver
+    }
+
+    /// Pin `vertex` to the direction `(x, y, z)`, replacing any earlier constraint on it. Takes
+    /// effect on the next [`FieldSolver::solve`].
+    pub fn set_constraint(&mut self, vertex: usize, x: f32, y: f32, z: f32) -> Result<(), JsValue> {
+        if vertex >= self.field.len() {
+            return Err("Vertex index out of range".into());
+        }
+
+        let direction = vec3(x, y, z);
+        match self.constraints.iter_mut().find(|(i, _)| *i == vertex) {
+            Some(c) => c.1 = direction,
+            None => self.constraints.push((vertex, direction)),
+        }
+        Ok(())
+    }
+
+    pub fn remove_constraint(&mut self, vertex: usize) {
+        self.constraints.retain(|(i, _)| *i != vertex);
+    }
+
+    pub fn clear_constraints(&mut self) {
+        self.constraints.clear();
+    }
+
+    /// Vertices that currently carry a constraint.
+    #[wasm_bindgen(getter)]
+    pub fn constrained_vertices(&self) -> Vec<usize> {
+        self.constraints.iter().map(|(i, _)| *i).collect()
+    }
+
+    /// Sweeps run on every level not covered by [`FieldSolver::set_schedule`]. Takes effect on
+    /// the next [`FieldSolver::solve`].
+    #[wasm_bindgen(setter)]
+    pub fn set_iterations(&mut self, iterations: usize) {
+        self.iterations = iterations;
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn iterations(&self) -> usize {
+        self.iterations
+    }
+
+    /// Set the sweeps run on each level, indexed from the coarsest, as in
+    /// [`SmoothingOptions::schedule`]. Levels past the end of `schedule` keep the iteration count
+    /// given at construction. Takes effect on the next [`FieldSolver::solve`].
+    pub fn set_schedule(&mut self, schedule: Vec<usize>) {
+        self.schedule = Some(schedule);
+    }
+
+    /// Seed for the random initial directions, as in [`SmoothingOptions::seed`]. Takes effect on
+    /// the next [`FieldSolver::solve`].
+    #[wasm_bindgen(setter)]
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Re-solve the field, honoring the current constraints.
+    ///
+    /// Each level's field is kept from the last solve, and levels are only smoothed again from
+    /// the coarsest one whose sweeps or constraints have changed. The result is the same as
+    /// solving from scratch.
+    pub fn solve(&mut self) {
+        let options = SmoothingOptions {
+            iterations: self.iterations,
+            schedule: self.schedule.clone(),
+            seed: self.seed,
+            ..Default::default()
+        };
+        self.sweeps = 0;
+        smooth_levels(
+            &self.hierarchy,
+            &options,
+            &self.constraints,
+            None,
+            &mut self.sweeps,
+            &mut self.levels,
+        );
+        self.field = self.levels[self.levels.len() - 1].field.clone();
+    }
+
+    /// Re-solve the field with `iterations` sweeps per level, keeping the hierarchy built at
+    /// construction, for tuning the iteration count interactively.
+    pub fn smooth(&mut self, iterations: usize) {
+        self.iterations = iterations;
+        self.solve();
+    }
+
+    /// Smoothing sweeps run by the last solve, over the levels it did not reuse.
+    #[wasm_bindgen(getter)]
+    pub fn sweeps(&self) -> usize {
+        self.sweeps
+    }
+
+    /// Export the mesh and solved field as a binary glTF (`.glb`), with the field in a custom
+    /// `_ORIENTATION` vertex attribute and, if requested, the dual areas in `_DUAL_AREA`. Fails if
+    /// the mesh has no triangles.
+    pub fn to_glb(&self, include_dual_area: bool) -> Result<Vec<u8>, JsValue> {
+        gltf::write_glb(self.mesh(), &self.field, include_dual_area)
+            .map_err(|e| e.to_string().into())
+    }
+
+    /// Export the solved field as CSV, as from [`field_to_csv`].
+    pub fn to_csv(&self) -> String {
+        field_to_csv(&self.mesh().vertices, &self.field)
+    }
+
+    /// Solve a position field with lattice spacing `scale` against the current orientation
+    /// field, running `iterations` sweeps per level, and extract a quad-dominant mesh from it.
+    pub fn extract_quads(&self, scale: f32, iterations: usize) -> QuadMesh {
+        let p_field = position::hierarchical_position_smoothing(
+            &self.hierarchy,
+            &self.field,
+            scale,
+            iterations,
+        );
+        remesh::extract_quads(&self.hierarchy, &self.field, &p_field, scale)
+    }
+
+    /// The solved field as packed `x, y, z` triples, one per vertex of the input mesh.
+    #[wasm_bindgen(getter)]
+    pub fn field(&self) -> Vec<f32> {
+        self.field.iter().flat_map(|o| o.to_array()).collect()
+    }
+
+    /// Per-vertex misalignment of the solved field, as from [`residuals`], for showing with
+    /// [`Renderer::color_by_scalar`](crate::renderer::Renderer::color_by_scalar).
+    #[wasm_bindgen(getter)]
+    pub fn residuals(&self) -> Vec<f32> {
+        residuals(self.mesh(), &self.field)
+    }
+
+    /// Per-vertex tangent frames as from [`tangent_frames`], packed as nine floats per vertex:
+    /// tangent, bitangent and normal.
+    #[wasm_bindgen(getter)]
+    pub fn tangent_frames(&self) -> Vec<f32> {
+        tangent_frames(&self.mesh().normals, &self.field)
+            .iter()
+            .flat_map(|frame| frame.iter().flat_map(|v| v.to_array()))
+            .collect()
+    }
+}
+
+impl FieldSolver {
+    /// The hierarchy the field is solved over, from the coarsest level.
+    pub(crate) fn hierarchy(&self) -> &[HierarchyLevel] {
+        &self.hierarchy
+    }
+
+    /// The finest hierarchy level, which the field is defined on.
+    pub(crate) fn mesh(&self) -> &ProcessMesh {
+        &self.hierarchy[self.hierarchy.len() - 1].mesh
+    }
+
+    pub(crate) fn o_field(&self) -> &[Vec3] {
+        &self.field
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Quat;
+
+    use super::*;
+    use crate::shapes;
+
+    #[test]
+    fn compat_ties_keep_the_unrotated_pair() {
+        // o1 is 45 degrees from both o0 and its quarter turn, so the two pairs tie
+        let o1 = vec3(1.0, 1.0, 0.0).normalize();
+        assert_eq!(
+            extrinsic_compat(Vec3::X, Vec3::Z, o1, Vec3::Z),
+            (Vec3::X, o1)
+        );
+        assert_eq!(
+            extrinsic_compat(o1, Vec3::Z, Vec3::X, Vec3::Z),
+            (o1, Vec3::X)
+        );
+    }
+
+    #[test]
+    fn confident_half_of_a_plane_sets_the_field() {
+        let mesh = ProcessMesh::from(shapes::plane(8));
+        let diagonal = vec3(1.0, 1.0, 0.0).normalize();
+        let confident = mesh.vertices.iter().map(|v| v.x < 0.0).collect::<Vec<_>>();
+        let confidence = confident
+            .iter()
+            .map(|&c| if c { 1.0 } else { 0.01 })
+            .collect::<Vec<_>>();
+        let alignment = |weighted: bool| {
+            let mut o_field = confident
+                .iter()
+                .map(|&c| if c { Vec3::X } else { diagonal })
+                .collect::<Vec<_>>();
+            for _ in 0..100 {
+                if weighted {
+                    extrinsic_smooth_weighted(&mesh, &mut o_field, None, &confidence).unwrap();
+                } else {
+                    extrinsic_smooth(&mesh, &mut o_field, None).unwrap();
+                }
+            }
+            o_field
+                .iter()
+                .map(|&o| {
+                    extrinsic_compat(Vec3::X, Vec3::Z, o, Vec3::Z)
+                        .1
+                        .dot(Vec3::X)
+                })
+                .fold(f32::INFINITY, f32::min)
+        };
+        let (weighted, unweighted) = (alignment(true), alignment(false));
+        assert!(weighted > 0.99, "field strayed {} radians", weighted.acos());
+        assert!(unweighted < 0.95, "{}", unweighted);
+    }
+
+    #[test]
+    fn inputs_without_an_entry_per_vertex_are_rejected() {
+        let mesh = ProcessMesh::from(shapes::plane(2));
+        let n = mesh.vertices.len();
+        let mut o_field = vec![Vec3::X; n];
+        assert!(extrinsic_smooth(&mesh, &mut o_field[1..], None).is_err());
+        assert!(extrinsic_smooth(&mesh, &mut o_field, Some(&[true; 2])).is_err());
+        assert!(extrinsic_smooth_weighted(&mesh, &mut o_field, None, &[1.0; 2]).is_err());
+        assert!(extrinsic_smooth_aligned(&mesh, &mut o_field, None, &[None; 2], 1.0).is_err());
+        assert!(o_field.iter().all(|o| *o == Vec3::X));
+
+        let hierarchy = hierarchy::build(mesh);
+        let err = hierarchical_smoothing(&hierarchy, 1, None, &[(n, Vec3::X)]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("Constraint on vertex {} of a mesh with {} vertices", n, n)
+        );
+    }
+
+    #[test]
+    fn flat_patch_field_settles_between_tied_starts() {
+        // A checkerboard of crosses at 0 and 45 degrees, so that every vertex ties with the
+        // neighbors along the grid lines. Breaking each tie with the least rotation meets them
+        // halfway, rather than a quarter turn off on either side.
+        let mesh = ProcessMesh::from(shapes::plane(8));
+        let diagonal = vec3(1.0, 1.0, 0.0).normalize();
+        let mut o_field = (0..mesh.vertices.len())
+            .map(|i| if i % 2 == 0 { Vec3::X } else { diagonal })
+            .collect::<Vec<_>>();
+        for _ in 0..100 {
+            extrinsic_smooth(&mesh, &mut o_field, None).unwrap();
+        }
+
+        let halfway = Quat::from_rotation_z(std::f32::consts::PI / 8.0) * Vec3::X;
+        for (i, o) in o_field.iter().enumerate() {
+            assert!(
+                o.abs_diff_eq(halfway, 1e-3),
+                "vertex {} settled at {}",
+                i,
+                o
+            );
+        }
+    }
+
+    #[test]
+    fn smooth_region_holds_inactive_vertices() {
+        let mesh = ProcessMesh::from(shapes::plane(8));
+        let start = (0..mesh.vertices.len())
+            .map(|i| Quat::from_rotation_z(i as f32) * Vec3::X)
+            .collect::<Vec<_>>();
+        let active = (0..mesh.vertices.len())
+            .map(|i| i % 3 == 0)
+            .collect::<Vec<_>>();
+
+        let mut o_field = start.clone();
+        smooth_region(&mesh, &mut o_field, &active, 5).unwrap();
+        for (i, (o, s)) in o_field.iter().zip(&start).enumerate() {
+            assert_eq!(active[i], o != s, "vertex {}", i);
+        }
+
+        assert!(smooth_region(&mesh, &mut o_field, &active[1..], 5).is_err());
+    }
+
+    /// Unit disk in the XY plane, with `rings` rings of `segments` vertices around its center.
+    fn disk(rings: usize, segments: usize) -> InputMesh {
+        let ring = |k: usize, s: usize| 1 + (k - 1) * segments + s % segments;
+        let mut vertices = vec![Vec3::ZERO];
+        let mut tris = Vec::new();
+        for k in 1..=rings {
+            for s in 0..segments {
+                let angle = s as f32 / segments as f32 * std::f32::consts::TAU;
+                vertices.push(k as f32 / rings as f32 * vec3(angle.cos(), angle.sin(), 0.0));
+                if k == 1 {
+                    tris.push([0, ring(1, s), ring(1, s + 1)]);
+                } else {
+                    let [a, b] = [ring(k - 1, s), ring(k - 1, s + 1)];
+                    let [c, d] = [ring(k, s), ring(k, s + 1)];
+                    tris.extend([[a, c, d], [a, d, b]]);
+                }
+            }
+        }
+        InputMesh::from_triangles(vertices, tris)
+    }
+
+    #[test]
+    fn open_disk_field_aligns_with_its_boundary() {
+        let mesh = ProcessMesh::from(disk(6, 32));
+        let tangents = mesh.boundary_tangents();
+        // Angle between a cross and the tangent, folded by quarter turns into the cosine of
+        // four times it
+        let alignment = |o: Vec3, t: Vec3| (4.0 * (o.y.atan2(o.x) - t.y.atan2(t.x))).cos();
+
+        let mut free = vec![Vec3::X; mesh.vertices.len()];
+        let mut aligned = free.clone();
+        for _ in 0..100 {
+            extrinsic_smooth(&mesh, &mut free, None).unwrap();
+            extrinsic_smooth_aligned(&mesh, &mut aligned, None, &tangents, 16.0).unwrap();
+        }
+
+        let boundary = tangents
+            .iter()
+            .enumerate()
+            .filter_map(|(i, t)| t.map(|t| (i, t)))
+            .collect::<Vec<_>>();
+        assert_eq!(boundary.len(), 32);
+        for &(i, t) in &boundary {
+            assert!(
+                alignment(aligned[i], t) > 0.9,
+                "vertex {} points along {}",
+                i,
+                aligned[i]
+            );
+        }
+        // Left alone, the uniform start is already smooth and never turns to the boundary
+        assert!(boundary.iter().any(|&(i, t)| alignment(free[i], t) < 0.0));
+    }
+
+    #[test]
+    fn constrained_plane_field_follows_the_constraint() {
+        let mesh = ProcessMesh::from(shapes::plane(8));
+        let center = mesh.vertices.len() / 2;
+        let hierarchy = crate::hierarchy::build(mesh);
+
+        // Off the tangent plane, so the pin must be projected into it
+        let angle = 0.5f32;
+        let direction = vec3(angle.cos(), angle.sin(), 0.3);
+        let field = hierarchical_smoothing(&hierarchy, 20, None, &[(center, direction)]).unwrap();
+
+        let pinned = vec3(angle.cos(), angle.sin(), 0.0);
+        assert!(field[center].abs_diff_eq(pinned, 1e-5), "{}", field[center]);
+        for (i, o) in field.iter().enumerate() {
+            // Crosses match under quarter turns, so compare four times the angle
+            let offset = o.y.atan2(o.x) - angle;
+            assert!(
+                (4.0 * offset).cos() > 0.99,
+                "vertex {} points along {}",
+                i,
+                o
+            );
+        }
+    }
+
+    #[test]
+    fn sweeps_ignore_triangle_order() {
+        let input = shapes::icosphere(2);
+        let mut shuffled = input.clone();
+        shuffled.tris.reverse();
+        for (k, t) in shuffled.tris.iter_mut().enumerate() {
+            t.rotate_left(k % 3);
+        }
+        let (mesh, shuffled) = (ProcessMesh::from(input), ProcessMesh::from(shuffled));
+
+        let mut rng = rng::seeded_rng(7);
+        let start = initial_field(&mesh, None, &mut rng);
+        let (mut a, mut b) = (start.clone(), start);
+        for _ in 0..5 {
+            extrinsic_smooth(&mesh, &mut a, None).unwrap();
+            extrinsic_smooth(&shuffled, &mut b, None).unwrap();
+        }
+        assert_eq!(a, b);
+    }
 }
@@ -0,0 +1,57 @@
+struct Uniforms {
+    view_transform: mat4x4<f32>,
+    model_transform: mat4x4<f32>,
+}
+
+struct MeshUniforms {
+    transform: mat4x4<f32>,
+    // Inverse transpose of the transform, for carrying normals under non-uniform scaling
+    normal_transform: mat4x4<f32>,
+    // Index of the mesh among those drawn, written before each pick
+    index: u32,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) @interpolate(flat) ids: vec3<u32>,
+    @location(1) barycentric: vec3<f32>,
+    @location(2) @interpolate(flat) mesh: u32,
+};
+
+@group(0)
+@binding(0)
+var<uniform> uniforms: Uniforms;
+
+@group(1)
+@binding(0)
+var<uniform> mesh: MeshUniforms;
+
+@vertex
+fn vs_main(
+    @location(0) position: vec3<f32>,
+    @location(1) ids: vec3<u32>,
+    @location(2) corner: u32,
+) -> VertexOutput {
+    var result: VertexOutput;
+    var model_transform = uniforms.model_transform * mesh.transform;
+    result.position = uniforms.view_transform * model_transform * vec4<f32>(position, 1.0);
+    result.ids = ids;
+    result.mesh = mesh.index;
+    result.barycentric = vec3<f32>(f32(corner == 0u), f32(corner == 1u), f32(corner == 2u));
+
+    return result;
+}
+
+// The nearest vertex id and the mesh index. Vertex ids are written off by one so that 0 can mean
+// background
+@fragment
+fn fs_main(vertex: VertexOutput) -> @location(0) vec2<u32> {
+    var b = vertex.barycentric;
+    if (b.x >= b.y && b.x >= b.z) {
+        return vec2<u32>(vertex.ids.x + 1u, vertex.mesh);
+    }
+    if (b.y >= b.z) {
+        return vec2<u32>(vertex.ids.y + 1u, vertex.mesh);
+    }
+    return vec2<u32>(vertex.ids.z + 1u, vertex.mesh);
+}
@@ -0,0 +1,576 @@
+use std::{borrow::Cow, cell::Cell, fmt, rc::Rc};
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    diagnostics::Diagnostic,
+    hierarchy::{self, BuildOptions, HierarchyLevel, LevelBuilder},
+    mesh::{InputMesh, ProcessMesh},
+    orientation::{self, LevelSweeps, SmoothingOptions},
+};
+
+/// Every setting of the processing pipeline, for [`run_pipeline`]. The defaults reproduce what
+/// the individual stages do on their own.
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default)]
+pub struct PipelineConfig {
+    smoothing: SmoothingOptions,
+    hierarchy: BuildOptions,
+    cleanup: bool,
+}
+
+#[wasm_bindgen]
+impl PipelineConfig {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> PipelineConfig {
+        Default::default()
+    }
+
+    /// Smoothing sweeps run on every hierarchy level.
+    pub fn set_iterations(&mut self, iterations: usize) {
+        self.smoothing.iterations = iterations;
+    }
+
+    /// Rotational symmetry of the field. Only 4-RoSy (cross) fields are supported so far.
+    pub fn set_rosy(&mut self, rosy: u32) -> Result<(), JsValue> {
+        if rosy != 4 {
+            return Err("Only 4-RoSy fields are supported".into());
+        }
+        Ok(())
+    }
+
+    /// Sweeps for each hierarchy level, starting from the coarsest. Levels past the end of
+    /// `schedule` run the number set with `set_iterations`, as do all levels if it is empty.
+    pub fn set_schedule(&mut self, schedule: Vec<usize>) {
+        self.smoothing.schedule = (!schedule.is_empty()).then_some(schedule);
+    }
+
+    /// Seed for the random initial directions, as in [`SmoothingOptions::seed`].
+    pub fn set_seed(&mut self, seed: u64) {
+        self.smoothing.seed = seed;
+    }
+
+    /// Weight each neighbor during smoothing by how closely its normal agrees.
+    pub fn set_normal_weighting(&mut self, normal_weighting: bool) {
+        self.smoothing.normal_weighting = normal_weighting;
+    }
+
+    /// Pull the field at open boundaries to run along them, as strongly as `weight` neighbors
+    /// would. Zero, the default, leaves the boundary free.
+    pub fn set_boundary_alignment(&mut self, weight: f32) {
+        self.smoothing.boundary_alignment = weight.max(0.0);
+    }
+
+    /// Confidence of each vertex of the mesh, such as from a scanner, so that uncertain regions
+    /// sway the field less. Must have one value per vertex after any cleanup, or it is ignored
+    /// with a warning among the result's diagnostics. Empty, the default, trusts every vertex
+    /// equally.
+    pub fn set_confidence(&mut self, confidence: Vec<f32>) {
+        self.smoothing.confidence = (!confidence.is_empty()).then_some(confidence);
+    }
+
+    /// Stop coarsening the hierarchy once a level has at most this many vertices.
+    pub fn set_coarsening_threshold(&mut self, min_vertices: usize) {
+        self.hierarchy.min_vertices = min_vertices.max(1);
+    }
+
+    /// Weld duplicate vertices and remove duplicate faces before processing.
+    pub fn set_cleanup(&mut self, cleanup: bool) {
+        self.cleanup = cleanup;
+    }
+}
+
+/// Output of [`run_pipeline`]: the solved field along with statistics about the run.
+#[wasm_bindgen]
+pub struct PipelineResult {
+    field: Vec<glam::Vec3>,
+    report: PipelineReport,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// Numbers summarizing a pipeline run, for a dashboard or for regression checks. Everything but
+/// the timings is the same from run to run for the same mesh and [`PipelineConfig`].
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PipelineReport {
+    num_vertices: usize,
+    num_triangles: usize,
+    num_levels: usize,
+    sweeps: usize,
+    energy: f32,
+    singularities: usize,
+    non_manifold_vertices: usize,
+    welded_vertices: usize,
+    removed_faces: usize,
+    cleanup_ms: f64,
+    hierarchy_ms: f64,
+    smoothing_ms: f64,
+}
+
+#[wasm_bindgen]
+impl PipelineReport {
+    /// Vertices of the processed mesh, after any cleanup.
+    #[wasm_bindgen(getter)]
+    pub fn num_vertices(&self) -> usize {
+        self.num_vertices
+    }
+
+    /// Triangles of the processed mesh, after any cleanup.
+    #[wasm_bindgen(getter)]
+    pub fn num_triangles(&self) -> usize {
+        self.num_triangles
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn num_levels(&self) -> usize {
+        self.num_levels
+    }
+
+    /// Smoothing sweeps run over all hierarchy levels.
+    #[wasm_bindgen(getter)]
+    pub fn sweeps(&self) -> usize {
+        self.sweeps
+    }
+
+    /// [`orientation::field_energy`] of the final field.
+    #[wasm_bindgen(getter)]
+    pub fn energy(&self) -> f32 {
+        self.energy
+    }
+
+    /// Faces holding a singularity of the final field, as found by
+    /// [`orientation::singularities`].
+    #[wasm_bindgen(getter)]
+    pub fn singularities(&self) -> usize {
+        self.singularities
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn non_manifold_vertices(&self) -> usize {
+        self.non_manifold_vertices
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn welded_vertices(&self) -> usize {
+        self.welded_vertices
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn removed_faces(&self) -> usize {
+        self.removed_faces
+    }
+
+    /// Milliseconds spent copying and cleaning up the mesh and building its adjacency.
+    #[wasm_bindgen(getter)]
+    pub fn cleanup_ms(&self) -> f64 {
+        self.cleanup_ms
+    }
+
+    /// Milliseconds spent building the hierarchy. For [`run_pipeline_async`] this includes time
+    /// given to the event loop.
+    #[wasm_bindgen(getter)]
+    pub fn hierarchy_ms(&self) -> f64 {
+        self.hierarchy_ms
+    }
+
+    /// Milliseconds spent smoothing the field, likewise.
+    #[wasm_bindgen(getter)]
+    pub fn smoothing_ms(&self) -> f64 {
+        self.smoothing_ms
+    }
+}
+
+#[wasm_bindgen]
+impl PipelineResult {
+    /// The solved field as packed `x, y, z` triples, one per vertex of the processed mesh.
+    #[wasm_bindgen(getter)]
+    pub fn field(&self) -> Vec<f32> {
+        self.field.iter().flat_map(|o| o.to_array()).collect()
+    }
+
+    /// Statistics about the run as a whole.
+    #[wasm_bindgen(getter)]
+    pub fn report(&self) -> PipelineReport {
+        self.report
+    }
+
+    /// Warnings from loading and cleaning up the mesh, followed by one for each non-manifold
+    /// vertex found while processing it.
+    #[wasm_bindgen(getter)]
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.diagnostics.clone()
+    }
+}
+
+/// Run every processing stage on `mesh` as configured by `config`: optional cleanup, building
+/// the hierarchy, and smoothing the orientation field.
+#[wasm_bindgen]
+pub fn run_pipeline(mesh: &InputMesh, config: &PipelineConfig) -> PipelineResult {
+    let mut stopwatch = Stopwatch::start();
+    let (mesh, mut clean_up) = clean_up(mesh, config);
+    let smoothing = smoothing_options(config, &mesh, &mut clean_up.diagnostics);
+    let cleanup_ms = stopwatch.lap();
+    let hierarchy = hierarchy::build_with(mesh, &config.hierarchy);
+    let hierarchy_ms = stopwatch.lap();
+    let (field, sweeps) = orientation::hierarchical_smoothing_with(&hierarchy, &smoothing, &[])
+        .expect("Smoothing without constraints can't fail");
+    let smoothing_ms = stopwatch.lap();
+    pipeline_result(
+        &hierarchy,
+        field,
+        sweeps,
+        clean_up,
+        [cleanup_ms, hierarchy_ms, smoothing_ms],
+    )
+}
+
+/// Returned by [`run_pipeline_async`] when it is cancelled before finishing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Pipeline cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Longest stretch of work, in milliseconds, between yields to the event loop.
+const YIELD_INTERVAL_MS: f64 = 10.0;
+
+/// [`run_pipeline`], giving way to the event loop between hierarchy levels and smoothing sweeps
+/// so that a browser stays responsive during long runs. Stops with [`Cancelled`] at the next
+/// such point once `cancelled` is set. The result is the same as from [`run_pipeline`].
+pub async fn run_pipeline_async(
+    mesh: &InputMesh,
+    config: &PipelineConfig,
+    cancelled: &Cell<bool>,
+) -> Result<PipelineResult, Cancelled> {
+    let mut pause = Pause::new(cancelled);
+    let mut stopwatch = Stopwatch::start();
+    let (mesh, mut clean_up) = clean_up(mesh, config);
+    let smoothing = smoothing_options(config, &mesh, &mut clean_up.diagnostics);
+    let cleanup_ms = stopwatch.lap();
+    pause.check().await?;
+
+    let mut builder = LevelBuilder::new(mesh);
+    while builder.step(&config.hierarchy) {
+        pause.check().await?;
+    }
+    let hierarchy = builder.finish();
+    let hierarchy_ms = stopwatch.lap();
+
+    let mut sweeper = LevelSweeps::start(&hierarchy, &smoothing);
+    let mut sweeps = 0;
+    while sweeper.step() {
+        sweeps += 1;
+        pause.check().await?;
+    }
+    let field = sweeper.into_field();
+    let smoothing_ms = stopwatch.lap();
+
+    Ok(pipeline_result(
+        &hierarchy,
+        field,
+        sweeps,
+        clean_up,
+        [cleanup_ms, hierarchy_ms, smoothing_ms],
+    ))
+}
+
+/// What [`clean_up`] changed and found.
+struct CleanUp {
+    welded_vertices: usize,
+    removed_faces: usize,
+    non_manifold_vertices: usize,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// A copy of `mesh` ready for processing, cleaned up if `config` asks for it, along with what
+/// was changed and any warnings so far.
+fn clean_up(mesh: &InputMesh, config: &PipelineConfig) -> (ProcessMesh, CleanUp) {
+    let mut mesh = mesh.clone();
+    let (welded_vertices, removed_faces) = if config.cleanup {
+        (mesh.weld_vertices(), mesh.dedup_faces())
+    } else {
+        (0, 0)
+    };
+
+    let mut diagnostics = std::mem::take(&mut mesh.diagnostics);
+    let (mesh, non_manifold) = ProcessMesh::build(mesh);
+    let non_manifold_vertices = non_manifold.len();
+    // Already logged while building
+    diagnostics.extend(
+        non_manifold
+            .into_iter()
+            .map(|v| Diagnostic::warning(format!("Non-manifold vertex {}", v)).at_vertex(v)),
+    );
+    (
+        mesh,
+        CleanUp {
+            welded_vertices,
+            removed_faces,
+            non_manifold_vertices,
+            diagnostics,
+        },
+    )
+}
+
+/// The smoothing settings of `config` for `mesh`. Confidence values that don't match its
+/// vertices, as when cleanup merged some away, are dropped with a warning in `diagnostics`.
+fn smoothing_options<'a>(
+    config: &'a PipelineConfig,
+    mesh: &ProcessMesh,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Cow<'a, SmoothingOptions> {
+    match &config.smoothing.confidence {
+        Some(confidence) if confidence.len() != mesh.vertices.len() => {
+            Diagnostic::warning(format!(
+                "Ignoring {} confidence values for a mesh with {} vertices",
+                confidence.len(),
+                mesh.vertices.len()
+            ))
+            .report(diagnostics);
+            Cow::Owned(SmoothingOptions {
+                confidence: None,
+                ..config.smoothing.clone()
+            })
+        }
+        _ => Cow::Borrowed(&config.smoothing),
+    }
+}
+
+/// Gather the outputs of every stage, along with the milliseconds spent on cleanup, the
+/// hierarchy and smoothing in `[cleanup_ms, hierarchy_ms, smoothing_ms]`.
+fn pipeline_result(
+    hierarchy: &[HierarchyLevel],
+    field: Vec<glam::Vec3>,
+    sweeps: usize,
+    clean_up: CleanUp,
+    [cleanup_ms, hierarchy_ms, smoothing_ms]: [f64; 3],
+) -> PipelineResult {
+    let mesh = &hierarchy[hierarchy.len() - 1].mesh;
+    let report = PipelineReport {
+        num_vertices: mesh.vertices.len(),
+        num_triangles: mesh.tris.len(),
+        num_levels: hierarchy.len(),
+        sweeps,
+        energy: orientation::field_energy(mesh, &field),
+        singularities: orientation::singularities(mesh, &field).len(),
+        non_manifold_vertices: clean_up.non_manifold_vertices,
+        welded_vertices: clean_up.welded_vertices,
+        removed_faces: clean_up.removed_faces,
+        cleanup_ms,
+        hierarchy_ms,
+        smoothing_ms,
+    };
+    PipelineResult {
+        field,
+        report,
+        diagnostics: clean_up.diagnostics,
+    }
+}
+
+/// Measures the time taken by successive stages.
+struct Stopwatch {
+    last: f64,
+}
+
+impl Stopwatch {
+    fn start() -> Self {
+        Self { last: now_ms() }
+    }
+
+    /// Milliseconds since the previous lap, or since the start for the first one.
+    fn lap(&mut self) -> f64 {
+        let now = now_ms();
+        let elapsed = now - self.last;
+        self.last = now;
+        elapsed
+    }
+}
+
+/// Yields to the event loop once [`YIELD_INTERVAL_MS`] have passed since it last did, and
+/// reports cancellation.
+struct Pause<'a> {
+    cancelled: &'a Cell<bool>,
+    last: f64,
+}
+
+impl<'a> Pause<'a> {
+    fn new(cancelled: &'a Cell<bool>) -> Self {
+        Self {
+            cancelled,
+            last: now_ms(),
+        }
+    }
+
+    async fn check(&mut self) -> Result<(), Cancelled> {
+        if now_ms() - self.last >= YIELD_INTERVAL_MS {
+            yield_now().await;
+            self.last = now_ms();
+        }
+        if self.cancelled.get() {
+            return Err(Cancelled);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn now_ms() -> f64 {
+    js_sys::Date::now()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn now_ms() -> f64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0.0, |d| d.as_secs_f64() * 1000.0)
+}
+
+/// Let the event loop run. This waits on a zero timeout rather than an already resolved
+/// promise, since microtasks all run before the browser gets to input events such as a click on
+/// a cancel button.
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn yield_now() {
+    let promise = js_sys::Promise::new(&mut |resolve, _| {
+        let global = js_sys::global();
+        let set_timeout = js_sys::Reflect::get(&global, &"setTimeout".into())
+            .and_then(|f| f.dyn_into::<js_sys::Function>());
+        let scheduled =
+            set_timeout.and_then(|f| f.call2(&global, &resolve, &JsValue::from_f64(0.0)));
+        if scheduled.is_err() {
+            let _ = resolve.call0(&JsValue::UNDEFINED);
+        }
+    });
+    let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+}
+
+/// Let other futures on the executor run.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn yield_now() {
+    let mut yielded = false;
+    futures::future::poll_fn(|cx| {
+        if yielded {
+            std::task::Poll::Ready(())
+        } else {
+            yielded = true;
+            cx.waker().wake_by_ref();
+            std::task::Poll::Pending
+        }
+    })
+    .await
+}
+
+/// A pipeline run in the background, as from [`run_pipeline_async`], which can be cancelled.
+#[wasm_bindgen]
+pub struct PipelineTask {
+    cancelled: Rc<Cell<bool>>,
+    result: js_sys::Promise,
+}
+
+#[wasm_bindgen]
+impl PipelineTask {
+    /// Start running the pipeline on a copy of `mesh` with a copy of `config`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(mesh: &InputMesh, config: &PipelineConfig) -> PipelineTask {
+        let cancelled = Rc::new(Cell::new(false));
+        let (mesh, config, flag) = (mesh.clone(), config.clone(), cancelled.clone());
+        let result = wasm_bindgen_futures::future_to_promise(async move {
+            run_pipeline_async(&mesh, &config, &flag)
+                .await
+                .map(JsValue::from)
+                .map_err(|e| e.to_string().into())
+        });
+        PipelineTask { cancelled, result }
+    }
+
+    /// Resolves to the [`PipelineResult`], or rejects with "Pipeline cancelled" if the task was
+    /// cancelled first.
+    #[wasm_bindgen(getter)]
+    pub fn result(&self) -> js_sys::Promise {
+        self.result.clone()
+    }
+
+    /// Stop the run at its next yield point. Does nothing once it has finished.
+    pub fn cancel(&self) {
+        self.cancelled.set(true);
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn cancelled(&self) -> bool {
+        self.cancelled.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{vec3, Vec3};
+
+    use super::*;
+
+    /// Run the pipeline on `mesh` with cleanup on and off, checking for a finite field.
+    fn check_runs(mesh: &InputMesh) {
+        for cleanup in [false, true] {
+            let mut config = PipelineConfig::new();
+            config.set_cleanup(cleanup);
+            let result = run_pipeline(mesh, &config);
+            let field = result.field();
+            assert!(field.len() <= 3 * mesh.vertices.len());
+            assert!(field.iter().all(|x| x.is_finite()), "{:?}", field);
+            assert!(result.report().energy().is_finite());
+        }
+    }
+
+    #[test]
+    fn mismatched_confidence_is_reported_and_ignored() {
+        let mesh = crate::shapes::plane(4);
+        let mut config = PipelineConfig::new();
+        let expected = run_pipeline(&mesh, &config).field();
+
+        config.set_confidence(vec![1.0; 3]);
+        let result = run_pipeline(&mesh, &config);
+        assert_eq!(result.field(), expected);
+        let messages = result
+            .diagnostics()
+            .iter()
+            .map(|d| d.message())
+            .filter(|m| m.contains("confidence"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            ["Ignoring 3 confidence values for a mesh with 25 vertices"]
+        );
+    }
+
+    #[test]
+    fn empty_mesh() {
+        check_runs(&InputMesh::default());
+    }
+
+    #[test]
+    fn vertices_without_faces() {
+        check_runs(&InputMesh::from_triangles(
+            vec![Vec3::ZERO, Vec3::X, Vec3::Y],
+            Vec::new(),
+        ));
+    }
+
+    #[test]
+    fn single_triangle() {
+        check_runs(&InputMesh::from_triangles(
+            vec![Vec3::ZERO, Vec3::X, Vec3::Y],
+            vec![[0, 1, 2]],
+        ));
+    }
+
+    #[test]
+    fn triangle_with_an_isolated_vertex() {
+        check_runs(&InputMesh::from_triangles(
+            vec![Vec3::ZERO, Vec3::X, Vec3::Y, vec3(5.0, 5.0, 5.0)],
+            vec![[0, 1, 2]],
+        ));
+    }
+}
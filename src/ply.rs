@@ -1,8 +1,12 @@
-use anyhow::{bail, Context, Result};
+use std::rc::Rc;
+
+use anyhow::{anyhow, bail, Context, Result};
 use byteorder::{BigEndian, ByteOrder, LittleEndian};
 use glam::{vec3, Vec3};
 
-use crate::{mesh::InputMesh, stream::AsyncStreamReader};
+use crate::{
+    diagnostics::Diagnostic, format::LoadOptions, mesh::InputMesh, stream::AsyncStreamReader,
+};
 
 enum Format {
     Ascii,
@@ -45,9 +49,20 @@ impl DynamicScalar {
         }
     }
 
+    fn is_negative(&self) -> bool {
+        match self {
+            Self::I8(v) => *v < 0,
+            Self::I16(v) => *v < 0,
+            Self::I32(v) => *v < 0,
+            Self::F32(v) => *v < 0.0,
+            Self::F64(v) => *v < 0.0,
+            _ => false,
+        }
+    }
+
     fn as_f32(&self) -> Option<f32> {
         match self {
-            Self::F32(v) => Some(*v as f32),
+            Self::F32(v) => Some(*v),
             Self::F64(v) => Some(*v as f32),
             _ => None,
         }
@@ -55,16 +70,47 @@ impl DynamicScalar {
 }
 
 impl ScalarType {
+    /// Size of the scalar in a binary body, in bytes.
+    fn size(&self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    /// Decode the scalar from the start of `bytes`, which must hold at least
+    /// [`size`](Self::size) bytes.
+    fn decode<O: ByteOrder>(&self, bytes: &[u8]) -> DynamicScalar {
+        match self {
+            Self::I8 => DynamicScalar::I8(bytes[0] as i8),
+            Self::U8 => DynamicScalar::U8(bytes[0]),
+            Self::I16 => DynamicScalar::I16(O::read_i16(bytes)),
+            Self::U16 => DynamicScalar::U16(O::read_u16(bytes)),
+            Self::I32 => DynamicScalar::I32(O::read_i32(bytes)),
+            Self::U32 => DynamicScalar::U32(O::read_u32(bytes)),
+            Self::F32 => DynamicScalar::F32(O::read_f32(bytes)),
+            Self::F64 => DynamicScalar::F64(O::read_f64(bytes)),
+        }
+    }
+
     async fn read<O: ByteOrder>(&self, reader: &mut AsyncStreamReader) -> Result<DynamicScalar> {
+        Ok(self.decode::<O>(reader.read_exact(self.size()).await?))
+    }
+}
+
+impl ScalarType {
+    fn parse(&self, token: &str) -> Result<DynamicScalar> {
         match self {
-            Self::I8 => Ok(DynamicScalar::I8(reader.read_exact(1).await?[0] as i8)),
-            Self::U8 => Ok(DynamicScalar::U8(reader.read_exact(1).await?[0])),
-            Self::I16 => Ok(DynamicScalar::I16(O::read_i16(reader.read_exact(2).await?))),
-            Self::U16 => Ok(DynamicScalar::U16(O::read_u16(reader.read_exact(2).await?))),
-            Self::I32 => Ok(DynamicScalar::I32(O::read_i32(reader.read_exact(4).await?))),
-            Self::U32 => Ok(DynamicScalar::U32(O::read_u32(reader.read_exact(4).await?))),
-            Self::F32 => Ok(DynamicScalar::F32(O::read_f32(reader.read_exact(4).await?))),
-            Self::F64 => Ok(DynamicScalar::F64(O::read_f64(reader.read_exact(8).await?))),
+            Self::I8 => Ok(DynamicScalar::I8(token.parse()?)),
+            Self::U8 => Ok(DynamicScalar::U8(token.parse()?)),
+            Self::I16 => Ok(DynamicScalar::I16(token.parse()?)),
+            Self::U16 => Ok(DynamicScalar::U16(token.parse()?)),
+            Self::I32 => Ok(DynamicScalar::I32(token.parse()?)),
+            Self::U32 => Ok(DynamicScalar::U32(token.parse()?)),
+            Self::F32 => Ok(DynamicScalar::F32(token.parse()?)),
+            Self::F64 => Ok(DynamicScalar::F64(token.parse()?)),
         }
     }
 }
@@ -89,8 +135,12 @@ impl Property {
         match &self.ty {
             PropertyType::Scalar(ty) => Ok(DynamicProperty::Scalar(ty.read::<O>(reader).await?)),
             PropertyType::List(len_ty, ty) => {
-                let len = len_ty.read::<O>(reader).await?.as_usize().unwrap();
-                let mut list = Vec::with_capacity(len);
+                let len = len_ty.read::<O>(reader).await?;
+                if len.is_negative() {
+                    bail!("List length is negative");
+                }
+                let len = len.as_usize().context("List length is not an integer")?;
+                let mut list = Vec::new();
 
                 for _ in 0..len {
                     list.push(ty.read::<O>(reader).await?);
@@ -102,19 +152,87 @@ impl Property {
     }
 }
 
+impl Property {
+    fn parse<'a, I: Iterator<Item = &'a str>>(&self, tokens: &mut I) -> Result<DynamicProperty> {
+        let mut next = || tokens.next().context("Missing property value");
+        match &self.ty {
+            PropertyType::Scalar(ty) => Ok(DynamicProperty::Scalar(ty.parse(next()?)?)),
+            PropertyType::List(len_ty, ty) => {
+                let len = len_ty.parse(next()?)?;
+                if len.is_negative() {
+                    bail!("List length is negative");
+                }
+                let len = len.as_usize().context("List length is not an integer")?;
+                let mut list = Vec::new();
+
+                for _ in 0..len {
+                    list.push(ty.parse(next()?)?);
+                }
+
+                Ok(DynamicProperty::List(list))
+            }
+        }
+    }
+}
+
 struct Element {
     name: String,
     count: usize,
     properties: Vec<Property>,
 }
 
+impl Element {
+    /// Size of each row in a binary body, if every property is a fixed-width scalar.
+    fn row_size(&self) -> Option<usize> {
+        self.properties
+            .iter()
+            .map(|prop| match &prop.ty {
+                PropertyType::Scalar(ty) => Some(ty.size()),
+                PropertyType::List(..) => None,
+            })
+            .sum()
+    }
+
+    /// Byte offset and type of the properties named by `names` within each row, if the element
+    /// is the vertex element, has fixed-width rows and stores all three as floats.
+    fn float_fields(&self, names: &[String; 3]) -> Option<[(usize, &ScalarType); 3]> {
+        if self.name != "vertex" {
+            return None;
+        }
+        self.row_size()?;
+
+        let mut offset = 0;
+        let mut fields = [None; 3];
+        for prop in &self.properties {
+            let PropertyType::Scalar(ty) = &prop.ty else {
+                return None;
+            };
+            if let Some(axis) = names.iter().position(|name| *name == prop.name) {
+                if !matches!(ty, ScalarType::F32 | ScalarType::F64) {
+                    return None;
+                }
+                fields[axis] = Some((offset, ty));
+            }
+            offset += ty.size();
+        }
+        Some([fields[0]?, fields[1]?, fields[2]?])
+    }
+
+    /// Whether the element has a property named by any of `names`.
+    fn has_any(&self, names: &[String; 3]) -> bool {
+        self.properties.iter().any(|p| names.contains(&p.name))
+    }
+}
+
 trait PlyVisitor {
     fn visit_element(self, name: &str) -> Box<dyn ElementVisitor<Self>>;
 }
 
 trait ElementVisitor<P: PlyVisitor> {
     fn visit_property(&mut self, name: &str, property: DynamicProperty);
-    fn finish(self: Box<Self>) -> P;
+    /// Hand the row over to the parent, or fail if it was incomplete. Errors describe what was
+    /// wrong without saying which row, which the caller adds.
+    fn finish(self: Box<Self>) -> Result<P>;
 }
 
 async fn read_magic(reader: &mut AsyncStreamReader) -> Result<()> {
@@ -146,17 +264,19 @@ fn parse_element_line<'a, I: Iterator<Item = &'a str>>(tokens: &mut I) -> Result
     Ok((name.to_string(), num))
 }
 
+/// Scalar type named by `token`, accepting the standard PLY names, their sized aliases, C-style
+/// `_t` names and any capitalization.
 fn parse_scalar_type(token: &str) -> Result<ScalarType> {
-    match token {
-        "int8" | "char" => Ok(ScalarType::I8),
-        "uint8" | "uchar" => Ok(ScalarType::U8),
-        "int16" | "short" => Ok(ScalarType::I16),
-        "uint16" | "ushort" => Ok(ScalarType::U16),
-        "int32" | "int" => Ok(ScalarType::I32),
-        "uint32" | "uint" => Ok(ScalarType::U32),
+    match token.to_ascii_lowercase().as_str() {
+        "int8" | "char" | "schar" | "int8_t" => Ok(ScalarType::I8),
+        "uint8" | "uchar" | "uint8_t" => Ok(ScalarType::U8),
+        "int16" | "short" | "int16_t" => Ok(ScalarType::I16),
+        "uint16" | "ushort" | "uint16_t" => Ok(ScalarType::U16),
+        "int32" | "int" | "int32_t" => Ok(ScalarType::I32),
+        "uint32" | "uint" | "uint32_t" => Ok(ScalarType::U32),
         "float32" | "float" => Ok(ScalarType::F32),
         "float64" | "double" => Ok(ScalarType::F64),
-        _ => bail!("Unknown scalar type"),
+        _ => bail!("Unknown scalar type '{}'", token),
     }
 }
 
@@ -171,8 +291,33 @@ fn parse_property_type<'a, I: Iterator<Item = &'a str>>(tokens: &mut I) -> Resul
     }
 }
 
-fn parse_property<'a, I: Iterator<Item = &'a str>>(tokens: &mut I) -> Result<Property> {
-    let ty = parse_property_type(tokens)?;
+/// Parse the tokens of a property line after `property`. Anything after the name is ignored,
+/// and a scalar property with its name before its type is accepted too, with a warning for
+/// header line `line`.
+fn parse_property<'a, I: Iterator<Item = &'a str> + Clone>(
+    tokens: &mut I,
+    line: usize,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<Property> {
+    let mut swapped = tokens.clone();
+    let ty = match parse_property_type(tokens) {
+        Ok(ty) => ty,
+        Err(e) => match (swapped.next(), swapped.next().map(parse_scalar_type)) {
+            (Some(name), Some(Ok(ty))) => {
+                Diagnostic::warning(format!(
+                    "PLY property {} on line {} has its name before its type",
+                    name, line
+                ))
+                .at_line(line)
+                .report(diagnostics);
+                return Ok(Property {
+                    name: name.to_string(),
+                    ty: PropertyType::Scalar(ty),
+                });
+            }
+            _ => return Err(e),
+        },
+    };
     let name = tokens.next().context("Missing property name")?;
 
     Ok(Property {
@@ -185,179 +330,565 @@ trait Accept<T> {
     fn accept(&mut self, v: T);
 }
 
-struct VertexVisitor<V: PlyVisitor + Accept<Vec3>> {
-    x: Option<f32>,
-    y: Option<f32>,
-    z: Option<f32>,
+/// Names of the vertex properties holding the position and normal coordinates.
+struct VertexNames {
+    position: [String; 3],
+    normal: [String; 3],
+}
+
+/// A parsed vertex: its position and normal, if the file has one.
+type Vertex = (Vec3, Option<Vec3>);
+
+struct VertexVisitor<V: PlyVisitor + Accept<Vertex>> {
+    position: [Option<f32>; 3],
+    normal: [Option<f32>; 3],
+    names: Rc<VertexNames>,
     parent: V,
 }
 
-impl<V: PlyVisitor + Accept<Vec3>> VertexVisitor<V> {
-    fn new(parent: V) -> Self {
+impl<V: PlyVisitor + Accept<Vertex>> VertexVisitor<V> {
+    fn new(parent: V, names: Rc<VertexNames>) -> Self {
         Self {
-            x: None,
-            y: None,
-            z: None,
+            position: [None; 3],
+            normal: [None; 3],
+            names,
             parent,
         }
     }
 }
 
-impl<V: PlyVisitor + Accept<Vec3>> ElementVisitor<V> for VertexVisitor<V> {
+impl<V: PlyVisitor + Accept<Vertex>> ElementVisitor<V> for VertexVisitor<V> {
     fn visit_property(&mut self, name: &str, property: DynamicProperty) {
-        match property {
-            DynamicProperty::Scalar(s) => match name {
-                "x" => self.x = s.as_f32(),
-                "y" => self.y = s.as_f32(),
-                "z" => self.z = s.as_f32(),
-                _ => (),
-            },
-            DynamicProperty::List(_) => (),
+        let DynamicProperty::Scalar(s) = property else {
+            return;
+        };
+        if let Some(axis) = self.names.position.iter().position(|n| n == name) {
+            self.position[axis] = s.as_f32();
+        } else if let Some(axis) = self.names.normal.iter().position(|n| n == name) {
+            self.normal[axis] = s.as_f32();
         }
     }
 
-    fn finish(mut self: Box<Self>) -> V {
-        self.parent
-            .accept(vec3(self.x.unwrap(), self.y.unwrap(), self.z.unwrap()));
-        self.parent
+    fn finish(mut self: Box<Self>) -> Result<V> {
+        let [x, y, z] = self.position;
+        let x = x.context("missing x coordinate")?;
+        let y = y.context("missing y coordinate")?;
+        let z = z.context("missing z coordinate")?;
+        let normal = match self.normal {
+            [Some(x), Some(y), Some(z)] => Some(vec3(x, y, z)),
+            _ => None,
+        };
+        self.parent.accept((vec3(x, y, z), normal));
+        Ok(self.parent)
     }
 }
 
-struct FaceVisitor<V: PlyVisitor + Accept<[usize; 3]>> {
-    indices: Option<[usize; 3]>,
+/// A parsed triangle: its corners and material id, if any.
+type Face = ([usize; 3], Option<u32>);
+
+/// The corners of a face from its `vertex_indices` list. Each row has its own list length, so
+/// one element can mix triangles, quads and larger polygons.
+fn polygon(indices: &[DynamicScalar]) -> Result<Vec<usize>> {
+    if indices.len() < 3 {
+        bail!("has {} vertices, faces need at least 3", indices.len());
+    }
+    indices
+        .iter()
+        .map(|i| match i.as_usize() {
+            Some(index) if !i.is_negative() => Ok(index),
+            _ => Err(anyhow!(
+                "has a vertex index that is not a non-negative integer"
+            )),
+        })
+        .collect()
+}
+
+/// Visitor for a `face` element. Polygons are fan triangulated from their first corner, like
+/// OBJ faces, with every triangle taking the polygon's material.
+struct FaceVisitor<V: PlyVisitor + Accept<Face>> {
+    indices: Option<Result<Vec<usize>>>,
+    material: Option<u32>,
     parent: V,
 }
 
-impl<V: PlyVisitor + Accept<[usize; 3]>> FaceVisitor<V> {
+impl<V: PlyVisitor + Accept<Face>> FaceVisitor<V> {
     fn new(parent: V) -> Self {
         Self {
             indices: None,
+            material: None,
             parent,
         }
     }
 }
 
-impl<V: PlyVisitor + Accept<[usize; 3]>> ElementVisitor<V> for FaceVisitor<V> {
+impl<V: PlyVisitor + Accept<Face>> ElementVisitor<V> for FaceVisitor<V> {
     fn visit_property(&mut self, name: &str, property: DynamicProperty) {
         match property {
-            DynamicProperty::Scalar(_) => (),
-            DynamicProperty::List(v) => match name {
-                "vertex_indices" => {
-                    if v.len() != 3 {
-                        unimplemented!();
-                    }
-                    self.indices = Some([
-                        v[0].as_usize().unwrap(),
-                        v[1].as_usize().unwrap(),
-                        v[2].as_usize().unwrap(),
-                    ])
+            DynamicProperty::Scalar(s) => {
+                if name == "material_index" || name == "material_id" {
+                    self.material = s.as_usize().map(|m| m as u32);
                 }
-                _ => (),
-            },
+            }
+            DynamicProperty::List(v) => {
+                if name == "vertex_indices" {
+                    self.indices = Some(polygon(&v));
+                }
+            }
         }
     }
 
-    fn finish(mut self: Box<Self>) -> V {
-        self.parent.accept(self.indices.unwrap());
-        self.parent
+    fn finish(mut self: Box<Self>) -> Result<V> {
+        let indices = self.indices.context("missing vertex_indices")??;
+        for i in 1..indices.len() - 1 {
+            self.parent
+                .accept(([indices[0], indices[i], indices[i + 1]], self.material));
+        }
+        Ok(self.parent)
+    }
+}
+
+/// Visitor for a `tristrips` element, as written by older Stanford scans: each row has a
+/// `vertex_indices` list of triangle strips separated by -1.
+struct TristripVisitor<V: PlyVisitor + Accept<Face>> {
+    indices: Vec<usize>,
+    parent: V,
+}
+
+impl<V: PlyVisitor + Accept<Face>> TristripVisitor<V> {
+    fn new(parent: V) -> Self {
+        Self {
+            indices: Vec::new(),
+            parent,
+        }
+    }
+
+    /// Emit the triangles of the strip gathered so far and start a new one. Every other
+    /// triangle of a strip is wound the other way, so it is flipped back to match the first, and
+    /// the degenerate triangles used to join strips are dropped.
+    fn end_strip(&mut self) {
+        for (k, w) in self.indices.windows(3).enumerate() {
+            let tri = if k % 2 == 0 {
+                [w[0], w[1], w[2]]
+            } else {
+                [w[1], w[0], w[2]]
+            };
+            if tri[0] != tri[1] && tri[1] != tri[2] && tri[2] != tri[0] {
+                self.parent.accept((tri, None));
+            }
+        }
+        self.indices.clear();
+    }
+}
+
+impl<V: PlyVisitor + Accept<Face>> ElementVisitor<V> for TristripVisitor<V> {
+    fn visit_property(&mut self, name: &str, property: DynamicProperty) {
+        if let (DynamicProperty::List(v), "vertex_indices") = (property, name) {
+            for i in v {
+                match i.as_usize() {
+                    Some(index) if !i.is_negative() => self.indices.push(index),
+                    _ => self.end_strip(),
+                }
+            }
+        }
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<V> {
+        self.end_strip();
+        Ok(self.parent)
     }
 }
 
 struct AnyElementVisitor<V: PlyVisitor>(V);
 impl<V: PlyVisitor> ElementVisitor<V> for AnyElementVisitor<V> {
     fn visit_property(&mut self, _name: &str, _property: DynamicProperty) {}
-    fn finish(self: Box<Self>) -> V {
-        self.0
+    fn finish(self: Box<Self>) -> Result<V> {
+        Ok(self.0)
     }
 }
 
 struct MeshVisitor {
     mesh: InputMesh,
+    names: Rc<VertexNames>,
+    /// Whether every vertex so far came with a normal.
+    has_normals: bool,
 }
 
 impl MeshVisitor {
-    fn new() -> Self {
+    fn new(options: &LoadOptions) -> Self {
         Self {
             mesh: Default::default(),
+            names: Rc::new(VertexNames {
+                position: options.position_names.clone(),
+                normal: options.normal_names.clone(),
+            }),
+            has_normals: true,
         }
     }
 
-    fn finish(mut self) -> InputMesh {
-        self.mesh
-            .normals
-            .resize(self.mesh.vertices.len(), Vec3::ZERO);
-
-        for &[a, b, c] in &self.mesh.tris {
-            let v0 = self.mesh.vertices[a];
-            let v1 = self.mesh.vertices[b];
-            let v2 = self.mesh.vertices[c];
-
-            let n = (v2 - v0).cross(v1 - v0).normalize();
-
-            self.mesh.normals[a] += n;
-            self.mesh.normals[b] += n;
-            self.mesh.normals[c] += n;
+    /// Check the faces against the vertices, which may have come after them. Normals are kept
+    /// from the file if every vertex had one, flipped to point inward (see
+    /// [`face_normal`](crate::mesh::face_normal)), and computed from the faces otherwise.
+    fn finish(mut self) -> Result<InputMesh> {
+        let num_vertices = self.mesh.vertices.len();
+        if let Some((face, v)) = self
+            .mesh
+            .tris
+            .iter()
+            .enumerate()
+            .find_map(|(face, tri)| tri.iter().find(|v| **v >= num_vertices).map(|v| (face, v)))
+        {
+            bail!(
+                "Face {} uses vertex {}, but there are only {} vertices",
+                face,
+                v,
+                num_vertices
+            );
         }
 
-        for n in &mut self.mesh.normals {
-            *n = n.normalize();
+        if !self.mesh.material_ids.is_empty() {
+            self.mesh.material_ids.resize(self.mesh.tris.len(), 0);
         }
-
-        self.mesh
+        if self.has_normals && !self.mesh.vertices.is_empty() {
+            for n in &mut self.mesh.normals {
+                *n = -n.normalize_or_zero();
+            }
+        } else {
+            self.mesh.compute_normals();
+        }
+        Ok(self.mesh)
     }
 }
 
-impl<'a> PlyVisitor for MeshVisitor {
+impl PlyVisitor for MeshVisitor {
     fn visit_element(self, name: &str) -> Box<dyn ElementVisitor<Self>> {
         match name {
-            "vertex" => Box::new(VertexVisitor::new(self)),
+            "vertex" => {
+                let names = self.names.clone();
+                Box::new(VertexVisitor::new(self, names))
+            }
             "face" => Box::new(FaceVisitor::new(self)),
+            "tristrips" => Box::new(TristripVisitor::new(self)),
             _ => Box::new(AnyElementVisitor(self)),
         }
     }
 }
 
-impl Accept<Vec3> for MeshVisitor {
-    fn accept(&mut self, v: Vec3) {
+impl Accept<Vertex> for MeshVisitor {
+    fn accept(&mut self, (v, normal): Vertex) {
+        match normal {
+            Some(n) if self.has_normals => self.mesh.normals.push(n),
+            _ => self.has_normals = false,
+        }
         self.mesh.vertices.push(v)
     }
 }
 
-impl Accept<[usize; 3]> for MeshVisitor {
-    fn accept(&mut self, v: [usize; 3]) {
-        self.mesh.tris.push(v)
+impl Accept<Face> for MeshVisitor {
+    fn accept(&mut self, (tri, material): Face) {
+        // Faces without an id before the first one with an id get material 0
+        if let Some(material) = material {
+            self.mesh.material_ids.resize(self.mesh.tris.len(), 0);
+            self.mesh.material_ids.push(material);
+        }
+        self.mesh.tris.push(tri)
     }
 }
 
+/// Number of rows parsed between calls to the progress callback.
+const BATCH_ROWS: usize = 1 << 16;
+
+/// A partially parsed mesh, passed to the progress callback after every batch of rows.
+pub struct LoadProgress<'a> {
+    pub mesh: &'a InputMesh,
+    /// Vertex count declared in the header.
+    pub num_vertices: usize,
+    /// Face count declared in the header.
+    pub num_faces: usize,
+}
+
+fn declared_count(elements: &[Element], name: &str) -> usize {
+    elements
+        .iter()
+        .find(|e| e.name == name)
+        .map_or(0, |e| e.count)
+}
+
+/// Finish the visitor for `row` of `element`, naming the row in any error.
+fn finish_row<P: PlyVisitor>(
+    visitor: Box<dyn ElementVisitor<P>>,
+    element: &Element,
+    row: usize,
+) -> Result<P> {
+    visitor
+        .finish()
+        .map_err(|e| anyhow!("{} {} {}", element.name, row, e))
+}
+
+/// Number of leading rows of each element checked for obviously corrupt binary values.
+const SNIFF_ROWS: usize = 16;
+
+/// Whether a property read from the start of a binary body could plausibly be real data.
+/// Text misread as binary tends to produce absurd coordinates and out-of-range face indices.
+fn is_plausible(element: &str, property: &DynamicProperty, num_vertices: usize) -> bool {
+    match property {
+        DynamicProperty::Scalar(s) => s.as_f32().map_or(true, |v| v.is_finite() && v.abs() < 1e30),
+        DynamicProperty::List(list) => {
+            element != "face"
+                || list
+                    .iter()
+                    .all(|i| i.as_usize().map_or(true, |i| i < num_vertices))
+        }
+    }
+}
+
+/// Fail on a property from the first rows of a binary body that is unlikely to be real data.
+fn check_plausible(
+    element: &Element,
+    prop: &Property,
+    p: &DynamicProperty,
+    row: usize,
+    num_vertices: usize,
+) -> Result<()> {
+    if row < SNIFF_ROWS && !is_plausible(&element.name, p, num_vertices) {
+        bail!(
+            "Implausible value for {} property {} in row {}",
+            element.name,
+            prop.name,
+            row
+        );
+    }
+    Ok(())
+}
+
+/// Parse the rows of an element whose properties are all fixed-width scalars. The rows are
+/// read in large blocks and decoded in a synchronous loop, and vertex positions skip the
+/// visitors altogether once past the rows that are sniffed for text.
+async fn parse_fixed_rows<O: ByteOrder>(
+    reader: &mut AsyncStreamReader,
+    element: &Element,
+    stride: usize,
+    mut visitor: MeshVisitor,
+    num_vertices: usize,
+    num_faces: usize,
+    on_batch: &mut dyn FnMut(LoadProgress),
+) -> Result<MeshVisitor> {
+    // Positions skip the visitors only if the normals, when the file has them, can as well
+    let positions = element.float_fields(&visitor.names.position);
+    let normals = element.float_fields(&visitor.names.normal);
+    let fast_fields = match normals {
+        Some(normals) => positions.map(|p| (p, Some(normals))),
+        None if element.has_any(&visitor.names.normal) => None,
+        None => positions.map(|p| (p, None)),
+    };
+
+    let mut row = 0;
+    while row < element.count {
+        // The sniffed rows come in a block of their own, so that a text body is caught before
+        // reading far past the mark
+        let end = if row < SNIFF_ROWS {
+            SNIFF_ROWS
+        } else {
+            (row / BATCH_ROWS + 1) * BATCH_ROWS
+        }
+        .min(element.count);
+        let block = reader.read_exact((end - row) * stride).await?;
+
+        for (i, bytes) in block.chunks_exact(stride).enumerate() {
+            let row = row + i;
+            match fast_fields {
+                Some((positions, normals)) if row >= SNIFF_ROWS => {
+                    let decode =
+                        |fields: [(usize, &ScalarType); 3]| {
+                            Vec3::from_array(fields.map(|(offset, ty)| {
+                                ty.decode::<O>(&bytes[offset..]).as_f32().unwrap()
+                            }))
+                        };
+                    visitor.accept((decode(positions), normals.map(decode)));
+                }
+                _ => {
+                    let mut el_visitor = visitor.visit_element(element.name.as_str());
+                    let mut offset = 0;
+                    for prop in &element.properties {
+                        let PropertyType::Scalar(ty) = &prop.ty else {
+                            unreachable!();
+                        };
+                        let p = DynamicProperty::Scalar(ty.decode::<O>(&bytes[offset..]));
+                        offset += ty.size();
+                        check_plausible(element, prop, &p, row, num_vertices)?;
+                        el_visitor.visit_property(prop.name.as_str(), p);
+                    }
+                    visitor = finish_row(el_visitor, element, row)?;
+                }
+            }
+        }
+        row = end;
+
+        if row % BATCH_ROWS == 0 || row == element.count {
+            on_batch(LoadProgress {
+                mesh: &visitor.mesh,
+                num_vertices,
+                num_faces,
+            });
+        }
+    }
+    Ok(visitor)
+}
+
 async fn parse_binary<O: ByteOrder>(
     reader: &mut AsyncStreamReader,
-    elements: Vec<Element>,
+    elements: &[Element],
+    options: &LoadOptions,
+    on_batch: &mut dyn FnMut(LoadProgress),
 ) -> Result<InputMesh> {
-    let mut visitor = MeshVisitor::new();
+    let num_vertices = declared_count(elements, "vertex");
+    let num_faces = declared_count(elements, "face");
+
+    let mut visitor = MeshVisitor::new(options);
     for element in elements {
-        for _ in 0..element.count {
+        if let Some(stride) = element.row_size() {
+            visitor = parse_fixed_rows::<O>(
+                reader,
+                element,
+                stride,
+                visitor,
+                num_vertices,
+                num_faces,
+                on_batch,
+            )
+            .await?;
+            continue;
+        }
+
+        for row in 0..element.count {
             let mut el_visitor = visitor.visit_element(element.name.as_str());
             for prop in &element.properties {
                 let p = prop.read::<O>(reader).await?;
+                check_plausible(element, prop, &p, row, num_vertices)?;
+                el_visitor.visit_property(prop.name.as_str(), p);
+            }
+            visitor = finish_row(el_visitor, element, row)?;
+
+            if (row + 1) % BATCH_ROWS == 0 || row + 1 == element.count {
+                on_batch(LoadProgress {
+                    mesh: &visitor.mesh,
+                    num_vertices,
+                    num_faces,
+                });
+            }
+        }
+    }
+    visitor.finish()
+}
+
+async fn parse_ascii(
+    reader: &mut AsyncStreamReader,
+    elements: &[Element],
+    options: &LoadOptions,
+    on_batch: &mut dyn FnMut(LoadProgress),
+) -> Result<InputMesh> {
+    let num_vertices = declared_count(elements, "vertex");
+    let num_faces = declared_count(elements, "face");
+
+    let mut visitor = MeshVisitor::new(options);
+    for element in elements {
+        for row in 0..element.count {
+            let mut line = reader.read_line_utf8().await?;
+            while line.trim().is_empty() {
+                line = reader.read_line_utf8().await?;
+            }
+
+            let mut tokens = line.split_whitespace();
+            let mut el_visitor = visitor.visit_element(element.name.as_str());
+            for prop in &element.properties {
+                let p = prop.parse(&mut tokens)?;
                 el_visitor.visit_property(prop.name.as_str(), p);
             }
-            visitor = el_visitor.finish();
+            visitor = finish_row(el_visitor, element, row)?;
+
+            if (row + 1) % BATCH_ROWS == 0 || row + 1 == element.count {
+                on_batch(LoadProgress {
+                    mesh: &visitor.mesh,
+                    num_vertices,
+                    num_faces,
+                });
+            }
         }
     }
-    Ok(visitor.finish())
+    visitor.finish()
+}
+
+/// Parse a body declared as binary, falling back to ASCII if it turns out to be text. Some
+/// exporters write an ASCII body under a binary format line.
+async fn parse_binary_or_ascii<O: ByteOrder>(
+    reader: &mut AsyncStreamReader,
+    elements: &[Element],
+    options: &LoadOptions,
+    on_batch: &mut dyn FnMut(LoadProgress),
+) -> Result<InputMesh> {
+    reader.mark();
+    let binary = parse_binary::<O>(reader, elements, options, on_batch).await;
+    let mesh = match binary {
+        Ok(mesh) => Ok(mesh),
+        Err(binary_err) if reader.reset_to_mark().is_ok() => {
+            match parse_ascii(reader, elements, options, on_batch).await {
+                Ok(mut mesh) => {
+                    Diagnostic::warning(format!(
+                        "PLY body declared binary but parsed as ASCII ({})",
+                        binary_err
+                    ))
+                    .report(&mut mesh.diagnostics);
+                    Ok(mesh)
+                }
+                Err(_) => Err(binary_err),
+            }
+        }
+        Err(binary_err) => Err(binary_err),
+    };
+    reader.clear_mark();
+    mesh
 }
 
-pub async fn load_ply(reader: &mut AsyncStreamReader) -> Result<InputMesh> {
+/// Load a PLY mesh, calling `on_batch` with the mesh parsed so far after every batch of rows.
+/// If the body has to be re-parsed as ASCII, the mesh passed to `on_batch` starts over.
+///
+/// If `options.strict`, anything but whitespace after the last declared element is an error, as
+/// left by element counts that are too small or by data appended to the file. Otherwise it is
+/// ignored. Vertex positions are read from the properties named in `options.position_names`,
+/// which the vertex element must have, and normals from those in `options.normal_names`, if it
+/// has them.
+///
+/// Elements may come in any order. Face indices are only checked against the vertices once the
+/// whole body is read, so files listing their faces before their vertices load as usual, though
+/// `on_batch` then sees faces whose vertices have not been parsed yet. Faces using a vertex that
+/// never turns up are an error.
+pub async fn load_ply_progressive(
+    reader: &mut AsyncStreamReader,
+    options: &LoadOptions,
+    on_batch: &mut dyn FnMut(LoadProgress),
+) -> Result<InputMesh> {
     read_magic(reader).await?;
     let format = read_format(reader).await?;
 
     let mut elements = Vec::new();
     let mut parsing_element = None;
+    let mut texture_files = Vec::new();
+    let mut diagnostics = Vec::new();
 
+    // The magic and format lines come first
+    let mut line_number = 2;
     while let Ok(line) = reader.read_line_utf8().await {
+        line_number += 1;
         let mut tokens = line.split_whitespace();
         match tokens.next() {
-            Some("comment") | None => (),
+            Some("comment") => {
+                if tokens.next() == Some("TextureFile") {
+                    let name = tokens.collect::<Vec<_>>().join(" ");
+                    if !name.is_empty() {
+                        texture_files.push(name);
+                    }
+                }
+            }
+            None => (),
             Some("element") => {
                 if let Some(el) = parsing_element.take() {
                     elements.push(el);
@@ -372,7 +903,10 @@ pub async fn load_ply(reader: &mut AsyncStreamReader) -> Result<InputMesh> {
             }
             Some("property") => {
                 if let Some(el) = parsing_element.as_mut() {
-                    el.properties.push(parse_property(&mut tokens)?);
+                    el.properties.push(
+                        parse_property(&mut tokens, line_number, &mut diagnostics)
+                            .map_err(|e| anyhow!("{} in property line '{}'", e, line))?,
+                    );
                 } else {
                     bail!("Unexpected property line");
                 }
@@ -383,13 +917,208 @@ pub async fn load_ply(reader: &mut AsyncStreamReader) -> Result<InputMesh> {
                 }
                 break;
             }
-            _ => log::warn!("Unexpected PLY line"),
+            _ => Diagnostic::warning(format!("Unexpected PLY header line {}", line_number))
+                .at_line(line_number)
+                .report(&mut diagnostics),
         }
     }
 
-    match format {
-        Format::Ascii => unimplemented!(),
-        Format::BigEndian => parse_binary::<BigEndian>(reader, elements).await,
-        Format::LittleEndian => parse_binary::<LittleEndian>(reader, elements).await,
+    if let Some(vertex) = elements.iter().find(|e| e.name == "vertex") {
+        for name in &options.position_names {
+            if !vertex.properties.iter().any(|p| p.name == *name) {
+                bail!("vertex element has no {} property", name);
+            }
+        }
+    }
+
+    let mut mesh = match format {
+        Format::Ascii => parse_ascii(reader, &elements, options, on_batch).await?,
+        Format::BigEndian => {
+            parse_binary_or_ascii::<BigEndian>(reader, &elements, options, on_batch).await?
+        }
+        Format::LittleEndian => {
+            parse_binary_or_ascii::<LittleEndian>(reader, &elements, options, on_batch).await?
+        }
+    };
+    if options.strict && reader.has_trailing_data().await {
+        bail!("Unexpected data after the last PLY element");
+    }
+    mesh.texture_files = texture_files;
+    diagnostics.append(&mut mesh.diagnostics);
+    mesh.diagnostics = diagnostics;
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str, options: &LoadOptions) -> Result<InputMesh> {
+        parse_bytes(text.as_bytes().to_vec(), options)
+    }
+
+    fn parse_bytes(data: Vec<u8>, options: &LoadOptions) -> Result<InputMesh> {
+        let mut reader = AsyncStreamReader::from_bytes(data);
+        futures::executor::block_on(load_ply_progressive(&mut reader, options, &mut |_| ()))
+    }
+
+    /// The error from parsing `text`, which must fail.
+    fn parse_error(text: &str, options: &LoadOptions) -> String {
+        match parse(text, options) {
+            Ok(_) => panic!("parsed successfully"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    /// ASCII PLY of a row of six vertices and the element header and rows in `rest`.
+    fn with_vertices(rest: &str) -> String {
+        let (header, rows) = rest.split_once("---\n").unwrap();
+        format!(
+            "ply\nformat ascii 1.0\nelement vertex 6\nproperty float x\nproperty float y\n\
+             property float z\n{}end_header\n0 0 0\n1 0 0\n0 1 0\n1 1 0\n0 2 0\n1 2 0\n{}",
+            header, rows
+        )
+    }
+
+    #[test]
+    fn vertex_element_without_the_position_names_is_rejected() {
+        let text = "ply\nformat ascii 1.0\nelement vertex 1\nproperty float px\n\
+                    property float py\nproperty float pz\nend_header\n1 2 3\n";
+        assert_eq!(
+            parse_error(text, &LoadOptions::default()),
+            "vertex element has no x property"
+        );
+
+        let options = LoadOptions {
+            position_names: ["px", "py", "pz"].map(String::from),
+            ..Default::default()
+        };
+        let mesh = parse(text, &options).unwrap();
+        assert_eq!(mesh.vertices, [vec3(1.0, 2.0, 3.0)]);
+
+        // Only one of the names missing still names it
+        let options = LoadOptions {
+            position_names: ["px", "py", "w"].map(String::from),
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_error(text, &options),
+            "vertex element has no w property"
+        );
+    }
+
+    #[test]
+    fn incomplete_rows_are_errors() {
+        let vertex = "ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\n\
+                      property float y\n";
+        let options = LoadOptions::default();
+        assert_eq!(
+            parse_error(&format!("{}end_header\n0 0\n", vertex), &options),
+            "vertex element has no z property"
+        );
+        // A z that isn't a scalar is missing from every row
+        let text = format!(
+            "{}property list uchar float z\nend_header\n0 0 1 0\n",
+            vertex
+        );
+        assert_eq!(
+            parse_error(&text, &options),
+            "vertex 0 missing z coordinate"
+        );
+
+        let text = with_vertices("element face 1\nproperty int material_index\n---\n3\n");
+        assert_eq!(
+            parse_error(&text, &options),
+            "face 0 missing vertex_indices"
+        );
+
+        let text =
+            with_vertices("element face 1\nproperty list char int vertex_indices\n---\n-3 0 1 2\n");
+        assert!(parse_error(&text, &options).contains("List length is negative"));
+
+        // The same in a binary body
+        let mut data = b"ply\nformat binary_little_endian 1.0\nelement vertex 1\n\
+                         property float x\nproperty float y\nproperty float z\n\
+                         element face 1\nproperty list char int vertex_indices\nend_header\n"
+            .to_vec();
+        data.extend([0.0f32; 3].iter().flat_map(|v| v.to_le_bytes()));
+        data.push(-3i8 as u8);
+        match parse_bytes(data, &LoadOptions::default()) {
+            Ok(_) => panic!("parsed successfully"),
+            Err(e) => assert!(e.to_string().contains("List length is negative"), "{}", e),
+        }
+    }
+
+    #[test]
+    fn normals_are_read_under_their_names_and_flipped_inward() {
+        // Past the sniffed rows, binary vertices skip the visitors, so check both ways
+        let count = SNIFF_ROWS + 4;
+        let mut data = format!(
+            "ply\nformat binary_little_endian 1.0\nelement vertex {}\nproperty float x\n\
+             property float y\nproperty float z\nproperty float normal_x\n\
+             property float normal_y\nproperty float normal_z\nend_header\n",
+            count
+        )
+        .into_bytes();
+        for i in 0..count {
+            for v in [i as f32, 0.0, 0.0, 0.0, 0.0, 2.0] {
+                data.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        let options = LoadOptions {
+            normal_names: ["normal_x", "normal_y", "normal_z"].map(String::from),
+            ..Default::default()
+        };
+        let mesh = parse_bytes(data.clone(), &options).unwrap();
+        assert_eq!(mesh.vertices.len(), count);
+        assert_eq!(mesh.normals, vec![vec3(0.0, 0.0, -1.0); count]);
+
+        // Under the default names, the normals are computed from the faces instead
+        let mesh = parse_bytes(data, &LoadOptions::default()).unwrap();
+        assert_eq!(mesh.normals, vec![Vec3::ZERO; count]);
+    }
+
+    #[test]
+    fn faces_may_come_before_vertices() {
+        let faces_first = |faces: &str| {
+            format!(
+                "ply\nformat ascii 1.0\nelement face 2\nproperty list uchar int vertex_indices\n\
+                 element vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+                 end_header\n{}0 0 0\n1 0 0\n1 1 0\n0 1 0\n",
+                faces
+            )
+        };
+        let mesh = parse(&faces_first("3 0 1 2\n3 0 2 3\n"), &LoadOptions::default()).unwrap();
+        assert_eq!(mesh.tris, [[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(mesh.vertices.len(), 4);
+        assert!(mesh.normals.iter().all(|n| *n == vec3(0.0, 0.0, -1.0)));
+
+        // Indices are still checked, once the vertices are known
+        assert_eq!(
+            parse_error(&faces_first("3 0 1 2\n3 0 2 4\n"), &LoadOptions::default()),
+            "Face 1 uses vertex 4, but there are only 4 vertices"
+        );
+    }
+
+    #[test]
+    fn tristrips_alternate_winding_and_restart_at_minus_one() {
+        let text = with_vertices(
+            "element tristrips 1\nproperty list int int vertex_indices\n---\n8 0 1 2 3 -1 2 3 4\n",
+        );
+        let mesh = parse(&text, &LoadOptions::default()).unwrap();
+        // The second triangle of the first strip is flipped back to the first one's winding,
+        // and the strip after the -1 starts afresh
+        assert_eq!(mesh.tris, [[0, 1, 2], [2, 1, 3], [2, 3, 4]]);
+    }
+
+    #[test]
+    fn tristrips_drop_degenerate_joins() {
+        let text = with_vertices(
+            "element tristrips 1\nproperty list int int vertex_indices\n---\n\
+             10 0 1 2 3 3 2 2 3 4 5\n",
+        );
+        let mesh = parse(&text, &LoadOptions::default()).unwrap();
+        // Both strips keep the winding they would have on their own
+        assert_eq!(mesh.tris, [[0, 1, 2], [2, 1, 3], [2, 3, 4], [4, 3, 5]]);
     }
 }
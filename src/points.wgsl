@@ -0,0 +1,76 @@
+struct Uniforms {
+    view_transform: mat4x4<f32>,
+    model_transform: mat4x4<f32>,
+    // x: 0 to output as is, 1 to encode to sRGB, 2 to decode from sRGB
+    // zw: half-size of a vertex point in normalized device coordinates
+    output: vec4<f32>,
+}
+
+struct MeshUniforms {
+    transform: mat4x4<f32>,
+    // Inverse transpose of the transform, for carrying normals under non-uniform scaling
+    normal_transform: mat4x4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+};
+
+@group(0)
+@binding(0)
+var<uniform> uniforms: Uniforms;
+
+@group(1)
+@binding(0)
+var<uniform> mesh: MeshUniforms;
+
+// Each point is a screen-aligned quad of two triangles, since point primitives are only ever a
+// single pixel wide
+@vertex
+fn vs_main(
+    @builtin(vertex_index) index: u32,
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 1.0),
+        vec2<f32>(-1.0, -1.0),
+    );
+
+    var result: VertexOutput;
+    var model_transform = uniforms.model_transform * mesh.transform;
+    var center = uniforms.view_transform * model_transform * vec4<f32>(position, 1.0);
+    var offset = corners[index] * uniforms.output.zw * center.w;
+    // Pull the point slightly towards the camera so it isn't hidden by its own faces
+    result.position = vec4<f32>(center.xy + offset, center.z - 0.002 * center.w, center.w);
+    result.color = color;
+
+    return result;
+}
+
+fn linear_to_srgb(c: vec3<f32>) -> vec3<f32> {
+    var x = clamp(c, vec3<f32>(0.0), vec3<f32>(1.0));
+    return select(1.055 * pow(x, vec3<f32>(1.0 / 2.4)) - 0.055, 12.92 * x, x <= vec3<f32>(0.0031308));
+}
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    var x = clamp(c, vec3<f32>(0.0), vec3<f32>(1.0));
+    return select(pow((x + 0.055) / 1.055, vec3<f32>(2.4)), x / 12.92, x <= vec3<f32>(0.04045));
+}
+
+@fragment
+fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    // Darken the vertex color so points stand out against the surface shaded with it
+    var c = 0.35 * vertex.color;
+    if (uniforms.output.x == 1.0) {
+        c = linear_to_srgb(c);
+    } else if (uniforms.output.x == 2.0) {
+        c = srgb_to_linear(c);
+    }
+    return vec4<f32>(c, 1.0);
+}
@@ -0,0 +1,168 @@
+use glam::Vec3;
+use rand::{seq::SliceRandom, Rng};
+
+use crate::{hierarchy::HierarchyLevel, mesh::ProcessMesh, rng};
+
+/// Point closest to both `p0` and `p1` while lying in both of their tangent planes, as far as
+/// that is possible for nearly parallel planes.
+fn middle_point(p0: Vec3, n0: Vec3, p1: Vec3, n1: Vec3) -> Vec3 {
+    let n0p0 = n0.dot(p0);
+    let n0p1 = n0.dot(p1);
+    let n1p0 = n1.dot(p0);
+    let n1p1 = n1.dot(p1);
+    let n0n1 = n0.dot(n1);
+    let denom = 1.0 / (1.0 - n0n1 * n0n1 + 1e-4);
+    let lambda0 = 2.0 * (n0p1 - n0p0 - n0n1 * (n1p0 - n1p1)) * denom;
+    let lambda1 = 2.0 * (n1p0 - n1p1 - n0n1 * (n0p1 - n0p0)) * denom;
+
+    0.5 * (p0 + p1) - 0.25 * (n0 * lambda0 + n1 * lambda1)
+}
+
+/// Lattice point of the grid through `o` spanned by `q` and `n × q` that is nearest to `p`, or
+/// with `floor` the one at the lower corner of the cell containing `p`.
+pub(crate) fn lattice_point(o: Vec3, q: Vec3, n: Vec3, p: Vec3, scale: f32, floor: bool) -> Vec3 {
+    let t = n.cross(q);
+    let d = p - o;
+    let (a, b) = (q.dot(d) / scale, t.dot(d) / scale);
+    let (a, b) = if floor {
+        (a.floor(), b.floor())
+    } else {
+        (a.round(), b.round())
+    };
+    o + q * a * scale + t * b * scale
+}
+
+/// Representatives of the lattices of vertex 0 and vertex 1 that lie closest together, near the
+/// middle of the edge between them.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn position_compat(
+    v0: Vec3,
+    n0: Vec3,
+    q0: Vec3,
+    o0: Vec3,
+    v1: Vec3,
+    n1: Vec3,
+    q1: Vec3,
+    o1: Vec3,
+    scale: f32,
+) -> (Vec3, Vec3) {
+    let t0 = n0.cross(q0);
+    let t1 = n1.cross(q1);
+    let middle = middle_point(v0, n0, v1, n1);
+    let base0 = lattice_point(o0, q0, n0, middle, scale, true);
+    let base1 = lattice_point(o1, q1, n1, middle, scale, true);
+
+    let mut best = (f32::INFINITY, base0, base1);
+    for i in 0..4 {
+        let c0 = base0 + (q0 * (i & 1) as f32 + t0 * ((i & 2) >> 1) as f32) * scale;
+        for j in 0..4 {
+            let c1 = base1 + (q1 * (j & 1) as f32 + t1 * ((j & 2) >> 1) as f32) * scale;
+            let cost = c0.distance_squared(c1);
+            if cost < best.0 {
+                best = (cost, c0, c1);
+            }
+        }
+    }
+    (best.1, best.2)
+}
+
+/// Run a single Gauss-Seidel sweep of the position field over every vertex of `mesh`, visiting
+/// them in random order. Each `p_field` value is an origin of a square lattice with spacing
+/// `scale`, aligned with the orientation field and kept close to its vertex.
+pub fn extrinsic_position_smooth<R: Rng>(
+    mesh: &ProcessMesh,
+    o_field: &[Vec3],
+    p_field: &mut [Vec3],
+    scale: f32,
+    rng: &mut R,
+) {
+    let mut indices = (0..mesh.vertices.len()).collect::<Vec<_>>();
+    indices.shuffle(rng);
+
+    for i in indices {
+        let v_i = mesh.vertices[i];
+        let n_i = mesh.normals[i];
+        let q_i = o_field[i];
+
+        let mut sum = p_field[i];
+        let mut weight_sum = 0.0;
+        for (j, _) in &mesh.adjacency_face[i] {
+            let (compat_i, compat_j) = position_compat(
+                v_i,
+                n_i,
+                q_i,
+                sum,
+                mesh.vertices[*j],
+                mesh.normals[*j],
+                o_field[*j],
+                p_field[*j],
+                scale,
+            );
+            sum = compat_i * weight_sum + compat_j;
+            weight_sum += 1.0;
+            sum /= weight_sum;
+        }
+
+        sum -= n_i * n_i.dot(sum - v_i);
+        p_field[i] = lattice_point(sum, q_i, n_i, v_i, scale, false);
+    }
+}
+
+/// Smooth a position field coarse-to-fine over `hierarchy`, aligned with the finest-level
+/// orientation field `o_field`, running `iterations` sweeps per level.
+///
+/// The orientation field is carried down to the coarser levels through `up_mapping`, and each
+/// level starts from the positions of its parents, or the vertices themselves on the coarsest.
+pub fn hierarchical_position_smoothing(
+    hierarchy: &[HierarchyLevel],
+    o_field: &[Vec3],
+    scale: f32,
+    iterations: usize,
+) -> Vec<Vec3> {
+    let o_fields = restrict_field(hierarchy, o_field);
+
+    let mut rng = rng::seeded_rng(0);
+    let mut p_field = hierarchy[0].mesh.vertices.clone();
+    for (level, mesh) in hierarchy.iter().map(|l| &l.mesh).enumerate() {
+        if level > 0 {
+            let up_mapping = &hierarchy[level].up_mapping;
+            p_field = (0..mesh.vertices.len())
+                .map(|i| {
+                    let p = p_field[up_mapping[i]];
+                    let n = mesh.normals[i];
+                    p - n * n.dot(p - mesh.vertices[i])
+                })
+                .collect();
+        }
+
+        for _ in 0..iterations {
+            extrinsic_position_smooth(mesh, &o_fields[level], &mut p_field, scale, &mut rng);
+        }
+    }
+    p_field
+}
+
+/// Copy a finest-level orientation field to every level of `hierarchy`, taking any child's
+/// direction for each coarse vertex and projecting it into the coarse tangent plane.
+fn restrict_field(hierarchy: &[HierarchyLevel], o_field: &[Vec3]) -> Vec<Vec<Vec3>> {
+    let mut fields = vec![o_field.to_vec()];
+    for level in (1..hierarchy.len()).rev() {
+        let coarse = &hierarchy[level - 1].mesh;
+        let fine_field = fields.last().unwrap();
+        let mut field = vec![Vec3::ZERO; coarse.vertices.len()];
+        for (i, parent) in hierarchy[level].up_mapping.iter().enumerate() {
+            field[*parent] = fine_field[i];
+        }
+        for (o, n) in field.iter_mut().zip(&coarse.normals) {
+            let projected = (*o - *n * o.dot(*n)).normalize_or_zero();
+            *o = if projected == Vec3::ZERO {
+                n.any_orthonormal_vector()
+            } else {
+                projected
+            };
+        }
+        fields.push(field);
+    }
+    fields.reverse();
+    fields
+}
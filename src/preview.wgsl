@@ -1,37 +1,77 @@
 struct Uniforms {
     view_transform: mat4x4<f32>,
     model_transform: mat4x4<f32>,
+    // x: 0 to output as is, 1 to encode to sRGB, 2 to decode from sRGB
+    // y: opacity for fs_transparent
+    // zw: half-size of a vertex point in normalized device coordinates
+    output: vec4<f32>,
+    normals: vec4<f32>,
+    lines: vec4<f32>,
+    // xyz: position of the camera, in the same space as world_pos
+    eye: vec4<f32>,
+}
+
+struct MeshUniforms {
+    transform: mat4x4<f32>,
+    // Inverse transpose of the transform, for carrying normals under non-uniform scaling
+    normal_transform: mat4x4<f32>,
 }
 
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
     @location(0) world_pos: vec3<f32>,
     @location(1) normal: vec3<f32>,
+    @location(2) color: vec3<f32>,
 };
 
 @group(0)
 @binding(0)
 var<uniform> uniforms: Uniforms;
 
+@group(1)
+@binding(0)
+var<uniform> mesh: MeshUniforms;
+
 @vertex
 fn vs_main(
     @location(0) position: vec3<f32>,
     @location(1) normal: vec3<f32>,
+    @location(2) color: vec3<f32>,
 ) -> VertexOutput {
     var result: VertexOutput;
-    result.position = uniforms.view_transform * uniforms.model_transform * vec4<f32>(position, 1.0);
-    result.world_pos = position;
-    result.normal = (uniforms.model_transform * vec4<f32>(normal, 0.0)).xyz;
+    var model_transform = uniforms.model_transform * mesh.transform;
+    result.position = uniforms.view_transform * model_transform * vec4<f32>(position, 1.0);
+    result.world_pos = (model_transform * vec4<f32>(position, 1.0)).xyz;
+    result.normal = normalize((uniforms.model_transform * mesh.normal_transform * vec4<f32>(normal, 0.0)).xyz);
+    result.color = color;
 
     return result;
 }
 
-@fragment
-fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
-    var cam = normalize(vertex.world_pos - vec3<f32>(0.0, 150.0, 0.0));
+fn linear_to_srgb(c: vec3<f32>) -> vec3<f32> {
+    var x = clamp(c, vec3<f32>(0.0), vec3<f32>(1.0));
+    return select(1.055 * pow(x, vec3<f32>(1.0 / 2.4)) - 0.055, 12.92 * x, x <= vec3<f32>(0.0031308));
+}
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    var x = clamp(c, vec3<f32>(0.0), vec3<f32>(1.0));
+    return select(pow((x + 0.055) / 1.055, vec3<f32>(2.4)), x / 12.92, x <= vec3<f32>(0.04045));
+}
+
+fn output_color(c: vec3<f32>) -> vec3<f32> {
+    if (uniforms.output.x == 1.0) {
+        return linear_to_srgb(c);
+    } else if (uniforms.output.x == 2.0) {
+        return srgb_to_linear(c);
+    }
+    return c;
+}
+
+fn shade(vertex: VertexOutput) -> vec3<f32> {
+    var cam = normalize(vertex.world_pos - uniforms.eye.xyz);
 
     var a = vec3<f32>(0.1, 0.1, 0.1);
-    var d = vec3<f32>(0.5, 0.5, 1.0);
+    var d = vertex.color;
     var s = vec3<f32>(0.3, 0.3, 0.3);
     var p = 8.0;
 
@@ -47,5 +87,21 @@ fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
     var phong1 = d * ndotl1 + s * pow(rdotl1, p);
     var phong2 = d * ndotl2 + s * pow(rdotl2, p);
 
-    return vec4<f32>(d * (a + phong1 + phong2), 1.0);
+    return d * (a + phong1 + phong2);
+}
+
+@fragment
+fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(output_color(shade(vertex)), 1.0);
+}
+
+@fragment
+fn fs_xray(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(output_color(shade(vertex)), 0.25);
+}
+
+@fragment
+fn fs_transparent(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    var alpha = uniforms.output.y;
+    return vec4<f32>(output_color(shade(vertex)) * alpha, alpha);
 }
@@ -0,0 +1,306 @@
+use std::fmt::Write;
+
+use glam::Vec3;
+use wasm_bindgen::prelude::*;
+
+use crate::{hierarchy::HierarchyLevel, mesh::InputMesh, position::position_compat};
+
+/// A quad-dominant mesh extracted from orientation and position fields. Faces the extraction
+/// couldn't close into quads are kept as triangles.
+#[derive(Clone, Default)]
+#[wasm_bindgen]
+pub struct QuadMesh {
+    pub(crate) vertices: Vec<Vec3>,
+    pub(crate) normals: Vec<Vec3>,
+    pub(crate) quads: Vec<[usize; 4]>,
+    pub(crate) tris: Vec<[usize; 3]>,
+}
+
+#[wasm_bindgen]
+impl QuadMesh {
+    #[wasm_bindgen(getter)]
+    pub fn num_quads(&self) -> usize {
+        self.quads.len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn num_triangles(&self) -> usize {
+        self.tris.len()
+    }
+
+    /// Split every quad along its shorter diagonal, for display or further processing as a
+    /// triangle mesh.
+    pub fn triangulate(&self) -> InputMesh {
+        let mut tris = self.tris.clone();
+        for &[a, b, c, d] in &self.quads {
+            let ac = self.vertices[a].distance_squared(self.vertices[c]);
+            let bd = self.vertices[b].distance_squared(self.vertices[d]);
+            if ac <= bd {
+                tris.extend([[a, b, c], [a, c, d]]);
+            } else {
+                tris.extend([[a, b, d], [b, c, d]]);
+            }
+        }
+        InputMesh::from_triangles(self.vertices.clone(), tris)
+    }
+
+    /// Write the mesh as Wavefront OBJ text, keeping quads as quads.
+    pub fn to_obj(&self) -> String {
+        let faces = self.quads.len() + self.tris.len();
+        let mut obj = String::with_capacity(40 * (self.vertices.len() + faces));
+        for v in &self.vertices {
+            writeln!(obj, "v {} {} {}", v.x, v.y, v.z).unwrap();
+        }
+        for q in &self.quads {
+            let [a, b, c, d] = q.map(|v| v + 1);
+            writeln!(obj, "f {a} {b} {c} {d}").unwrap();
+        }
+        for t in &self.tris {
+            let [a, b, c] = t.map(|v| v + 1);
+            writeln!(obj, "f {a} {b} {c}").unwrap();
+        }
+        obj
+    }
+}
+
+fn find(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+/// Extract a quad-dominant mesh from the finest level of `hierarchy`.
+///
+/// Every vertex is snapped to its nearest point of the lattice described by `o_field` and
+/// `p_field` with spacing `scale`. Vertices on neighboring edges that snap to the same lattice
+/// point are merged, and lattice points one step apart along the field become edges. Faces are
+/// then traced around the resulting graph, keeping those with three or four sides.
+///
+/// `scale` should be larger than the edges of the input mesh, since an edge spanning more than
+/// one lattice step is not connected.
+pub fn extract_quads(
+    hierarchy: &[HierarchyLevel],
+    o_field: &[Vec3],
+    p_field: &[Vec3],
+    scale: f32,
+) -> QuadMesh {
+    let mesh = &hierarchy[hierarchy.len() - 1].mesh;
+    let n = mesh.vertices.len();
+
+    // Classify every edge by the lattice offset between its endpoints
+    let mut parent = (0..n).collect::<Vec<_>>();
+    let mut lattice_edges = Vec::new();
+    for (i, adjacency) in mesh.adjacency_face.iter().enumerate() {
+        for (j, _) in adjacency {
+            let (c_i, c_j) = position_compat(
+                mesh.vertices[i],
+                mesh.normals[i],
+                o_field[i],
+                p_field[i],
+                mesh.vertices[*j],
+                mesh.normals[*j],
+                o_field[*j],
+                p_field[*j],
+                scale,
+            );
+            // The two lattice representatives meet, so the offset between the lattice points
+            // of the vertices is the sum of their offsets to the meeting point
+            let d = (c_i - p_field[i]) + (p_field[*j] - c_j);
+            let t_i = mesh.normals[i].cross(o_field[i]);
+            let steps =
+                (o_field[i].dot(d) / scale).round().abs() + (t_i.dot(d) / scale).round().abs();
+
+            if steps == 0.0 {
+                let (a, b) = (find(&mut parent, i), find(&mut parent, *j));
+                parent[a.max(b)] = a.min(b);
+            } else if steps == 1.0 {
+                lattice_edges.push((i, *j));
+            }
+        }
+    }
+
+    // One output vertex per merged cluster
+    let mut cluster = vec![usize::MAX; n];
+    let mut vertices = Vec::new();
+    let mut normals = Vec::new();
+    let mut directions = Vec::new();
+    let mut counts = Vec::new();
+    for i in 0..n {
+        let root = find(&mut parent, i);
+        if cluster[root] == usize::MAX {
+            cluster[root] = vertices.len();
+            vertices.push(Vec3::ZERO);
+            normals.push(Vec3::ZERO);
+            directions.push(o_field[i]);
+            counts.push(0.0);
+        }
+        let c = cluster[root];
+        cluster[i] = c;
+        vertices[c] += p_field[i];
+        normals[c] += mesh.normals[i];
+        counts[c] += 1.0;
+    }
+    for ((v, n), count) in vertices.iter_mut().zip(&mut normals).zip(&counts) {
+        *v /= *count;
+        *n = n.normalize_or_zero();
+    }
+
+    let mut neighbors = vec![Vec::new(); vertices.len()];
+    for (i, j) in lattice_edges {
+        let (a, b) = (cluster[i], cluster[j]);
+        if a != b {
+            neighbors[a].push(b);
+            neighbors[b].push(a);
+        }
+    }
+
+    // Sort each neighborhood by angle around the normal so faces can be traced
+    for (c, adjacent) in neighbors.iter_mut().enumerate() {
+        adjacent.sort_unstable();
+        adjacent.dedup();
+
+        let q = directions[c];
+        let t = normals[c].cross(q);
+        let angle = |v: &usize| {
+            let d = vertices[*v] - vertices[c];
+            t.dot(d).atan2(q.dot(d))
+        };
+        adjacent.sort_by(|a, b| angle(a).total_cmp(&angle(b)));
+    }
+
+    let mut quad_mesh = QuadMesh {
+        vertices,
+        normals,
+        ..Default::default()
+    };
+
+    let mut visited = neighbors
+        .iter()
+        .map(|a| vec![false; a.len()])
+        .collect::<Vec<_>>();
+    for start in 0..neighbors.len() {
+        for start_k in 0..neighbors[start].len() {
+            if visited[start][start_k] {
+                continue;
+            }
+
+            // Follow the face to the left of each half-edge, turning as sharply as possible
+            let mut face = Vec::new();
+            let (mut u, mut k) = (start, start_k);
+            while !visited[u][k] && face.len() <= 4 {
+                visited[u][k] = true;
+                face.push(u);
+
+                let v = neighbors[u][k];
+                let back = neighbors[v].iter().position(|w| *w == u).unwrap();
+                k = (back + neighbors[v].len() - 1) % neighbors[v].len();
+                u = v;
+            }
+            if u != start || k != start_k {
+                continue;
+            }
+
+            quad_mesh.push_face(&face);
+        }
+    }
+
+    quad_mesh
+}
+
+impl QuadMesh {
+    /// Add a traced face if it is a triangle or quad, wound to agree with the vertex normals the
+    /// same way [`InputMesh::compute_normals`] does.
+    fn push_face(&mut self, face: &[usize]) {
+        let v = |i: usize| self.vertices[face[i]];
+        let mut normal = Vec3::ZERO;
+        for i in 1..face.len() - 1 {
+            normal += (v(i + 1) - v(0)).cross(v(i) - v(0));
+        }
+        let vertex_normal = face.iter().map(|i| self.normals[*i]).sum::<Vec3>();
+        let flip = normal.dot(vertex_normal) < 0.0;
+
+        match *face {
+            [a, b, c] => self.tris.push(if flip { [a, c, b] } else { [a, b, c] }),
+            [a, b, c, d] => self
+                .quads
+                .push(if flip { [a, d, c, b] } else { [a, b, c, d] }),
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hierarchy, mesh::ProcessMesh, obj, orientation, position, shapes, stream::AsyncStreamReader,
+    };
+
+    /// Quads extracted from `mesh` at `scale`, along `o_field` or a smoothed field if `None`.
+    fn remesh(mesh: InputMesh, o_field: Option<Vec3>, scale: f32) -> QuadMesh {
+        let hierarchy = hierarchy::build(ProcessMesh::from(mesh));
+        let finest = &hierarchy[hierarchy.len() - 1].mesh;
+        let o_field = match o_field {
+            Some(o) => vec![o; finest.vertices.len()],
+            None => orientation::hierarchical_smoothing(&hierarchy, 10, None, &[]).unwrap(),
+        };
+        let p_field = position::hierarchical_position_smoothing(&hierarchy, &o_field, scale, 10);
+        extract_quads(&hierarchy, &o_field, &p_field, scale)
+    }
+
+    /// Lengths of the sides of every quad of `mesh`.
+    fn quad_edge_lengths(mesh: &QuadMesh) -> Vec<f32> {
+        mesh.quads
+            .iter()
+            .flat_map(|q| (0..4).map(move |k| (q[k], q[(k + 1) % 4])))
+            .map(|(a, b)| mesh.vertices[a].distance(mesh.vertices[b]))
+            .collect()
+    }
+
+    #[test]
+    fn flat_grid_becomes_a_grid_of_quads_at_the_target_scale() {
+        let quads = remesh(shapes::plane(24), Some(Vec3::X), 0.1);
+        assert_eq!((quads.num_quads(), quads.num_triangles()), (100, 0));
+        assert_eq!(quads.vertices.len(), 121);
+        for l in quad_edge_lengths(&quads) {
+            assert!((l - 0.1).abs() < 1e-5, "{}", l);
+        }
+
+        // Quads stay quads in OBJ, and load back as two triangles each
+        let obj = quads.to_obj();
+        assert_eq!(obj.lines().filter(|l| l.starts_with("f ")).count(), 100);
+        let mut reader = AsyncStreamReader::from_bytes(obj.into_bytes());
+        let loaded = futures::executor::block_on(obj::load_obj(&mut reader)).unwrap();
+        assert_eq!(loaded.vertices, quads.vertices);
+        assert_eq!(loaded.tris.len(), 200);
+    }
+
+    #[test]
+    fn torus_becomes_mostly_quads_at_the_target_scale() {
+        let scale = 0.15;
+        // Fine enough that even the outer edges are shorter than one lattice step
+        let quads = remesh(shapes::torus(72, 24, 1.0, 0.3), None, scale);
+        assert!(quads.num_quads() > 10 * quads.num_triangles());
+
+        // As many quads as fit in its area, give or take the singularities
+        let area = 4.0 * std::f32::consts::PI.powi(2) * 1.0 * 0.3;
+        let expected = area / (scale * scale);
+        let count = quads.num_quads() as f32;
+        assert!(
+            (0.75..1.25).contains(&(count / expected)),
+            "{} quads {} tris {} mean",
+            count,
+            quads.num_triangles(),
+            {
+                let l = quad_edge_lengths(&quads);
+                l.iter().sum::<f32>() / l.len() as f32
+            }
+        );
+
+        let lengths = quad_edge_lengths(&quads);
+        let mean = lengths.iter().sum::<f32>() / lengths.len() as f32;
+        assert!((mean / scale - 1.0).abs() < 0.05, "mean edge {}", mean);
+    }
+}
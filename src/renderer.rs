@@ -1,8 +1,17 @@
-use std::borrow::Cow;
+use std::{
+    borrow::Cow,
+    cell::Cell,
+    f32::consts::{FRAC_PI_2, PI},
+    mem,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc,
+    },
+};
 
-use anyhow::{Context, Result};
-use glam::{vec3, Mat4, Vec3};
-use rand::{rngs::SmallRng, Rng, SeedableRng};
+use glam::{vec3, Mat3, Mat4, Vec3};
+use rand::Rng;
 use raw_window_handle::{
     HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle, WebDisplayHandle,
     WebWindowHandle,
@@ -10,31 +19,57 @@ use raw_window_handle::{
 use wasm_bindgen::prelude::*;
 use wgpu::util::DeviceExt;
 
-use crate::mesh::InputMesh;
+use crate::{
+    hierarchy,
+    mesh::{
+        face_normal, flat_indices, interleaved_vertices, InputMesh, MeshChunk, MeshDiagnostics,
+        ProcessMesh,
+    },
+    orientation::{self, FieldSolver, Smoother},
+    pipeline::yield_now,
+    remesh::QuadMesh,
+    rng,
+};
 
 struct CanvasWindow {
     window_handle: RawWindowHandle,
     display_handle: RawDisplayHandle,
 }
 
+/// Next handle id given to canvases without a `data-raw-handle` attribute, counting up from
+/// well past the small ids apps tend to pick by hand.
+static NEXT_HANDLE: AtomicU32 = AtomicU32::new(1 << 30);
+
 impl CanvasWindow {
-    fn new(canvas: &web_sys::HtmlCanvasElement) -> Self {
+    /// Window for `canvas`, identified by `handle`. The handle is written to the canvas's
+    /// `data-raw-handle` attribute, which is how wgpu finds the canvas again.
+    fn new(canvas: &web_sys::HtmlCanvasElement, handle: u32) -> Result<Self, JsValue> {
+        if handle == 0 {
+            return Err("Canvas handle 0 is reserved".into());
+        }
+        canvas.dataset().set("rawHandle", &handle.to_string())?;
+
         let mut web_window = WebWindowHandle::empty();
-        web_window.id = canvas
-            .dataset()
-            .get("rawHandle")
-            .expect("Canvas element missing data-raw-handle")
-            .parse()
-            .expect("data-raw-handle not an integer");
+        web_window.id = handle;
         let window_handle = RawWindowHandle::Web(web_window);
 
         let web_display = WebDisplayHandle::empty();
         let display_handle = RawDisplayHandle::Web(web_display);
 
-        Self {
+        Ok(Self {
             window_handle,
             display_handle,
-        }
+        })
+    }
+}
+
+/// The handle in the `data-raw-handle` attribute of `canvas`, or a fresh one if it has none.
+fn canvas_handle(canvas: &web_sys::HtmlCanvasElement) -> Result<u32, JsValue> {
+    match canvas.dataset().get("rawHandle") {
+        Some(handle) => handle
+            .parse()
+            .map_err(|_| format!("data-raw-handle '{}' is not an integer", handle).into()),
+        None => Ok(NEXT_HANDLE.fetch_add(1, Ordering::Relaxed)),
     }
 }
 
@@ -50,30 +85,377 @@ unsafe impl HasRawDisplayHandle for CanvasWindow {
     }
 }
 
+/// A GPU buffer along with a copy of its contents, so that it can be recreated on a new device
+/// after the old one is lost.
+///
+/// The copy doubles the memory held for every mesh and overlay, which for a large scan means
+/// hundreds of megabytes on the CPU side alongside the GPU's. The meshes it comes from are often
+/// gone by the time the device is lost, as after [`Renderer::update_mesh_owned`], so the copy is
+/// the only way to bring the scene back without asking the caller to upload it again.
+struct RetainedBuffer {
+    buffer: wgpu::Buffer,
+    contents: Vec<u8>,
+    label: &'static str,
+    usage: wgpu::BufferUsages,
+}
+
+impl RetainedBuffer {
+    fn new(
+        device: &wgpu::Device,
+        label: &'static str,
+        contents: &[u8],
+        usage: wgpu::BufferUsages,
+    ) -> Self {
+        Self {
+            buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents,
+                usage,
+            }),
+            contents: contents.to_vec(),
+            label,
+            usage,
+        }
+    }
+
+    /// A buffer of `len` zero bytes, left for the device to clear rather than uploading zeros.
+    fn zeroed(
+        device: &wgpu::Device,
+        label: &'static str,
+        len: usize,
+        usage: wgpu::BufferUsages,
+    ) -> Self {
+        Self {
+            buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: len as u64,
+                usage,
+                mapped_at_creation: false,
+            }),
+            contents: vec![0; len],
+            label,
+            usage,
+        }
+    }
+
+    /// A buffer of `len` elements written from `items` straight into its retained contents, so
+    /// that uploading needs no assembled copy of the data on the side. Elements `items` runs
+    /// short of are zero.
+    fn from_iter<T: bytemuck::Pod>(
+        device: &wgpu::Device,
+        label: &'static str,
+        len: usize,
+        usage: wgpu::BufferUsages,
+        items: impl IntoIterator<Item = T>,
+    ) -> Self {
+        let stride = std::mem::size_of::<T>();
+        let mut contents = vec![0; len * stride];
+        for (dst, item) in contents.chunks_exact_mut(stride).zip(items) {
+            dst.copy_from_slice(bytemuck::bytes_of(&item));
+        }
+        Self {
+            buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: &contents,
+                usage,
+            }),
+            contents,
+            label,
+            usage,
+        }
+    }
+
+    /// Overwrite the contents starting `offset` bytes in.
+    fn write(&mut self, queue: &wgpu::Queue, offset: usize, data: &[u8]) {
+        self.contents[offset..offset + data.len()].copy_from_slice(data);
+        queue.write_buffer(&self.buffer, offset as u64, data);
+    }
+
+    /// Grow the buffer to hold at least `len` bytes, at least doubling it so that a run of
+    /// growing writes recreates it only a few times. The new space is zero.
+    fn reserve(&mut self, device: &wgpu::Device, len: usize) {
+        if len > self.contents.len() {
+            self.contents.resize(len.max(2 * self.contents.len()), 0);
+            self.restore(device);
+        }
+    }
+
+    /// Recreate the buffer on `device` from the retained contents.
+    fn restore(&mut self, device: &wgpu::Device) {
+        *self = Self::new(device, self.label, &self.contents, self.usage);
+    }
+}
+
+struct MeshEntry {
+    vertex_buf: RetainedBuffer,
+    color_buf: RetainedBuffer,
+    num_vertices: usize,
+    index_buf: RetainedBuffer,
+    num_indices: u32,
+    /// [`topology_hash`] of the uploaded triangles, if they were uploaded all at once.
+    topology_hash: Option<u64>,
+    /// Mesh vertex that each drawn vertex past `num_vertices` was split from, along a crease or
+    /// by [`Renderer::set_face_colors`].
+    split_source: Vec<u32>,
+    /// Drawn vertex that each copy made by [`Renderer::set_face_colors`] was split from. These
+    /// copies come last, after those split along creases.
+    face_split_from: Vec<u32>,
+    pick_buf: RetainedBuffer,
+    visible: bool,
+
+    transform: Mat4,
+    transform_buf: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl MeshEntry {
+    fn layout(&self) -> MeshLayout {
+        MeshLayout {
+            num_vertices: self.num_vertices,
+            num_indices: self.num_indices,
+        }
+    }
+}
+
+/// Sizes of the buffers holding a mesh, and which passes can draw it. Empty buffers can't be
+/// bound, so a mesh without faces draws no surface, and one without vertices nothing at all.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct MeshLayout {
+    num_vertices: usize,
+    num_indices: u32,
+}
+
+impl MeshLayout {
+    /// Layout of `num_vertices` vertices and `num_tris` triangles, which may both be zero. Panics
+    /// if the vertices can't all be addressed by 32-bit indices.
+    fn new(num_vertices: usize, num_tris: usize) -> Self {
+        assert!(
+            num_vertices as u64 <= u32::MAX as u64 + 1,
+            "{} vertices can't be drawn with 32-bit indices",
+            num_vertices
+        );
+        Self {
+            num_vertices,
+            num_indices: (3 * num_tris) as u32,
+        }
+    }
+
+    fn vertex_bytes(self) -> usize {
+        self.num_vertices * std::mem::size_of::<MeshVertex>()
+    }
+
+    fn index_bytes(self) -> usize {
+        self.num_indices as usize * std::mem::size_of::<u32>()
+    }
+
+    /// Picking draws every corner separately, with the triangle's corners all in each.
+    fn pick_bytes(self) -> usize {
+        self.num_indices as usize * std::mem::size_of::<[u32; 7]>()
+    }
+
+    fn draws_surface(self) -> bool {
+        self.num_indices > 0
+    }
+
+    fn draws_points(self) -> bool {
+        self.num_vertices > 0
+    }
+}
+
+/// How the orientation field overlay picks the vertices it draws crosses at.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OfieldSampling {
+    /// Every vertex equally likely, so dense parts of the mesh get more crosses.
+    PerVertex,
+    /// Each vertex in proportion to its dual area, spreading crosses evenly over the surface.
+    ByArea,
+}
+
+/// A vertex picked with [`Renderer::pick`].
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pick {
+    mesh: usize,
+    vertex: u32,
+}
+
+#[wasm_bindgen]
+impl Pick {
+    /// Index of the mesh that was hit, among those shown when the pick was made.
+    #[wasm_bindgen(getter)]
+    pub fn mesh(&self) -> usize {
+        self.mesh
+    }
+
+    /// Index of the picked vertex among the vertices of its mesh.
+    #[wasm_bindgen(getter)]
+    pub fn vertex(&self) -> u32 {
+        self.vertex
+    }
+}
+
 #[wasm_bindgen]
 pub struct Renderer {
+    canvas: web_sys::HtmlCanvasElement,
+    handle: u32,
+    /// Set once the device or the canvas's context is lost, after which nothing is drawn until
+    /// [`Renderer::recover`].
+    device_lost: Arc<AtomicBool>,
+    context_lost_listener: Closure<dyn FnMut(web_sys::Event)>,
+
+    #[allow(dead_code)]
     instance: wgpu::Instance,
     surface: wgpu::Surface,
     adapter: wgpu::Adapter,
-    device: wgpu::Device,
+    /// Shared with pending picks, which keep polling it until their readback is mapped.
+    device: Rc<wgpu::Device>,
     queue: wgpu::Queue,
     surface_config: wgpu::SurfaceConfiguration,
 
     uniforms: wgpu::Buffer,
     bind_group: wgpu::BindGroup,
+    mesh_bind_group_layout: wgpu::BindGroupLayout,
     depth_view: wgpu::TextureView,
     pipeline: wgpu::RenderPipeline,
+    xray_pipeline: wgpu::RenderPipeline,
+    transparent_pipeline: wgpu::RenderPipeline,
+    points_pipeline: wgpu::RenderPipeline,
+    normals_pipeline: wgpu::RenderPipeline,
     ofield_pipeline: wgpu::RenderPipeline,
+    ofield_hidden_pipeline: wgpu::RenderPipeline,
+    wire_pipeline: wgpu::RenderPipeline,
+    streamline_pipeline: wgpu::RenderPipeline,
+
+    pick_uniforms: wgpu::Buffer,
+    pick_bind_group: wgpu::BindGroup,
+    pick_pipeline: wgpu::RenderPipeline,
+    pick_texture: wgpu::Texture,
+    pick_view: wgpu::TextureView,
+    pick_depth_view: wgpu::TextureView,
+
+    meshes: Vec<MeshEntry>,
+    /// Index of the mesh that [`Renderer::append_mesh_chunk`] is filling in.
+    streaming_mesh: Option<usize>,
+
+    /// One instance per sampled vertex, expanded to a cross by the overlay's vertex shader.
+    ofield_instances: Option<(RetainedBuffer, u32)>,
+    ofield_see_through: bool,
+    /// Half-length of each cross arm, or `None` to size them to the mesh.
+    ofield_arm_length: Option<f32>,
+    /// Expected fraction of the vertices that get a cross.
+    ofield_density: f32,
+    ofield_sampling: OfieldSampling,
+
+    wire_buffer: Option<(RetainedBuffer, u32)>,
+    streamline_buffer: Option<(RetainedBuffer, u32)>,
+    xray: bool,
+    /// Opacity of the shaded meshes, drawn with the transparent pipeline below 1.
+    mesh_opacity: f32,
+    show_vertices: bool,
+    show_normals: bool,
+    /// Length of the lines of the normals overlay, or `None` to size them to the mesh bounds.
+    normal_length: Option<f32>,
+    /// Width of the wireframe, streamline and normals overlay lines, in pixels.
+    line_width: f32,
 
-    buffers: Option<(wgpu::Buffer, wgpu::Buffer)>,
-    num_indices: u32,
-
-    ofield_buffers: Option<(wgpu::Buffer, wgpu::Buffer)>,
-    num_ofield_indices: u32,
-
-    mouse_down: bool,
     rx: f32,
     ry: f32,
+    distance: f32,
+    near: f32,
+    far: f32,
+    center: Vec3,
+
+    bounds: Option<(Vec3, Vec3)>,
+    auto_frame: bool,
+
+    /// Whether the surface encodes to sRGB on write.
+    srgb_surface: bool,
+    gamma_correct: bool,
+
+    /// Whether anything shown has changed since the last draw.
+    needs_redraw: Cell<bool>,
+}
+
+/// Size of the shared uniforms: view and model transforms followed by the output settings, the
+/// normals overlay settings, the line settings and the eye position.
+const UNIFORMS_SIZE: u64 = 192;
+
+/// Contents of the shared uniform buffer.
+#[allow(clippy::too_many_arguments)]
+fn uniform_data(
+    view_transform: Mat4,
+    model_transform: Mat4,
+    transfer: f32,
+    opacity: f32,
+    point_size: [f32; 2],
+    normal_length: f32,
+    lines: [f32; 3],
+    eye: Vec3,
+) -> [f32; 48] {
+    let mut data = [0.0; 48];
+    data[..16].copy_from_slice(&view_transform.to_cols_array());
+    data[16..32].copy_from_slice(&model_transform.to_cols_array());
+    data[32] = transfer;
+    data[33] = opacity;
+    data[34..36].copy_from_slice(&point_size);
+    data[36] = normal_length;
+    data[40..43].copy_from_slice(&lines);
+    data[44..47].copy_from_slice(&eye.to_array());
+    data
+}
+
+/// Size of a mesh's uniform buffer: [`mesh_uniform_data`], then the mesh's index as a `u32`,
+/// written before each pick, padded to a multiple of 16 bytes.
+const MESH_UNIFORMS_SIZE: u64 = 144;
+
+/// Offset of the mesh's index in its uniform buffer.
+const MESH_INDEX_OFFSET: u64 = 128;
+
+/// Contents of a mesh's uniform buffer up to its index: its `transform`, then the inverse
+/// transpose of that, which keeps normals perpendicular to the surface under non-uniform
+/// scaling. A singular transform flattens the mesh anyway, so its normals just take the linear
+/// part.
+fn mesh_uniform_data(transform: Mat4) -> [f32; 32] {
+    let linear = Mat3::from_mat4(transform);
+    let normal_transform = if linear.determinant().abs() > f32::EPSILON {
+        linear.inverse().transpose()
+    } else {
+        linear
+    };
+
+    let mut data = [0.0; 32];
+    data[..16].copy_from_slice(&transform.to_cols_array());
+    data[16..].copy_from_slice(&Mat4::from_mat3(normal_transform).to_cols_array());
+    data
+}
+
+/// Width of the overlay lines in pixels, unless set with [`Renderer::set_line_width`].
+const DEFAULT_LINE_WIDTH: f32 = 1.5;
+
+/// Vertices of the two triangles each overlay line segment is widened to.
+const LINE_VERTICES: u32 = 6;
+
+/// Radius of the vertex points overlay, in pixels.
+const VERTEX_POINT_RADIUS: f32 = 2.5;
+
+/// Length of the normals overlay lines relative to the diagonal of the mesh bounds, unless set
+/// with [`Renderer::set_normal_length`].
+const NORMAL_LENGTH_SCALE: f32 = 0.02;
+
+/// Half-size of a vertex point in normalized device coordinates on a surface of the given size.
+fn point_size(width: u32, height: u32) -> [f32; 2] {
+    [width, height].map(|extent| 2.0 * VERTEX_POINT_RADIUS / extent.max(1) as f32)
+}
+
+/// How the mesh shader converts its linear lighting result before writing it: `0` as is, `1`
+/// encoded to sRGB, or `2` decoded from sRGB so that an sRGB surface stores the raw values.
+fn output_transfer(srgb_surface: bool, gamma_correct: bool) -> f32 {
+    match (srgb_surface, gamma_correct) {
+        (true, true) | (false, false) => 0.0,
+        (false, true) => 1.0,
+        (true, false) => 2.0,
+    }
 }
 
 fn create_depth_texture(device: &wgpu::Device, width: u32, height: u32) -> wgpu::TextureView {
@@ -95,35 +477,644 @@ fn create_depth_texture(device: &wgpu::Device, width: u32, height: u32) -> wgpu:
     depth_texture.create_view(&wgpu::TextureViewDescriptor::default())
 }
 
-fn create_view_transform(width: u32, height: u32) -> Mat4 {
-    Mat4::perspective_rh(
-        75f32.to_radians(),
-        width as f32 / height as f32,
-        0.1,
-        1000.0,
-    ) * Mat4::look_at_rh(vec3(0.0, 150.0, 0.0), Vec3::ZERO, Vec3::Z)
+const FOV: f32 = 75.0;
+const MESH_COLOR: Vec3 = vec3(0.5, 0.5, 1.0);
+
+/// Color for a vertex of the given valence: neutral at the regular valence of 6, shading towards
+/// blue below it and red above.
+fn valence_color(valence: usize) -> Vec3 {
+    let t = ((valence as f32 - 6.0) / 3.0).clamp(-1.0, 1.0);
+    if t < 0.0 {
+        Vec3::splat(0.8).lerp(vec3(0.1, 0.3, 1.0), -t)
+    } else {
+        Vec3::splat(0.8).lerp(vec3(1.0, 0.2, 0.1), t)
+    }
+}
+
+/// Color for a scalar normalized to `[0, 1]`: neutral gray at 0, shading towards red at 1.
+fn scalar_color(t: f32) -> Vec3 {
+    Vec3::splat(0.8).lerp(vec3(1.0, 0.2, 0.1), t.clamp(0.0, 1.0))
+}
+
+/// Categorical palette for material ids, repeating past its length.
+const MATERIAL_PALETTE: [Vec3; 10] = [
+    vec3(0.12, 0.47, 0.71),
+    vec3(1.0, 0.5, 0.05),
+    vec3(0.17, 0.63, 0.17),
+    vec3(0.84, 0.15, 0.16),
+    vec3(0.58, 0.4, 0.74),
+    vec3(0.55, 0.34, 0.29),
+    vec3(0.89, 0.47, 0.76),
+    vec3(0.5, 0.5, 0.5),
+    vec3(0.74, 0.74, 0.13),
+    vec3(0.09, 0.75, 0.81),
+];
+
+/// Color for hierarchy cluster `cluster`. Hues step by the golden angle, so clusters with nearby
+/// indices, which tend to be nearby on the mesh too, get clearly different colors.
+fn cluster_color(cluster: usize) -> Vec3 {
+    let hue = (cluster as f32 * 0.618034).fract() * 6.0;
+    let rgb = vec3(
+        (hue - 3.0).abs() - 1.0,
+        2.0 - (hue - 2.0).abs(),
+        2.0 - (hue - 4.0).abs(),
+    );
+    0.25 + 0.65 * rgb.clamp(Vec3::ZERO, Vec3::ONE)
+}
+
+/// How a mesh pipeline blends and depth tests.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum MeshBlend {
+    Opaque,
+    /// Every face, including back faces, blended and without depth testing, for use with the
+    /// translucent `fs_xray` entry point.
+    Xray,
+    /// Premultiplied alpha blending, depth tested against but not writing depth, for use with
+    /// the `fs_transparent` entry point.
+    Transparent,
+}
+
+/// Pipeline for shading meshes with the `fs_entry` entry point, blended according to `blend`.
+fn create_mesh_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    fs_entry: &str,
+    blend: MeshBlend,
+) -> wgpu::RenderPipeline {
+    let xray = blend == MeshBlend::Xray;
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[
+                wgpu::VertexBufferLayout {
+                    array_stride: 24 as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &[
+                        wgpu::VertexAttribute {
+                            format: wgpu::VertexFormat::Float32x3,
+                            offset: 0,
+                            shader_location: 0,
+                        },
+                        wgpu::VertexAttribute {
+                            format: wgpu::VertexFormat::Float32x3,
+                            offset: 12,
+                            shader_location: 1,
+                        },
+                    ],
+                },
+                wgpu::VertexBufferLayout {
+                    array_stride: 12 as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &[wgpu::VertexAttribute {
+                        format: wgpu::VertexFormat::Float32x3,
+                        offset: 0,
+                        shader_location: 2,
+                    }],
+                },
+            ],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: fs_entry,
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: match blend {
+                    MeshBlend::Opaque => None,
+                    MeshBlend::Xray => Some(wgpu::BlendState::ALPHA_BLENDING),
+                    MeshBlend::Transparent => Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                },
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            cull_mode: if xray { None } else { Some(wgpu::Face::Back) },
+            ..Default::default()
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: wgpu::TextureFormat::Depth16Unorm,
+            depth_write_enabled: blend == MeshBlend::Opaque,
+            depth_compare: if xray {
+                wgpu::CompareFunction::Always
+            } else {
+                wgpu::CompareFunction::Less
+            },
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+/// Vertices the overlay's vertex shader expands each cross instance into.
+const OFIELD_CROSS_VERTICES: u32 = 12;
+
+/// Pipeline for the orientation field crosses, drawn as one instance of position and arm length,
+/// o and normal per sampled vertex. The blended `fs_hidden` variant draws crosses that are behind
+/// the surface when used with a `Greater` depth compare.
+fn create_ofield_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    fs_entry: &str,
+    depth_write_enabled: bool,
+    depth_compare: wgpu::CompareFunction,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[wgpu::VertexBufferLayout {
+                array_stride: 40 as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Instance,
+                attributes: &[
+                    wgpu::VertexAttribute {
+                        format: wgpu::VertexFormat::Float32x4,
+                        offset: 0,
+                        shader_location: 0,
+                    },
+                    wgpu::VertexAttribute {
+                        format: wgpu::VertexFormat::Float32x3,
+                        offset: 16,
+                        shader_location: 1,
+                    },
+                    wgpu::VertexAttribute {
+                        format: wgpu::VertexFormat::Float32x3,
+                        offset: 28,
+                        shader_location: 2,
+                    },
+                ],
+            }],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: fs_entry,
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            cull_mode: None,
+            ..Default::default()
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: wgpu::TextureFormat::Depth16Unorm,
+            depth_write_enabled,
+            depth_compare,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+/// Pipeline for the vertex points overlay. Each vertex of a mesh is drawn as an instance, read
+/// straight from the mesh's own position and color buffers, which the vertex shader expands to
+/// a small screen-aligned quad.
+fn create_points_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[
+                wgpu::VertexBufferLayout {
+                    array_stride: 24 as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &[wgpu::VertexAttribute {
+                        format: wgpu::VertexFormat::Float32x3,
+                        offset: 0,
+                        shader_location: 0,
+                    }],
+                },
+                wgpu::VertexBufferLayout {
+                    array_stride: 12 as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &[wgpu::VertexAttribute {
+                        format: wgpu::VertexFormat::Float32x3,
+                        offset: 0,
+                        shader_location: 1,
+                    }],
+                },
+            ],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(format.into())],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: wgpu::TextureFormat::Depth16Unorm,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+/// Pipeline for the normals overlay, drawing a line per vertex of a mesh straight from its vertex
+/// buffer, which the vertex shader extends along the normal and widens to a quad of
+/// [`LINE_VERTICES`] vertices like [`create_line_pipeline`]. Like other lines, it is tested against
+/// depth but doesn't write it.
+fn create_normals_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[wgpu::VertexBufferLayout {
+                array_stride: 24 as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Instance,
+                attributes: &[
+                    wgpu::VertexAttribute {
+                        format: wgpu::VertexFormat::Float32x3,
+                        offset: 0,
+                        shader_location: 0,
+                    },
+                    wgpu::VertexAttribute {
+                        format: wgpu::VertexFormat::Float32x3,
+                        offset: 12,
+                        shader_location: 1,
+                    },
+                ],
+            }],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: wgpu::TextureFormat::Depth16Unorm,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+/// Pipeline for lines over the mesh, such as the quad wireframe and streamlines, colored by the
+/// `fs_entry` entry point of the line shader. Each segment is an instance of its two ends, which
+/// the vertex shader widens to a screen-aligned quad of [`LINE_VERTICES`] vertices so that lines
+/// can be wider than a pixel and blended smoothly at their edges. Lines don't write depth, so
+/// that the faded edge of one doesn't hide the lines behind it.
+fn create_line_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    fs_entry: &str,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[wgpu::VertexBufferLayout {
+                array_stride: 24 as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Instance,
+                attributes: &[
+                    wgpu::VertexAttribute {
+                        format: wgpu::VertexFormat::Float32x3,
+                        offset: 0,
+                        shader_location: 0,
+                    },
+                    wgpu::VertexAttribute {
+                        format: wgpu::VertexFormat::Float32x3,
+                        offset: 12,
+                        shader_location: 1,
+                    },
+                ],
+            }],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: fs_entry,
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: wgpu::TextureFormat::Depth16Unorm,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+/// Position and normal of a vertex, as laid out in a mesh vertex buffer.
+type MeshVertex = [[f32; 3]; 2];
+
+/// Vertices of `mesh` for drawing with hard creases. Each vertex keeps its index with the normal
+/// of its first corner, and is copied for every other distinct normal among its corners, with the
+/// copies appended. Returns the vertices with their normals, the triangles on them and the mesh
+/// vertex each copy came from.
+fn split_creases(
+    mesh: &InputMesh,
+    corner_normals: &[[Vec3; 3]],
+) -> (Vec<MeshVertex>, Vec<[u32; 3]>, Vec<u32>) {
+    let mut verts = interleaved_vertices(&mesh.vertices, &mesh.normals).collect::<Vec<_>>();
+    let mut assigned = vec![false; verts.len()];
+    let mut copies = std::collections::HashMap::new();
+    let mut split_source = Vec::new();
+
+    let tris = mesh
+        .tris
+        .iter()
+        .zip(corner_normals)
+        .map(|(tri, normals)| {
+            [0, 1, 2].map(|k| {
+                let (v, n) = (tri[k], normals[k].to_array());
+                if !assigned[v] {
+                    assigned[v] = true;
+                    verts[v][1] = n;
+                    return v as u32;
+                }
+                if verts[v][1] == n {
+                    return v as u32;
+                }
+                *copies.entry((v, n.map(f32::to_bits))).or_insert_with(|| {
+                    verts.push([verts[v][0], n]);
+                    split_source.push(v as u32);
+                    verts.len() as u32 - 1
+                })
+            })
+        })
+        .collect();
+    (verts, tris, split_source)
+}
+
+/// Triangles `indices` over `num_drawn` drawn vertices, split so that every face can show its own
+/// color from `face_colors`. Each vertex keeps the color of its first face, and is copied for every
+/// other distinct color among its faces, with the copies appended. Returns the new triangles, the
+/// drawn vertex each copy came from and the color of every vertex, `None` for those on no face.
+fn split_face_colors(
+    indices: &[u32],
+    num_drawn: usize,
+    face_colors: &[[f32; 3]],
+) -> (Vec<u32>, Vec<u32>, Vec<Option<[f32; 3]>>) {
+    let mut colors = vec![None; num_drawn];
+    let mut copies = std::collections::HashMap::new();
+    let mut split_from = Vec::new();
+
+    let tris = indices
+        .chunks_exact(3)
+        .zip(face_colors)
+        .flat_map(|(tri, color)| {
+            tri.iter()
+                .map(|v| match colors[*v as usize] {
+                    None => {
+                        colors[*v as usize] = Some(*color);
+                        *v
+                    }
+                    Some(c) if c == *color => *v,
+                    Some(_) => *copies
+                        .entry((*v, color.map(f32::to_bits)))
+                        .or_insert_with(|| {
+                            colors.push(Some(*color));
+                            split_from.push(*v);
+                            colors.len() as u32 - 1
+                        }),
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    (tris, split_from, colors)
+}
+
+/// Endpoints of the distinct edges of `quads`, lifted slightly out of the surface.
+fn quad_wire_vertices(quads: &QuadMesh) -> Vec<[f32; 3]> {
+    let mut edges = quads
+        .quads
+        .iter()
+        .flat_map(|q| (0..4).map(move |k| (q[k], q[(k + 1) % 4])))
+        .chain(
+            quads
+                .tris
+                .iter()
+                .flat_map(|t| (0..3).map(move |k| (t[k], t[(k + 1) % 3]))),
+        )
+        .map(|(a, b)| (a.min(b), a.max(b)))
+        .collect::<Vec<_>>();
+    edges.sort_unstable();
+    edges.dedup();
+
+    // Lift the lines slightly out of the surface, against the inward vertex normals, so they
+    // don't fight it for depth
+    let mean_length = edges
+        .iter()
+        .map(|(a, b)| quads.vertices[*a].distance(quads.vertices[*b]))
+        .sum::<f32>()
+        / edges.len().max(1) as f32;
+    let lift = |v: usize| quads.vertices[v] - 0.02 * mean_length * quads.normals[v];
+    edges
+        .iter()
+        .flat_map(|(a, b)| [lift(*a).to_array(), lift(*b).to_array()])
+        .collect()
+}
+
+/// Endpoints of the segments of streamlines `lines` traced on `mesh`, lifted slightly out of the
+/// surface.
+fn streamline_vertices(mesh: &ProcessMesh, lines: &[Vec<(Vec3, usize)>]) -> Vec<[f32; 3]> {
+    // Lift the lines out of the surface like the wireframe, along the inward face normals
+    let mean_length = mesh
+        .tris
+        .iter()
+        .map(|[a, b, _]| mesh.vertices[*a].distance(mesh.vertices[*b]))
+        .sum::<f32>()
+        / mesh.tris.len().max(1) as f32;
+    let lift = |(p, face): (Vec3, usize)| {
+        let [v0, v1, v2] = mesh.tris[face].map(|v| mesh.vertices[v]);
+        let n = face_normal(v0, v1, v2);
+        (p - 0.02 * mean_length * n).to_array()
+    };
+    lines
+        .iter()
+        .flat_map(|line| line.windows(2))
+        .flat_map(|w| [lift(w[0]), lift(w[1])])
+        .collect()
+}
+
+/// Hash identifying a triangle list, for telling whether an upload can reuse an index buffer.
+fn topology_hash(tris: &[[usize; 3]]) -> u64 {
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    tris.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Unindexed corners carrying the ids of their whole triangle, for picking. `position` looks up
+/// a vertex by its index in the mesh.
+fn pick_vertices(tris: &[[usize; 3]], position: impl Fn(usize) -> Vec3) -> Vec<[u32; 7]> {
+    tris.iter()
+        .flat_map(|tri| pick_triangle(*tri, &position))
+        .collect()
+}
+
+/// The three picking corners of `tri`, as in [`pick_vertices`].
+fn pick_triangle(tri: [usize; 3], position: impl Fn(usize) -> Vec3) -> [[u32; 7]; 3] {
+    [0, 1, 2].map(|k| {
+        let [x, y, z] = position(tri[k]).to_array();
+        [
+            x.to_bits(),
+            y.to_bits(),
+            z.to_bits(),
+            tri[0] as u32,
+            tri[1] as u32,
+            tri[2] as u32,
+            k as u32,
+        ]
+    })
+}
+
+/// Position of vertex `id` of `mesh` from its retained vertex buffer, after the mesh's own
+/// transform, or `None` if it has no such vertex.
+fn mesh_vertex_position(mesh: &MeshEntry, id: u32) -> Option<Vec3> {
+    // Copies split off for drawing past the mesh's own vertices aren't vertices of the mesh
+    if id as usize >= mesh.num_vertices {
+        return None;
+    }
+    let stride = std::mem::size_of::<MeshVertex>();
+    let start = id as usize * stride;
+    let bytes = mesh.vertex_buf.contents.get(start..start + stride)?;
+    let [position, _]: MeshVertex = bytemuck::pod_read_unaligned(bytes);
+    Some(mesh.transform.transform_point3(Vec3::from(position)))
+}
+
+/// The pick in the readback submitted by [`Renderer::submit_pick`], once the GPU gets to it, or
+/// `None` over the background. WebGL only finishes mapping buffers when the device is polled, so
+/// this polls it on every turn of the event loop rather than blocking, which the browser doesn't
+/// allow.
+async fn read_pick(device: &wgpu::Device, readback: &wgpu::Buffer) -> Option<Pick> {
+    let slice = readback.slice(0..8);
+    let (sender, mut receiver) = futures::channel::oneshot::channel();
+    slice.map_async(wgpu::MapMode::Read, move |r| {
+        let _ = sender.send(r.is_ok());
+    });
+
+    let mapped = loop {
+        device.poll(wgpu::Maintain::Poll);
+        match receiver.try_recv() {
+            Ok(Some(mapped)) => break mapped,
+            Ok(None) => yield_now().await,
+            Err(_) => break false,
+        }
+    };
+    if !mapped {
+        log::warn!("Picking readback did not complete");
+        return None;
+    }
+
+    let texel = slice.get_mapped_range();
+    decode_pick(&texel)
+}
+
+/// The pick in a texel of the picking target: the picked vertex's id plus one, so that 0 means
+/// the background, then its mesh's index.
+fn decode_pick(texel: &[u8]) -> Option<Pick> {
+    let [vertex, mesh] = [0, 4].map(|i| u32::from_ne_bytes(texel[i..i + 4].try_into().unwrap()));
+    Some(Pick {
+        mesh: mesh as usize,
+        vertex: vertex.checked_sub(1)?,
+    })
 }
 
-fn create_model_transform(rx: f32, ry: f32) -> Mat4 {
-    Mat4::from_euler(glam::EulerRot::XYZ, ry, 0.0, rx)
+/// Position of the camera `distance` away from the origin, in the space the model transform
+/// rotates the meshes into.
+fn eye_position(distance: f32) -> Vec3 {
+    vec3(0.0, distance, 0.0)
+}
+
+fn create_view_transform(width: u32, height: u32, distance: f32, near: f32, far: f32) -> Mat4 {
+    Mat4::perspective_rh(FOV.to_radians(), width as f32 / height as f32, near, far)
+        * Mat4::look_at_rh(eye_position(distance), Vec3::ZERO, Vec3::Z)
+}
+
+fn create_model_transform(rx: f32, ry: f32, center: Vec3) -> Mat4 {
+    Mat4::from_euler(glam::EulerRot::XYZ, ry, 0.0, rx) * Mat4::from_translation(-center)
 }
 
 #[wasm_bindgen]
 impl Renderer {
+    /// Create a renderer drawing to `canvas`, identified by its `data-raw-handle` attribute if
+    /// it has one, or else by a newly assigned handle.
+    #[allow(deprecated)]
     #[wasm_bindgen(constructor)]
-    pub async fn new(canvas: &web_sys::HtmlCanvasElement) -> Renderer {
-        let window = CanvasWindow::new(canvas);
+    pub async fn new(canvas: &web_sys::HtmlCanvasElement) -> Result<Renderer, JsValue> {
+        Self::with_handle(canvas, canvas_handle(canvas)?).await
+    }
 
-        let instance = wgpu::Instance::default();
+    /// Create a renderer drawing to `canvas`, identifying it by `handle` rather than a
+    /// `data-raw-handle` attribute set up in advance. The handle must be nonzero and not used by
+    /// another canvas. Fails if the browser can provide neither a WebGPU nor a WebGL2 device.
+    pub async fn with_handle(
+        canvas: &web_sys::HtmlCanvasElement,
+        handle: u32,
+    ) -> Result<Renderer, JsValue> {
+        let window = CanvasWindow::new(canvas, handle)?;
+
+        // Ask for WebGL2 alongside the primary backends so browsers without WebGPU still work
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::PRIMARY | wgpu::Backends::GL,
+            ..Default::default()
+        });
         let surface = unsafe { instance.create_surface(&window) }
-            .expect("Failed to create WebGPU surface from canvas");
+            .map_err(|e| format!("Failed to create surface from canvas: {}", e))?;
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
                 compatible_surface: Some(&surface),
                 ..Default::default()
             })
             .await
-            .expect("No WebGPU adapter available");
+            .ok_or("No WebGPU or WebGL2 adapter available")?;
+        log::info!(
+            "Rendering with the {:?} backend",
+            adapter.get_info().backend
+        );
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
@@ -135,7 +1126,33 @@ impl Renderer {
                 None,
             )
             .await
-            .expect("No suitable WebGPU device found!");
+            .map_err(|e| format!("Failed to get a graphics device: {}", e))?;
+
+        // wgpu reports running out of memory through uncaptured errors, and a lost WebGL context
+        // only through the canvas
+        let device_lost = Arc::new(AtomicBool::new(false));
+        device.on_uncaptured_error(Box::new({
+            let device_lost = device_lost.clone();
+            move |error| {
+                if let wgpu::Error::OutOfMemory { .. } = error {
+                    device_lost.store(true, Ordering::Relaxed);
+                }
+                log::error!("{}", error);
+            }
+        }));
+        let context_lost_listener = Closure::<dyn FnMut(web_sys::Event)>::new({
+            let device_lost = device_lost.clone();
+            move |event: web_sys::Event| {
+                // Without this the browser never offers to restore the context
+                event.prevent_default();
+                log::warn!("Lost the canvas's rendering context");
+                device_lost.store(true, Ordering::Relaxed);
+            }
+        });
+        canvas.add_event_listener_with_callback(
+            "webglcontextlost",
+            context_lost_listener.as_ref().unchecked_ref(),
+        )?;
 
         let swap_caps = surface.get_capabilities(&adapter);
         let swap_format = swap_caps.formats[0];
@@ -155,31 +1172,66 @@ impl Renderer {
             label: None,
             entries: &[wgpu::BindGroupLayoutEntry {
                 binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX,
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
                 ty: wgpu::BindingType::Buffer {
                     ty: wgpu::BufferBindingType::Uniform,
                     has_dynamic_offset: false,
-                    min_binding_size: wgpu::BufferSize::new(128),
+                    min_binding_size: wgpu::BufferSize::new(UNIFORMS_SIZE),
                 },
                 count: None,
             }],
         });
+        let mesh_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(MESH_UNIFORMS_SIZE),
+                    },
+                    count: None,
+                }],
+            });
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
             bind_group_layouts: &[&bind_group_layout],
             push_constant_ranges: &[],
         });
+        let mesh_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout, &mesh_bind_group_layout],
+            push_constant_ranges: &[],
+        });
 
         let depth_view = create_depth_texture(&device, surface_config.width, surface_config.height);
 
-        let view_transform = create_view_transform(surface_config.width, surface_config.height);
-        let model_transform = create_model_transform(0.0, 0.0);
+        let view_transform = create_view_transform(
+            surface_config.width,
+            surface_config.height,
+            150.0,
+            0.1,
+            1000.0,
+        );
+        let model_transform = create_model_transform(0.0, 0.0, Vec3::ZERO);
         let uniforms = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Uniforms"),
-            contents: bytemuck::cast_slice(&[
-                view_transform.to_cols_array(),
-                model_transform.to_cols_array(),
-            ]),
+            contents: bytemuck::cast_slice(&uniform_data(
+                view_transform,
+                model_transform,
+                output_transfer(swap_format.describe().srgb, true),
+                1.0,
+                point_size(surface_config.width, surface_config.height),
+                0.0,
+                [
+                    DEFAULT_LINE_WIDTH,
+                    surface_config.width as f32,
+                    surface_config.height as f32,
+                ],
+                eye_position(150.0),
+            )),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
@@ -192,24 +1244,128 @@ impl Renderer {
             }],
         });
 
+        let pick_uniforms = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Picking uniforms"),
+            size: UNIFORMS_SIZE,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let pick_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: pick_uniforms.as_entire_binding(),
+            }],
+        });
+
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: None,
             source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("preview.wgsl"))),
         });
 
+        let wire_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(concat!(
+                include_str!("line.wgsl"),
+                include_str!("wire.wgsl")
+            ))),
+        });
+
+        let pick_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("pick.wgsl"))),
+        });
+
         let ofield_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: None,
             source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("ofield.wgsl"))),
         });
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        let points_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: None,
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[wgpu::VertexBufferLayout {
-                    array_stride: 24 as wgpu::BufferAddress,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("points.wgsl"))),
+        });
+
+        let normals_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(concat!(
+                include_str!("line.wgsl"),
+                include_str!("normals.wgsl")
+            ))),
+        });
+
+        let pipeline = create_mesh_pipeline(
+            &device,
+            &mesh_pipeline_layout,
+            &shader,
+            swap_format,
+            "fs_main",
+            MeshBlend::Opaque,
+        );
+        let xray_pipeline = create_mesh_pipeline(
+            &device,
+            &mesh_pipeline_layout,
+            &shader,
+            swap_format,
+            "fs_xray",
+            MeshBlend::Xray,
+        );
+        let transparent_pipeline = create_mesh_pipeline(
+            &device,
+            &mesh_pipeline_layout,
+            &shader,
+            swap_format,
+            "fs_transparent",
+            MeshBlend::Transparent,
+        );
+        let points_pipeline =
+            create_points_pipeline(&device, &mesh_pipeline_layout, &points_shader, swap_format);
+        let normals_pipeline =
+            create_normals_pipeline(&device, &mesh_pipeline_layout, &normals_shader, swap_format);
+
+        let ofield_pipeline = create_ofield_pipeline(
+            &device,
+            &pipeline_layout,
+            &ofield_shader,
+            swap_format,
+            "fs_main",
+            true,
+            wgpu::CompareFunction::Less,
+        );
+        let ofield_hidden_pipeline = create_ofield_pipeline(
+            &device,
+            &pipeline_layout,
+            &ofield_shader,
+            swap_format,
+            "fs_hidden",
+            false,
+            wgpu::CompareFunction::Greater,
+        );
+
+        let wire_pipeline = create_line_pipeline(
+            &device,
+            &pipeline_layout,
+            &wire_shader,
+            swap_format,
+            "fs_main",
+        );
+        let streamline_pipeline = create_line_pipeline(
+            &device,
+            &pipeline_layout,
+            &wire_shader,
+            swap_format,
+            "fs_streamline",
+        );
+
+        let pick_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&mesh_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &pick_shader,
+                entry_point: "vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: 28 as wgpu::BufferAddress,
                     step_mode: wgpu::VertexStepMode::Vertex,
                     attributes: &[
                         wgpu::VertexAttribute {
@@ -218,17 +1374,22 @@ impl Renderer {
                             shader_location: 0,
                         },
                         wgpu::VertexAttribute {
-                            format: wgpu::VertexFormat::Float32x3,
+                            format: wgpu::VertexFormat::Uint32x3,
                             offset: 12,
                             shader_location: 1,
                         },
+                        wgpu::VertexAttribute {
+                            format: wgpu::VertexFormat::Uint32,
+                            offset: 24,
+                            shader_location: 2,
+                        },
                     ],
                 }],
             },
             fragment: Some(wgpu::FragmentState {
-                module: &shader,
+                module: &pick_shader,
                 entry_point: "fs_main",
-                targets: &[Some(swap_format.into())],
+                targets: &[Some(wgpu::TextureFormat::Rg32Uint.into())],
             }),
             primitive: wgpu::PrimitiveState {
                 cull_mode: Some(wgpu::Face::Back),
@@ -245,66 +1406,92 @@ impl Renderer {
             multiview: None,
         });
 
-        let ofield_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &ofield_shader,
-                entry_point: "vs_main",
-                buffers: &[wgpu::VertexBufferLayout {
-                    array_stride: 12 as wgpu::BufferAddress,
-                    step_mode: wgpu::VertexStepMode::Vertex,
-                    attributes: &[wgpu::VertexAttribute {
-                        format: wgpu::VertexFormat::Float32x3,
-                        offset: 0,
-                        shader_location: 0,
-                    }],
-                }],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &ofield_shader,
-                entry_point: "fs_main",
-                targets: &[Some(swap_format.into())],
-            }),
-            primitive: wgpu::PrimitiveState {
-                cull_mode: None,
-                ..Default::default()
+        // Picking renders only the pixel under the cursor, zoomed to fill a 1x1 target of the
+        // vertex and mesh hit
+        let pick_texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
             },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: wgpu::TextureFormat::Depth16Unorm,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState::default(),
-            multiview: None,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rg32Uint,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            label: Some("Picking target"),
+            view_formats: &[],
         });
+        let pick_view = pick_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let pick_depth_view = create_depth_texture(&device, 1, 1);
+
+        Ok(Self {
+            canvas: canvas.clone(),
+            handle,
+            device_lost,
+            context_lost_listener,
 
-        Self {
             instance,
             surface,
             adapter,
-            device,
+            device: Rc::new(device),
             queue,
             surface_config,
 
             uniforms,
             bind_group,
+            mesh_bind_group_layout,
             depth_view,
             pipeline,
+            xray_pipeline,
+            transparent_pipeline,
+            points_pipeline,
+            normals_pipeline,
             ofield_pipeline,
+            ofield_hidden_pipeline,
+            wire_pipeline,
+            streamline_pipeline,
+
+            pick_uniforms,
+            pick_bind_group,
+            pick_pipeline,
+            pick_texture,
+            pick_view,
+            pick_depth_view,
+
+            meshes: Vec::new(),
+            streaming_mesh: None,
+
+            ofield_instances: None,
+            ofield_see_through: false,
+            ofield_arm_length: None,
+            ofield_density: 0.05,
+            ofield_sampling: OfieldSampling::PerVertex,
+
+            wire_buffer: None,
+            streamline_buffer: None,
+            xray: false,
+            mesh_opacity: 1.0,
+            show_vertices: false,
+            show_normals: false,
+            normal_length: None,
+            line_width: DEFAULT_LINE_WIDTH,
 
-            buffers: None,
-            num_indices: 0,
-
-            ofield_buffers: None,
-            num_ofield_indices: 0,
-
-            mouse_down: false,
             rx: 0.0,
             ry: 0.0,
-        }
+            distance: 150.0,
+            near: 0.1,
+            far: 1000.0,
+            center: Vec3::ZERO,
+
+            bounds: None,
+            auto_frame: true,
+
+            srgb_surface: swap_format.describe().srgb,
+            gamma_correct: true,
+
+            needs_redraw: Cell::new(true),
+        })
     }
 
     /*
@@ -459,7 +1646,7 @@ impl Renderer {
                         let mut vertices = Vec::new();
                         let mut indices = Vec::new();
 
-                        let mut rng = SmallRng::seed_from_u64(0);
+                        let mut rng = rng::seeded_rng(0);
                         for (i, p) in p.iter().enumerate() {
                             if rng.gen::<f32>() > 0.95 {
                                 let n = n[i];
@@ -521,10 +1708,22 @@ impl Renderer {
 
     #[wasm_bindgen]
     pub fn draw(&self) {
-        let frame = self
-            .surface
-            .get_current_texture()
-            .expect("Failed to acquire next swap chain texture");
+        if self.device_lost() {
+            return;
+        }
+        let frame = match self.surface.get_current_texture() {
+            Ok(frame) => frame,
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                self.surface.configure(&self.device, &self.surface_config);
+                return;
+            }
+            Err(wgpu::SurfaceError::OutOfMemory) => {
+                log::error!("Out of memory acquiring the next frame");
+                self.device_lost.store(true, Ordering::Relaxed);
+                return;
+            }
+            Err(wgpu::SurfaceError::Timeout) => return,
+        };
         let view = frame
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
@@ -558,75 +1757,1497 @@ impl Renderer {
                 }),
             });
 
-            if let Some((vertex_buf, index_buf)) = self.buffers.as_ref() {
-                rpass.set_pipeline(&self.pipeline);
+            for mesh in self
+                .meshes
+                .iter()
+                .filter(|m| m.visible && m.layout().draws_surface())
+            {
+                rpass.set_pipeline(if self.xray {
+                    &self.xray_pipeline
+                } else if self.mesh_opacity < 1.0 {
+                    &self.transparent_pipeline
+                } else {
+                    &self.pipeline
+                });
                 rpass.set_bind_group(0, &self.bind_group, &[]);
-                rpass.set_index_buffer(index_buf.slice(..), wgpu::IndexFormat::Uint32);
-                rpass.set_vertex_buffer(0, vertex_buf.slice(..));
-                rpass.draw_indexed(0..self.num_indices as u32, 0, 0..1);
+                rpass.set_bind_group(1, &mesh.bind_group, &[]);
+                rpass.set_index_buffer(mesh.index_buf.buffer.slice(..), wgpu::IndexFormat::Uint32);
+                rpass.set_vertex_buffer(0, mesh.vertex_buf.buffer.slice(..));
+                rpass.set_vertex_buffer(1, mesh.color_buf.buffer.slice(..));
+                rpass.draw_indexed(0..mesh.num_indices, 0, 0..1);
             }
 
-            if let Some((vertex_buf, index_buf)) = self.ofield_buffers.as_ref() {
-                rpass.set_pipeline(&self.ofield_pipeline);
+            if self.show_vertices {
+                for mesh in self
+                    .meshes
+                    .iter()
+                    .filter(|m| m.visible && m.layout().draws_points())
+                {
+                    rpass.set_pipeline(&self.points_pipeline);
+                    rpass.set_bind_group(0, &self.bind_group, &[]);
+                    rpass.set_bind_group(1, &mesh.bind_group, &[]);
+                    rpass.set_vertex_buffer(0, mesh.vertex_buf.buffer.slice(..));
+                    rpass.set_vertex_buffer(1, mesh.color_buf.buffer.slice(..));
+                    rpass.draw(0..6, 0..mesh.num_vertices as u32);
+                }
+            }
+
+            if self.show_normals {
+                for mesh in self
+                    .meshes
+                    .iter()
+                    .filter(|m| m.visible && m.layout().draws_points())
+                {
+                    // Crease copies come after the mesh's own vertices, each with its own normal
+                    let num_drawn = mesh.num_vertices + mesh.split_source.len();
+                    rpass.set_pipeline(&self.normals_pipeline);
+                    rpass.set_bind_group(0, &self.bind_group, &[]);
+                    rpass.set_bind_group(1, &mesh.bind_group, &[]);
+                    rpass.set_vertex_buffer(0, mesh.vertex_buf.buffer.slice(..));
+                    rpass.draw(0..LINE_VERTICES, 0..num_drawn as u32);
+                }
+            }
+
+            if let Some((segment_buf, num_segments)) = self.wire_buffer.as_ref() {
+                rpass.set_pipeline(&self.wire_pipeline);
+                rpass.set_bind_group(0, &self.bind_group, &[]);
+                rpass.set_vertex_buffer(0, segment_buf.buffer.slice(..));
+                rpass.draw(0..LINE_VERTICES, 0..*num_segments);
+            }
+
+            if let Some((segment_buf, num_segments)) = self.streamline_buffer.as_ref() {
+                rpass.set_pipeline(&self.streamline_pipeline);
                 rpass.set_bind_group(0, &self.bind_group, &[]);
-                rpass.set_index_buffer(index_buf.slice(..), wgpu::IndexFormat::Uint32);
-                rpass.set_vertex_buffer(0, vertex_buf.slice(..));
-                rpass.draw_indexed(0..self.num_ofield_indices as u32, 0, 0..1);
+                rpass.set_vertex_buffer(0, segment_buf.buffer.slice(..));
+                rpass.draw(0..LINE_VERTICES, 0..*num_segments);
+            }
+
+            if let Some((instance_buf, num_instances)) = self.ofield_instances.as_ref() {
+                rpass.set_bind_group(0, &self.bind_group, &[]);
+                rpass.set_vertex_buffer(0, instance_buf.buffer.slice(..));
+                if self.ofield_see_through {
+                    rpass.set_pipeline(&self.ofield_hidden_pipeline);
+                    rpass.draw(0..OFIELD_CROSS_VERTICES, 0..*num_instances);
+                }
+                rpass.set_pipeline(&self.ofield_pipeline);
+                rpass.draw(0..OFIELD_CROSS_VERTICES, 0..*num_instances);
             }
         }
 
         self.queue.submit(Some(encoder.finish()));
         frame.present();
+        self.needs_redraw.set(false);
     }
 
+    /// Draw a frame only if something shown has changed since the last one, returning whether
+    /// it drew. Calling this every animation frame lets a static scene idle, while
+    /// [`Renderer::draw`] always draws.
+    #[wasm_bindgen]
+    pub fn draw_if_needed(&mut self) -> bool {
+        let needed = self.needs_redraw.get();
+        if needed {
+            self.draw();
+        }
+        needed
+    }
+
+    /// Whether the GPU device or the canvas's context has been lost, as can happen when the
+    /// browser reclaims memory or the driver resets. Nothing is drawn until
+    /// [`Renderer::recover`] is called, which on WebGL should wait for the canvas's
+    /// `webglcontextrestored` event.
+    #[wasm_bindgen(getter)]
+    pub fn device_lost(&self) -> bool {
+        self.device_lost.load(Ordering::Relaxed)
+    }
+
+    /// Rebuild the renderer on a new device after the old one was lost, uploading the meshes
+    /// and overlays again from the copies kept of them and keeping the camera and display
+    /// settings.
+    #[wasm_bindgen]
+    pub async fn recover(&mut self) -> Result<(), JsValue> {
+        let fresh = Self::with_handle(&self.canvas, self.handle).await?;
+        let mut old = mem::replace(self, fresh);
+
+        self.meshes = mem::take(&mut old.meshes);
+        for i in 0..self.meshes.len() {
+            let (transform_buf, bind_group) = self.mesh_binding(self.meshes[i].transform);
+            let mesh = &mut self.meshes[i];
+            mesh.vertex_buf.restore(&self.device);
+            mesh.color_buf.restore(&self.device);
+            mesh.index_buf.restore(&self.device);
+            mesh.pick_buf.restore(&self.device);
+            mesh.transform_buf = transform_buf;
+            mesh.bind_group = bind_group;
+        }
+        self.streaming_mesh = old.streaming_mesh;
+
+        self.ofield_instances = old.ofield_instances.take();
+        self.wire_buffer = old.wire_buffer.take();
+        self.streamline_buffer = old.streamline_buffer.take();
+        for (buffer, _) in [
+            &mut self.ofield_instances,
+            &mut self.wire_buffer,
+            &mut self.streamline_buffer,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            buffer.restore(&self.device);
+        }
+        self.ofield_see_through = old.ofield_see_through;
+        self.ofield_arm_length = old.ofield_arm_length;
+        self.ofield_density = old.ofield_density;
+        self.ofield_sampling = old.ofield_sampling;
+
+        self.xray = old.xray;
+        self.mesh_opacity = old.mesh_opacity;
+        self.show_vertices = old.show_vertices;
+        self.show_normals = old.show_normals;
+        self.normal_length = old.normal_length;
+        self.line_width = old.line_width;
+
+        self.rx = old.rx;
+        self.ry = old.ry;
+        self.distance = old.distance;
+        self.near = old.near;
+        self.far = old.far;
+        self.center = old.center;
+        self.bounds = old.bounds;
+        self.auto_frame = old.auto_frame;
+        self.gamma_correct = old.gamma_correct;
+
+        self.write_uniforms();
+        Ok(())
+    }
+
+    /// Replace every displayed mesh with `mesh`.
+    ///
+    /// If `mesh` has the same triangles as the only mesh currently shown, its index buffer is
+    /// kept and only the vertices are uploaded again.
     #[wasm_bindgen]
     pub fn update_mesh(&mut self, mesh: &InputMesh) {
-        // Assemble data in a more GPU-friendly manner
-        assert!(mesh.vertices.len() - 1 <= u32::MAX as usize);
-        let verts = mesh
-            .vertices
+        let hash = topology_hash(&mesh.tris);
+        let index_buf = if mesh.crease_angle.is_none() {
+            self.take_index_buf(hash, mesh.tris.len())
+        } else {
+            None
+        };
+
+        self.clear_meshes();
+        self.upload_mesh(mesh, hash, index_buf);
+        self.set_bounds(mesh.bounds());
+    }
+
+    /// Replace every displayed mesh with one given as flat arrays, such as typed arrays built in
+    /// JS, without going through [`InputMesh`]. `positions` and `normals` hold three coordinates
+    /// per vertex, with normals pointing into the surface like those of an [`InputMesh`], and
+    /// `indices` three vertices per triangle. The mesh is drawn smooth, without crease splitting.
+    ///
+    /// Like [`Renderer::update_mesh`], the index buffer is kept if the triangles are the same as
+    /// those of the only mesh currently shown.
+    #[wasm_bindgen]
+    pub fn update_mesh_raw(
+        &mut self,
+        positions: &[f32],
+        normals: &[f32],
+        indices: &[u32],
+    ) -> Result<(), JsValue> {
+        if positions.len() % 3 != 0 {
+            return Err("Positions must have three coordinates per vertex".into());
+        }
+        if normals.len() != positions.len() {
+            return Err("Normals must have three coordinates per vertex".into());
+        }
+        if indices.len() % 3 != 0 {
+            return Err("Indices must have three vertices per triangle".into());
+        }
+        let num_vertices = positions.len() / 3;
+        if let Some(i) = indices.iter().find(|i| **i as usize >= num_vertices) {
+            return Err(
+                format!("Index {} is out of range for {} vertices", i, num_vertices).into(),
+            );
+        }
+
+        let tris = indices
+            .chunks_exact(3)
+            .map(|t| [t[0] as usize, t[1] as usize, t[2] as usize])
+            .collect::<Vec<_>>();
+        let layout = MeshLayout::new(num_vertices, tris.len());
+        let hash = topology_hash(&tris);
+        let index_buf = self.take_index_buf(hash, tris.len());
+        self.clear_meshes();
+
+        let position = |v: usize| Vec3::from_slice(&positions[3 * v..3 * v + 3]);
+        let verts = (0..num_vertices)
+            .map(|v| {
+                [
+                    position(v).to_array(),
+                    Vec3::from_slice(&normals[3 * v..3 * v + 3]).to_array(),
+                ]
+            })
+            .collect::<Vec<MeshVertex>>();
+        let vertex_buf = RetainedBuffer::new(
+            &self.device,
+            "Mesh vertices",
+            bytemuck::cast_slice(verts.as_slice()),
+            wgpu::BufferUsages::VERTEX,
+        );
+        let index_buf = index_buf.unwrap_or_else(|| {
+            RetainedBuffer::new(
+                &self.device,
+                "Mesh indices",
+                bytemuck::cast_slice(indices),
+                wgpu::BufferUsages::INDEX,
+            )
+        });
+        let pick_buf = RetainedBuffer::new(
+            &self.device,
+            "Mesh picking vertices",
+            bytemuck::cast_slice(pick_vertices(&tris, position).as_slice()),
+            wgpu::BufferUsages::VERTEX,
+        );
+        self.push_mesh_entry(
+            vertex_buf,
+            index_buf,
+            pick_buf,
+            layout,
+            Some(hash),
+            Vec::new(),
+        );
+
+        self.set_bounds((num_vertices > 0).then(|| {
+            (1..num_vertices).fold((position(0), position(0)), |(min, max), v| {
+                (min.min(position(v)), max.max(position(v)))
+            })
+        }));
+        Ok(())
+    }
+
+    /// Replace every displayed mesh with `mesh` like [`Renderer::update_mesh`], taking it over
+    /// so that it is freed as soon as it is uploaded, rather than living on beside the GPU copy
+    /// until JS lets go of it.
+    #[wasm_bindgen]
+    pub fn update_mesh_owned(&mut self, mesh: InputMesh) {
+        self.update_mesh(&mesh);
+    }
+
+    /// Name of the graphics backend in use, such as `BrowserWebGpu` or `Gl` for WebGL2.
+    #[wasm_bindgen(getter)]
+    pub fn backend(&self) -> String {
+        format!("{:?}", self.adapter.get_info().backend)
+    }
+
+    /// Whether `update_mesh` should move the camera to fit the new mesh. Enabled by default.
+    #[wasm_bindgen]
+    pub fn set_auto_frame(&mut self, auto_frame: bool) {
+        self.auto_frame = auto_frame;
+    }
+
+    /// Upload `mesh` alongside the already displayed ones, returning its index.
+    #[wasm_bindgen]
+    pub fn add_mesh(&mut self, mesh: &InputMesh) -> usize {
+        self.upload_mesh(mesh, topology_hash(&mesh.tris), None)
+    }
+
+    /// Show part of a mesh that is still loading, as produced by [`InputMesh::load_progressive`].
+    /// The first chunk replaces every displayed mesh, and later ones fill it in. Once loading
+    /// finishes, pass the complete mesh to [`Renderer::update_mesh`] for final normals.
+    #[wasm_bindgen]
+    pub fn append_mesh_chunk(&mut self, chunk: &MeshChunk) {
+        if chunk.first_tri == 0 {
+            self.clear_meshes();
+
+            let capacity = MeshLayout::new(chunk.total_vertices, chunk.total_tris);
+            let vertex_buf = RetainedBuffer::zeroed(
+                &self.device,
+                "Mesh vertices",
+                capacity.vertex_bytes(),
+                wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            );
+            let index_buf = RetainedBuffer::zeroed(
+                &self.device,
+                "Mesh indices",
+                capacity.index_bytes(),
+                wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+            );
+            let pick_buf = RetainedBuffer::zeroed(
+                &self.device,
+                "Mesh picking vertices",
+                capacity.pick_bytes(),
+                wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            );
+            let index = self.push_mesh_entry(
+                vertex_buf,
+                index_buf,
+                pick_buf,
+                MeshLayout::new(chunk.total_vertices, 0),
+                None,
+                Vec::new(),
+            );
+            self.streaming_mesh = Some(index);
+            self.set_bounds(chunk.bounds);
+        }
+
+        let Some(mesh) = self.streaming_mesh.and_then(|i| self.meshes.get_mut(i)) else {
+            return;
+        };
+        let end_vertex = chunk.vertex_ids.last().map_or(0, |v| v + 1);
+        if end_vertex * 24 > mesh.vertex_buf.contents.len() {
+            log::warn!("Mesh chunk exceeds the declared vertex count, skipping it");
+            return;
+        }
+        // Polygons and strips triangulate into more triangles than the declared face count
+        let end_tri = chunk.first_tri + chunk.tris.len();
+        mesh.index_buf.reserve(&self.device, end_tri * 12);
+        mesh.pick_buf.reserve(&self.device, end_tri * 84);
+
+        // Write each run of consecutive vertices at once
+        let mut start = 0;
+        while start < chunk.vertex_ids.len() {
+            let first = chunk.vertex_ids[start];
+            let len = chunk.vertex_ids[start..]
+                .iter()
+                .zip(first..)
+                .take_while(|(v, expected)| **v == *expected)
+                .count();
+            let verts = interleaved_vertices(
+                &chunk.vertices[start..start + len],
+                &chunk.normals[start..start + len],
+            )
+            .collect::<Vec<_>>();
+            mesh.vertex_buf.write(
+                &self.queue,
+                first * 24,
+                bytemuck::cast_slice(verts.as_slice()),
+            );
+            start += len;
+        }
+
+        let indices = flat_indices(&chunk.tris).collect::<Vec<_>>();
+        // Every corner of the chunk's faces is among its vertices
+        let pick_verts = pick_vertices(&chunk.tris, |v| {
+            chunk
+                .vertex_ids
+                .binary_search(&v)
+                .map_or(Vec3::ZERO, |i| chunk.vertices[i])
+        });
+
+        mesh.index_buf.write(
+            &self.queue,
+            chunk.first_tri * 12,
+            bytemuck::cast_slice(indices.as_slice()),
+        );
+        mesh.pick_buf.write(
+            &self.queue,
+            chunk.first_tri * 84,
+            bytemuck::cast_slice(pick_verts.as_slice()),
+        );
+        mesh.num_indices = mesh.num_indices.max((end_tri * 3) as u32);
+        self.needs_redraw.set(true);
+    }
+
+    /// Remove the mesh at `index`. Meshes added after it shift down by one.
+    #[wasm_bindgen]
+    pub fn remove_mesh(&mut self, index: usize) {
+        if index < self.meshes.len() {
+            self.meshes.remove(index);
+            self.needs_redraw.set(true);
+            self.streaming_mesh = match self.streaming_mesh {
+                Some(i) if i == index => None,
+                Some(i) if i > index => Some(i - 1),
+                other => other,
+            };
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_meshes(&mut self) {
+        self.meshes.clear();
+        self.streaming_mesh = None;
+        self.needs_redraw.set(true);
+    }
+
+    /// Remove every mesh and overlay and return the camera to its initial state, leaving an
+    /// empty scene.
+    #[wasm_bindgen]
+    pub fn clear(&mut self) {
+        self.clear_meshes();
+        self.clear_ofield();
+        self.clear_quad_wireframe();
+        self.clear_streamlines();
+
+        self.bounds = None;
+        self.rx = 0.0;
+        self.ry = 0.0;
+        self.distance = 150.0;
+        self.near = 0.1;
+        self.far = 1000.0;
+        self.center = Vec3::ZERO;
+        self.write_uniforms();
+    }
+
+    #[wasm_bindgen]
+    pub fn set_mesh_visible(&mut self, index: usize, visible: bool) {
+        if let Some(mesh) = self.meshes.get_mut(index) {
+            mesh.visible = visible;
+            self.needs_redraw.set(true);
+        }
+    }
+
+    /// Place the mesh at `index` in the scene with a column-major 4x4 matrix, applied before
+    /// the camera rotation.
+    #[wasm_bindgen]
+    pub fn set_mesh_transform(&mut self, index: usize, transform: &[f32]) -> Result<(), JsValue> {
+        if transform.len() != 16 {
+            return Err("Mesh transform must have 16 elements".into());
+        }
+        let mesh = self
+            .meshes
+            .get_mut(index)
+            .ok_or_else(|| JsValue::from("Mesh index out of range"))?;
+
+        mesh.transform = Mat4::from_cols_slice(transform);
+        self.queue.write_buffer(
+            &mesh.transform_buf,
+            0,
+            bytemuck::cast_slice(&mesh_uniform_data(mesh.transform)),
+        );
+        self.needs_redraw.set(true);
+        Ok(())
+    }
+
+    /// Color the mesh at `index` per vertex, from packed `r, g, b` triples in `0..1`.
+    #[wasm_bindgen]
+    pub fn set_mesh_colors(&mut self, index: usize, colors: &[f32]) -> Result<(), JsValue> {
+        let mesh = self
+            .meshes
+            .get_mut(index)
+            .ok_or_else(|| JsValue::from("Mesh index out of range"))?;
+        if colors.len() != 3 * mesh.num_vertices {
+            return Err("Expected one RGB triple per vertex".into());
+        }
+
+        mesh.color_buf
+            .write(&self.queue, 0, bytemuck::cast_slice(colors));
+        if !mesh.split_source.is_empty() {
+            // Vertices split along creases take the color of the vertex they came from
+            let split_colors = mesh
+                .split_source
+                .iter()
+                .flat_map(|v| &colors[3 * *v as usize..3 * *v as usize + 3])
+                .copied()
+                .collect::<Vec<_>>();
+            mesh.color_buf.write(
+                &self.queue,
+                12 * mesh.num_vertices,
+                bytemuck::cast_slice(&split_colors),
+            );
+        }
+        self.needs_redraw.set(true);
+        Ok(())
+    }
+
+    /// Color the mesh at `index` per face, from packed `r, g, b` triples in `0..1`. Vertices shared
+    /// by faces of different colors are split, until the mesh is next uploaded.
+    #[wasm_bindgen]
+    pub fn set_face_colors(&mut self, index: usize, colors: &[f32]) -> Result<(), JsValue> {
+        let mesh = self
+            .meshes
+            .get_mut(index)
+            .ok_or_else(|| JsValue::from("Mesh index out of range"))?;
+        let num_indices = mesh.num_indices as usize;
+        if colors.len() != num_indices {
+            return Err("Expected one RGB triple per face".into());
+        }
+
+        // Start over from the vertices drawn before any earlier split by face color
+        let num_drawn = mesh.num_vertices + mesh.split_source.len() - mesh.face_split_from.len();
+        let indices = mesh.index_buf.contents[..4 * num_indices]
+            .chunks_exact(4)
+            .map(|v| match u32::from_ne_bytes(v.try_into().unwrap()) {
+                v if v as usize >= num_drawn => mesh.face_split_from[v as usize - num_drawn],
+                v => v,
+            })
+            .collect::<Vec<_>>();
+        let (indices, split_from, vertex_colors) =
+            split_face_colors(&indices, num_drawn, bytemuck::cast_slice(colors));
+
+        let stride = std::mem::size_of::<MeshVertex>();
+        let mut verts = mesh.vertex_buf.contents[..stride * num_drawn].to_vec();
+        mesh.split_source.truncate(num_drawn - mesh.num_vertices);
+        for v in &split_from {
+            let v = *v as usize;
+            verts.extend_from_within(stride * v..stride * (v + 1));
+            mesh.split_source
+                .push(match v.checked_sub(mesh.num_vertices) {
+                    Some(split) => mesh.split_source[split],
+                    None => v as u32,
+                });
+        }
+        let vertex_colors = vertex_colors
+            .into_iter()
+            .map(|c| c.unwrap_or(MESH_COLOR.to_array()))
+            .collect::<Vec<_>>();
+
+        mesh.vertex_buf = RetainedBuffer::new(
+            &self.device,
+            mesh.vertex_buf.label,
+            &verts,
+            mesh.vertex_buf.usage,
+        );
+        mesh.index_buf = RetainedBuffer::new(
+            &self.device,
+            mesh.index_buf.label,
+            bytemuck::cast_slice(&indices),
+            mesh.index_buf.usage,
+        );
+        mesh.color_buf = RetainedBuffer::new(
+            &self.device,
+            mesh.color_buf.label,
+            bytemuck::cast_slice(&vertex_colors),
+            mesh.color_buf.usage,
+        );
+        mesh.face_split_from = split_from;
+        // The triangles now refer to split vertices, so they can't be reused by a later upload
+        mesh.topology_hash = None;
+        self.needs_redraw.set(true);
+        Ok(())
+    }
+
+    /// Return the mesh at `index` to the default flat color.
+    #[wasm_bindgen]
+    pub fn reset_mesh_colors(&mut self, index: usize) -> Result<(), JsValue> {
+        let num_vertices = self.meshes.get(index).map_or(0, |m| m.num_vertices);
+        let colors = MESH_COLOR.to_array().repeat(num_vertices);
+        self.set_mesh_colors(index, &colors)
+    }
+
+    /// Color the mesh at `index` by vertex valence, with regular vertices in gray, lower valences
+    /// in blue and higher in red. `diagnostics` must have been built from the same mesh.
+    #[wasm_bindgen]
+    pub fn color_by_valence(
+        &mut self,
+        index: usize,
+        diagnostics: &MeshDiagnostics,
+    ) -> Result<(), JsValue> {
+        let colors = diagnostics
+            .mesh
+            .valences()
+            .into_iter()
+            .flat_map(|v| valence_color(v).to_array())
+            .collect::<Vec<_>>();
+        self.set_mesh_colors(index, &colors)
+    }
+
+    /// Color the mesh at `index` by triangle quality, as from [`ProcessMesh::triangle_quality`],
+    /// with well-shaped triangles in gray and slivers in red. `diagnostics` must have been built
+    /// from the same mesh.
+    #[wasm_bindgen]
+    pub fn color_by_quality(
+        &mut self,
+        index: usize,
+        diagnostics: &MeshDiagnostics,
+    ) -> Result<(), JsValue> {
+        let colors = diagnostics
+            .mesh
+            .triangle_quality()
+            .into_iter()
+            .flat_map(|q| scalar_color(1.0 - q).to_array())
+            .collect::<Vec<_>>();
+        self.set_face_colors(index, &colors)
+    }
+
+    /// Color the mesh at `index` by one value per vertex, such as
+    /// [`FieldSolver::residuals`], scaled so the smallest is gray and the largest red. Vertices
+    /// with NaN or infinite values keep the default color.
+    #[wasm_bindgen]
+    pub fn color_by_scalar(&mut self, index: usize, values: &[f32]) -> Result<(), JsValue> {
+        let (min, max) = values
             .iter()
-            .zip(&mesh.normals)
-            .map(|(v, n)| [v.to_array(), n.to_array()])
+            .filter(|v| v.is_finite())
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), v| {
+                (min.min(*v), max.max(*v))
+            });
+        let range = (max - min).max(f32::EPSILON);
+        let colors = values
+            .iter()
+            .flat_map(|v| {
+                if v.is_finite() {
+                    scalar_color((v - min) / range).to_array()
+                } else {
+                    MESH_COLOR.to_array()
+                }
+            })
             .collect::<Vec<_>>();
-        let indices = mesh
-            .tris
+        self.set_mesh_colors(index, &colors)
+    }
+
+    /// Color the mesh at `index` by the material ids of `mesh`, which must be the mesh shown
+    /// there, from a categorical palette. Meshes without material ids keep the default color.
+    #[wasm_bindgen]
+    pub fn color_by_material(&mut self, index: usize, mesh: &InputMesh) -> Result<(), JsValue> {
+        if mesh.material_ids.is_empty() {
+            return self.reset_mesh_colors(index);
+        }
+
+        let colors = mesh
+            .material_ids
             .iter()
-            .map(|[a, b, c]| [*a as u32, *b as u32, *c as u32]) // necessary?
+            .flat_map(|id| MATERIAL_PALETTE[*id as usize % MATERIAL_PALETTE.len()].to_array())
             .collect::<Vec<_>>();
+        self.set_face_colors(index, &colors)
+    }
 
-        self.buffers = Some((
-            self.device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("Mesh vertices"),
-                    contents: bytemuck::cast_slice(verts.as_slice()),
-                    usage: wgpu::BufferUsages::VERTEX,
-                }),
-            self.device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("Mesh indices"),
-                    contents: bytemuck::cast_slice(indices.as_slice()),
-                    usage: wgpu::BufferUsages::INDEX,
-                }),
-        ));
-        self.num_indices = (mesh.tris.len() * 3) as u32;
+    /// Color the mesh at `index` by which vertex of hierarchy level `level` of `solver`, counted
+    /// from the coarsest, each of its vertices was merged into, so that every cluster gets a
+    /// color of its own. `solver` must have been built from the mesh shown there.
+    #[wasm_bindgen]
+    pub fn show_hierarchy_level(
+        &mut self,
+        index: usize,
+        solver: &FieldSolver,
+        level: usize,
+    ) -> Result<(), JsValue> {
+        let hierarchy = solver.hierarchy();
+        if level >= hierarchy.len() {
+            return Err("Hierarchy level out of range".into());
+        }
+        let colors = hierarchy::clusters(hierarchy, level)
+            .into_iter()
+            .flat_map(|c| cluster_color(c).to_array())
+            .collect::<Vec<_>>();
+        self.set_mesh_colors(index, &colors)
+    }
+
+    #[wasm_bindgen]
+    pub fn mesh_transform(&self, index: usize) -> Option<Vec<f32>> {
+        self.meshes
+            .get(index)
+            .map(|m| m.transform.to_cols_array().to_vec())
+    }
+
+    #[wasm_bindgen]
+    pub fn num_meshes(&self) -> usize {
+        self.meshes.len()
+    }
+
+    /// Show the solved field of `solver` as crosses over a sample of its vertices.
+    #[wasm_bindgen]
+    pub fn update_ofield(&mut self, solver: &FieldSolver) {
+        self.upload_ofield(solver.mesh(), solver.o_field());
+    }
+
+    /// Re-solve the field of `solver` with `iterations` sweeps per level, as from
+    /// [`FieldSolver::smooth`], and show the result in place of the current field.
+    #[wasm_bindgen]
+    pub fn resmooth_ofield(&mut self, solver: &mut FieldSolver, iterations: usize) {
+        solver.smooth(iterations);
+        self.update_ofield(solver);
+    }
+
+    /// Show the field of `smoother` as it stands, for animating its convergence.
+    #[wasm_bindgen]
+    pub fn update_ofield_progress(&mut self, smoother: &Smoother) {
+        self.upload_ofield(smoother.mesh(), &smoother.fine_field());
+    }
+
+    /// Outline the faces of `quads` as lines, showing the quad structure rather than its
+    /// triangulation.
+    #[wasm_bindgen]
+    pub fn update_quad_wireframe(&mut self, quads: &QuadMesh) {
+        self.wire_buffer = self.line_buffer("Wireframe vertices", &quad_wire_vertices(quads));
+        self.needs_redraw.set(true);
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_quad_wireframe(&mut self) {
+        self.wire_buffer = None;
+        self.needs_redraw.set(true);
+    }
+
+    /// Draw `count` streamlines of the solved field of `solver`, integrated in steps of length
+    /// `step`, as from [`orientation::trace_streamlines`].
+    #[wasm_bindgen]
+    pub fn update_streamlines(&mut self, solver: &FieldSolver, count: usize, step: f32) {
+        let mesh = solver.mesh();
+        let lines = orientation::trace_streamlines_on_faces(mesh, solver.o_field(), count, step);
+        self.streamline_buffer =
+            self.line_buffer("Streamline vertices", &streamline_vertices(mesh, &lines));
+        self.needs_redraw.set(true);
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_streamlines(&mut self) {
+        self.streamline_buffer = None;
+        self.needs_redraw.set(true);
+    }
+
+    /// Whether shaded colors are gamma-correct, encoded to sRGB by the surface or else by the
+    /// shader. Disable to write the raw linear lighting result. Enabled by default.
+    #[wasm_bindgen]
+    pub fn set_gamma_correct(&mut self, gamma_correct: bool) {
+        self.gamma_correct = gamma_correct;
+        self.write_uniforms();
+    }
+
+    /// Draw meshes translucent and without depth testing, showing every layer of the surface at
+    /// once.
+    #[wasm_bindgen]
+    pub fn set_xray(&mut self, on: bool) {
+        self.xray = on;
+        self.needs_redraw.set(true);
+    }
+
+    /// Draw a dot at every vertex on top of the surface, darkened from the vertex colors so
+    /// that [`Renderer::color_by_valence`] shows through. Off by default.
+    #[wasm_bindgen]
+    pub fn set_show_vertices(&mut self, on: bool) {
+        self.show_vertices = on;
+        self.needs_redraw.set(true);
+    }
+
+    /// Draw each vertex normal as a short line out of the surface, showing flipped or noisy
+    /// normals directly. Vertices split along creases draw a line for each of their normals. Off
+    /// by default.
+    #[wasm_bindgen]
+    pub fn set_show_normals(&mut self, on: bool) {
+        self.show_normals = on;
+        self.needs_redraw.set(true);
+    }
+
+    /// Fix the length of the normals overlay lines, or size them to 2% of the diagonal of the
+    /// mesh bounds if `None`.
+    #[wasm_bindgen]
+    pub fn set_normal_length(&mut self, length: Option<f32>) -> Result<(), JsValue> {
+        if length.map_or(false, |l| !l.is_finite() || l <= 0.0) {
+            return Err("Normal length must be positive".into());
+        }
+
+        self.normal_length = length;
+        self.write_uniforms();
+        Ok(())
+    }
+
+    /// Draw the wireframe, streamline and normals overlay lines `width` pixels wide, with their
+    /// edges blended over a further pixel. 1.5 by default.
+    #[wasm_bindgen]
+    pub fn set_line_width(&mut self, width: f32) -> Result<(), JsValue> {
+        if !width.is_finite() || width <= 0.0 {
+            return Err("Line width must be positive".into());
+        }
+
+        self.line_width = width;
+        self.write_uniforms();
+        Ok(())
+    }
+
+    /// Draw meshes with the given opacity, clamped to `[0, 1]`, so the orientation field shows
+    /// through the surface. Below 1, faces are blended in the order they are stored rather than
+    /// sorted back to front, so overlapping layers of anything but a convex mesh only blend
+    /// approximately. Opaque by default.
+    #[wasm_bindgen]
+    pub fn set_mesh_opacity(&mut self, alpha: f32) {
+        self.mesh_opacity = if alpha.is_nan() {
+            1.0
+        } else {
+            alpha.clamp(0.0, 1.0)
+        };
+        self.write_uniforms();
+    }
+
+    /// Fix the half-length of the orientation field cross arms, or size them to 1.5 times the
+    /// mean edge length if `None`. Takes effect at the next [`Renderer::update_ofield`].
+    #[wasm_bindgen]
+    pub fn set_ofield_arm_length(&mut self, arm_length: Option<f32>) -> Result<(), JsValue> {
+        if arm_length.map_or(false, |l| !l.is_finite() || l <= 0.0) {
+            return Err("Arm length must be positive".into());
+        }
+
+        self.ofield_arm_length = arm_length;
+        Ok(())
+    }
+
+    /// Draw crosses at about this fraction of the vertices, between 0 and 1. Defaults to 0.05.
+    /// Takes effect at the next [`Renderer::update_ofield`].
+    #[wasm_bindgen]
+    pub fn set_ofield_density(&mut self, density: f32) -> Result<(), JsValue> {
+        if !(0.0..=1.0).contains(&density) {
+            return Err("Density must be between 0 and 1".into());
+        }
+
+        self.ofield_density = density;
+        Ok(())
+    }
+
+    /// How to pick the vertices that get a cross, at the density set with
+    /// [`Renderer::set_ofield_density`]. Sampling by area keeps the expected number of crosses,
+    /// except that vertices with more than `1 / density` times the mean dual area can't get more
+    /// than one. Takes effect at the next [`Renderer::update_ofield`]. Per vertex by default.
+    #[wasm_bindgen]
+    pub fn set_ofield_sampling(&mut self, sampling: OfieldSampling) {
+        self.ofield_sampling = sampling;
+    }
+
+    /// Whether crosses hidden behind the surface are drawn faintly through it.
+    #[wasm_bindgen]
+    pub fn set_ofield_see_through(&mut self, see_through: bool) {
+        self.ofield_see_through = see_through;
+        self.needs_redraw.set(true);
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_ofield(&mut self) {
+        self.ofield_instances = None;
+        self.needs_redraw.set(true);
+    }
+
+    /// Resolves to the [`Pick`] of the vertex nearest to canvas pixel `(x, y)` on the frontmost
+    /// visible mesh, or to `undefined` over the background.
+    ///
+    /// The pixel is read back once the GPU has drawn it, which takes a frame or so, but the
+    /// renderer is free to be used in the meantime.
+    #[wasm_bindgen]
+    pub fn pick(&self, x: f32, y: f32) -> js_sys::Promise {
+        let readback = self.submit_pick(x, y);
+        let device = self.device.clone();
+        wasm_bindgen_futures::future_to_promise(async move {
+            let pick = match readback {
+                Some(readback) => read_pick(&device, &readback).await,
+                None => None,
+            };
+            Ok(pick.map_or(JsValue::UNDEFINED, JsValue::from))
+        })
+    }
+
+    /// World-space distance between two picked vertices, after each mesh's own transform, or
+    /// `None` if either mesh or vertex no longer exists.
+    #[wasm_bindgen]
+    pub fn measure_distance(&self, start: &Pick, end: &Pick) -> Option<f32> {
+        Some(
+            self.pick_position(start)?
+                .distance(self.pick_position(end)?),
+        )
+    }
+
+    /// Width, depth and height of the bounds of the displayed meshes, as `[x, y, z]`, or `None`
+    /// if nothing is shown.
+    #[wasm_bindgen]
+    pub fn bounds_size(&self) -> Option<Vec<f32>> {
+        self.bounds
+            .map(|(min, max)| (max - min).to_array().to_vec())
+    }
+
+    /// Override the camera's near and far clip distances. They are derived from the mesh bounding
+    /// sphere whenever a mesh is framed, which replaces this setting.
+    #[wasm_bindgen]
+    pub fn set_clip_planes(&mut self, near: f32, far: f32) -> Result<(), JsValue> {
+        if !(0.0 < near && near < far) {
+            return Err("Clip planes must satisfy 0 < near < far".into());
+        }
+
+        self.near = near;
+        self.far = far;
+        self.write_uniforms();
+        Ok(())
+    }
+
+    /// The full camera state as `[rx, ry, distance, center_x, center_y, center_z, near, far]`,
+    /// for restoring later with [`Renderer::set_camera_state`].
+    #[wasm_bindgen]
+    pub fn camera_state(&self) -> Vec<f32> {
+        vec![
+            self.rx,
+            self.ry,
+            self.distance,
+            self.center.x,
+            self.center.y,
+            self.center.z,
+            self.near,
+            self.far,
+        ]
+    }
+
+    #[wasm_bindgen]
+    pub fn set_camera_state(&mut self, state: &[f32]) -> Result<(), JsValue> {
+        let &[rx, ry, distance, cx, cy, cz, near, far] = state else {
+            return Err("Camera state must have 8 elements".into());
+        };
+        if !(0.0 < near && near < far) {
+            return Err("Camera near and far planes must satisfy 0 < near < far".into());
+        }
+
+        self.rx = rx;
+        self.ry = ry;
+        self.distance = distance;
+        self.center = vec3(cx, cy, cz);
+        self.near = near;
+        self.far = far;
+        self.write_uniforms();
+        Ok(())
     }
 
     #[wasm_bindgen]
     pub fn orbit_camera(&mut self, dx: f32, dy: f32) {
         self.rx += dx / 200.0;
         self.ry -= dy / 200.0;
+        self.write_uniforms();
+    }
 
-        let view_transform =
-            create_view_transform(self.surface_config.width, self.surface_config.height);
-        let model_transform = create_model_transform(self.rx, self.ry);
+    /// Set the model rotation directly, in radians: `rx` turns the mesh about its Z axis and `ry`
+    /// then tilts it toward or away from the camera. These are the same angles that
+    /// [`Renderer::orbit_camera`] accumulates.
+    #[wasm_bindgen]
+    pub fn set_orientation(&mut self, rx: f32, ry: f32) {
+        self.rx = rx;
+        self.ry = ry;
+        self.write_uniforms();
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn rx(&self) -> f32 {
+        self.rx
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn ry(&self) -> f32 {
+        self.ry
+    }
+
+    /// Look at the mesh from its -Y side, with +X to the right and +Z up.
+    #[wasm_bindgen]
+    pub fn view_front(&mut self) {
+        self.set_orientation(PI, 0.0);
+    }
+
+    /// Look down on the mesh from +Z, with +X to the right and +Y up.
+    #[wasm_bindgen]
+    pub fn view_top(&mut self) {
+        self.set_orientation(PI, -FRAC_PI_2);
+    }
+
+    /// Look at the mesh from its +X side, with +Y to the right and +Z up.
+    #[wasm_bindgen]
+    pub fn view_side(&mut self) {
+        self.set_orientation(FRAC_PI_2, 0.0);
+    }
+}
+
+impl Drop for Renderer {
+    fn drop(&mut self) {
+        let _ = self.canvas.remove_event_listener_with_callback(
+            "webglcontextlost",
+            self.context_lost_listener.as_ref().unchecked_ref(),
+        );
+    }
+}
+
+impl Renderer {
+    /// Take the index buffer of the only mesh shown if it holds the `num_tris` triangles hashing
+    /// to `hash`. The counts are checked as well, so that a hash collision between meshes of
+    /// different sizes can't leave the buffer short of the new triangles.
+    fn take_index_buf(&mut self, hash: u64, num_tris: usize) -> Option<RetainedBuffer> {
+        match self.meshes.as_slice() {
+            [entry]
+                if entry.topology_hash == Some(hash)
+                    && entry.num_indices as usize == 3 * num_tris
+                    && entry.index_buf.contents.len() == num_tris * 12 =>
+            {
+                self.meshes.pop().map(|entry| entry.index_buf)
+            }
+            _ => None,
+        }
+    }
+
+    /// Upload `mesh` as a new entry, reusing `index_buf` if given, which must hold the same
+    /// triangles.
+    fn upload_mesh(
+        &mut self,
+        mesh: &InputMesh,
+        topology_hash: u64,
+        index_buf: Option<RetainedBuffer>,
+    ) -> usize {
+        // Write the GPU layout straight into mapped buffers rather than assembling it first,
+        // which would hold a second copy of the mesh at the peak of the upload
+        let layout = MeshLayout::new(mesh.vertices.len(), mesh.tris.len());
+        let (vertex_buf, index_buf, topology_hash, split_source) = match mesh.corner_normals() {
+            // Split vertices are numbered differently, so their triangles always go up afresh
+            Some(corner_normals) => {
+                let (verts, tris, split_source) = split_creases(mesh, &corner_normals);
+                let vertex_buf = RetainedBuffer::from_iter(
+                    &self.device,
+                    "Mesh vertices",
+                    verts.len(),
+                    wgpu::BufferUsages::VERTEX,
+                    verts,
+                );
+                let index_buf = RetainedBuffer::from_iter(
+                    &self.device,
+                    "Mesh indices",
+                    tris.len(),
+                    wgpu::BufferUsages::INDEX,
+                    tris,
+                );
+                (vertex_buf, index_buf, None, split_source)
+            }
+            None => {
+                let vertex_buf = RetainedBuffer::from_iter(
+                    &self.device,
+                    "Mesh vertices",
+                    layout.num_vertices,
+                    wgpu::BufferUsages::VERTEX,
+                    interleaved_vertices(&mesh.vertices, &mesh.normals),
+                );
+                let index_buf = index_buf.unwrap_or_else(|| {
+                    RetainedBuffer::from_iter(
+                        &self.device,
+                        "Mesh indices",
+                        mesh.tris.len(),
+                        wgpu::BufferUsages::INDEX,
+                        flat_indices(&mesh.tris),
+                    )
+                });
+                (vertex_buf, index_buf, Some(topology_hash), Vec::new())
+            }
+        };
+        let pick_buf = RetainedBuffer::from_iter(
+            &self.device,
+            "Mesh picking vertices",
+            layout.num_indices as usize,
+            wgpu::BufferUsages::VERTEX,
+            mesh.tris
+                .iter()
+                .flat_map(|tri| pick_triangle(*tri, |v| mesh.vertices[v])),
+        );
+        self.push_mesh_entry(
+            vertex_buf,
+            index_buf,
+            pick_buf,
+            layout,
+            topology_hash,
+            split_source,
+        )
+    }
+
+    /// Register a mesh whose geometry buffers are already created, with a default color,
+    /// identity transform, and visible. Returns its index.
+    fn push_mesh_entry(
+        &mut self,
+        vertex_buf: RetainedBuffer,
+        index_buf: RetainedBuffer,
+        pick_buf: RetainedBuffer,
+        layout: MeshLayout,
+        topology_hash: Option<u64>,
+        split_source: Vec<u32>,
+    ) -> usize {
+        let transform = Mat4::IDENTITY;
+        let (transform_buf, bind_group) = self.mesh_binding(transform);
+
+        self.needs_redraw.set(true);
+        self.meshes.push(MeshEntry {
+            vertex_buf,
+            color_buf: RetainedBuffer::new(
+                &self.device,
+                "Mesh colors",
+                bytemuck::cast_slice(
+                    vec![MESH_COLOR.to_array(); layout.num_vertices + split_source.len()]
+                        .as_slice(),
+                ),
+                wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            ),
+            num_vertices: layout.num_vertices,
+            index_buf,
+            num_indices: layout.num_indices,
+            topology_hash,
+            split_source,
+            face_split_from: Vec::new(),
+            pick_buf,
+            visible: true,
+
+            transform,
+            transform_buf,
+            bind_group,
+        });
+        self.meshes.len() - 1
+    }
+
+    /// Uniform buffer holding a mesh's `transform` and normal transform, with room for its index,
+    /// and the bind group using it.
+    fn mesh_binding(&self, transform: Mat4) -> (wgpu::Buffer, wgpu::BindGroup) {
+        let transform_buf = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Mesh transform"),
+            size: MESH_UNIFORMS_SIZE,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
         self.queue.write_buffer(
-            &self.uniforms,
+            &transform_buf,
+            0,
+            bytemuck::cast_slice(&mesh_uniform_data(transform)),
+        );
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.mesh_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: transform_buf.as_entire_binding(),
+            }],
+        });
+        (transform_buf, bind_group)
+    }
+
+    /// Store the bounds of the displayed meshes, reframing the camera to them if auto-framing is
+    /// on.
+    fn set_bounds(&mut self, bounds: Option<(Vec3, Vec3)>) {
+        self.bounds = bounds;
+        if self.auto_frame {
+            self.frame_bounds();
+        } else {
+            // Resize the normals overlay to the new bounds
+            self.write_uniforms();
+        }
+    }
+
+    /// Draw the vertex and mesh ids under canvas pixel `(x, y)` and queue copying them to a buffer
+    /// for [`read_pick`], or `None` if the pixel is off the canvas.
+    fn submit_pick(&self, x: f32, y: f32) -> Option<wgpu::Buffer> {
+        let width = self.surface_config.width as f32;
+        let height = self.surface_config.height as f32;
+        if !(0.0..width).contains(&x) || !(0.0..height).contains(&y) {
+            return None;
+        }
+
+        // Scale clip space so the picked pixel covers the whole 1x1 target
+        let ndc_x = 2.0 * (x + 0.5) / width - 1.0;
+        let ndc_y = 1.0 - 2.0 * (y + 0.5) / height;
+        let pick_transform = Mat4::from_cols(
+            glam::vec4(width, 0.0, 0.0, 0.0),
+            glam::vec4(0.0, height, 0.0, 0.0),
+            glam::vec4(0.0, 0.0, 1.0, 0.0),
+            glam::vec4(-width * ndc_x, -height * ndc_y, 0.0, 1.0),
+        );
+        let view_transform = pick_transform
+            * create_view_transform(
+                self.surface_config.width,
+                self.surface_config.height,
+                self.distance,
+                self.near,
+                self.far,
+            );
+        let model_transform = create_model_transform(self.rx, self.ry, self.center);
+        self.queue.write_buffer(
+            &self.pick_uniforms,
             0,
             bytemuck::cast_slice(&[
                 view_transform.to_cols_array(),
                 model_transform.to_cols_array(),
             ]),
         );
+
+        // Meshes shift down when one is removed, so their indices are only written when needed
+        for (i, mesh) in self.meshes.iter().enumerate() {
+            self.queue.write_buffer(
+                &mesh.transform_buf,
+                MESH_INDEX_OFFSET,
+                &(i as u32).to_ne_bytes(),
+            );
+        }
+
+        let readback = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Picking readback"),
+            size: wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.pick_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.pick_depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: false,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+
+            for mesh in self
+                .meshes
+                .iter()
+                .filter(|m| m.visible && m.layout().draws_surface())
+            {
+                rpass.set_pipeline(&self.pick_pipeline);
+                rpass.set_bind_group(0, &self.pick_bind_group, &[]);
+                rpass.set_bind_group(1, &mesh.bind_group, &[]);
+                rpass.set_vertex_buffer(0, mesh.pick_buf.buffer.slice(..));
+                rpass.draw(0..mesh.num_indices, 0..1);
+            }
+        }
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.pick_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &readback,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        Some(readback)
+    }
+
+    /// World-space position of the vertex of `pick`, if its mesh still has it.
+    fn pick_position(&self, pick: &Pick) -> Option<Vec3> {
+        mesh_vertex_position(self.meshes.get(pick.mesh)?, pick.vertex)
+    }
+
+    /// Center the camera on the stored mesh bounds and pull it back far enough to see all of it
+    /// from any orbit angle.
+    fn frame_bounds(&mut self) {
+        let Some((min, max)) = self.bounds else {
+            return;
+        };
+
+        let radius = ((max - min).length() * 0.5).max(f32::EPSILON);
+        self.center = (min + max) * 0.5;
+        self.distance = 1.1 * radius / (0.5 * FOV.to_radians()).sin();
+        self.near = 0.9 * (self.distance - radius);
+        self.far = 1.1 * (self.distance + radius);
+        self.write_uniforms();
+    }
+
+    /// Buffer of line segments between consecutive pairs of `vertices`, with the number of
+    /// segments, or `None` when there are none to draw.
+    fn line_buffer(
+        &self,
+        label: &'static str,
+        vertices: &[[f32; 3]],
+    ) -> Option<(RetainedBuffer, u32)> {
+        if vertices.is_empty() {
+            return None;
+        }
+        Some((
+            RetainedBuffer::new(
+                &self.device,
+                label,
+                bytemuck::cast_slice(vertices),
+                wgpu::BufferUsages::VERTEX,
+            ),
+            (vertices.len() / 2) as u32,
+        ))
+    }
+
+    fn upload_ofield(&mut self, mesh: &ProcessMesh, o_field: &[Vec3]) {
+        // Size the crosses to the mesh so they read the same at any scale
+        let (edge_sum, edge_count) = mesh
+            .adjacency_face
+            .iter()
+            .enumerate()
+            .flat_map(|(i, adj)| adj.iter().map(move |(j, _)| (i, *j)))
+            .fold((0.0, 0), |(sum, count), (i, j)| {
+                (
+                    sum + (mesh.vertices[i] - mesh.vertices[j]).length(),
+                    count + 1,
+                )
+            });
+        let arm = self
+            .ofield_arm_length
+            .unwrap_or(1.5 * edge_sum / (edge_count.max(1) as f32));
+
+        // Only the sampled vertices are uploaded; the vertex shader builds each cross from its
+        // position, arm length, o and normal, colored by axis so that the field's orientation
+        // reads at a glance
+        let mut instances = Vec::new();
+
+        let mean_area = mesh.dual_area.iter().sum::<f32>() / mesh.dual_area.len().max(1) as f32;
+        let mut rng = rng::seeded_rng(0);
+        for (i, p) in mesh.vertices.iter().enumerate() {
+            let probability = match self.ofield_sampling {
+                OfieldSampling::PerVertex => self.ofield_density,
+                OfieldSampling::ByArea if mean_area > 0.0 => {
+                    self.ofield_density * mesh.dual_area[i] / mean_area
+                }
+                OfieldSampling::ByArea => self.ofield_density,
+            };
+            if rng.gen::<f32>() < probability {
+                let (o, n) = (o_field[i], mesh.normals[i]);
+                instances.push([p.x, p.y, p.z, arm, o.x, o.y, o.z, n.x, n.y, n.z]);
+            }
+        }
+
+        self.needs_redraw.set(true);
+        if instances.is_empty() {
+            self.ofield_instances = None;
+            return;
+        }
+        self.ofield_instances = Some((
+            RetainedBuffer::new(
+                &self.device,
+                "Ofield instances",
+                bytemuck::cast_slice(instances.as_slice()),
+                wgpu::BufferUsages::VERTEX,
+            ),
+            instances.len() as u32,
+        ));
+    }
+
+    fn write_uniforms(&self) {
+        let view_transform = create_view_transform(
+            self.surface_config.width,
+            self.surface_config.height,
+            self.distance,
+            self.near,
+            self.far,
+        );
+        let model_transform = create_model_transform(self.rx, self.ry, self.center);
+        self.queue.write_buffer(
+            &self.uniforms,
+            0,
+            bytemuck::cast_slice(&uniform_data(
+                view_transform,
+                model_transform,
+                output_transfer(self.srgb_surface, self.gamma_correct),
+                self.mesh_opacity,
+                point_size(self.surface_config.width, self.surface_config.height),
+                self.normal_length.unwrap_or_else(|| {
+                    self.bounds
+                        .map_or(0.0, |(min, max)| NORMAL_LENGTH_SCALE * min.distance(max))
+                }),
+                [
+                    self.line_width,
+                    self.surface_config.width as f32,
+                    self.surface_config.height as f32,
+                ],
+                eye_position(self.distance),
+            )),
+        );
+        self.needs_redraw.set(true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_mesh_uploads_empty_buffers_and_draws_nothing() {
+        let empty = MeshLayout::new(0, 0);
+        assert_eq!(
+            (
+                empty.vertex_bytes(),
+                empty.index_bytes(),
+                empty.pick_bytes()
+            ),
+            (0, 0, 0)
+        );
+        assert!(!empty.draws_surface() && !empty.draws_points());
+
+        let points = MeshLayout::new(5, 0);
+        assert!(!points.draws_surface() && points.draws_points());
+
+        let triangle = MeshLayout::new(3, 1);
+        assert_eq!(
+            (
+                triangle.vertex_bytes(),
+                triangle.index_bytes(),
+                triangle.pick_bytes()
+            ),
+            (72, 12, 84)
+        );
+        assert!(triangle.draws_surface() && triangle.draws_points());
+
+        // Index u32::MAX is still addressable
+        #[cfg(target_pointer_width = "64")]
+        MeshLayout::new(u32::MAX as usize + 1, 0);
+    }
+
+    #[test]
+    fn empty_meshes_have_no_lines_to_draw() {
+        let quads = QuadMesh {
+            vertices: Vec::new(),
+            normals: Vec::new(),
+            quads: Vec::new(),
+            tris: Vec::new(),
+        };
+        assert!(quad_wire_vertices(&quads).is_empty());
+
+        let mesh = ProcessMesh::from(InputMesh::from_triangles(Vec::new(), Vec::new()));
+        let lines = orientation::trace_streamlines_on_faces(&mesh, &[], 10, 0.1);
+        assert!(streamline_vertices(&mesh, &lines).is_empty());
+    }
+
+    #[test]
+    fn crease_split_cube_has_a_vertex_per_side_at_each_corner() {
+        let mut cube = crate::shapes::cube();
+        cube.crease_angle = Some(30.0);
+        let corner_normals = cube.corner_normals().unwrap();
+        let (verts, tris, split_source) = split_creases(&cube, &corner_normals);
+
+        assert_eq!(verts.len(), 24);
+        assert_eq!(split_source.len(), 16);
+        for (copy, source) in verts[8..].iter().zip(&split_source) {
+            assert_eq!(copy[0], verts[*source as usize][0]);
+        }
+        // Every triangle is drawn flat, with its own face normal at all three corners
+        for tri in &tris {
+            let [a, b, c] = tri.map(|v| Vec3::from(verts[v as usize][0]));
+            let normal = face_normal(a, b, c).to_array();
+            assert!(tri.iter().all(|v| verts[*v as usize][1] == normal));
+        }
+
+        // Above the right angle between sides, nothing is split
+        cube.crease_angle = Some(120.0);
+        let corner_normals = cube.corner_normals().unwrap();
+        let (verts, _, split_source) = split_creases(&cube, &corner_normals);
+        assert_eq!(verts.len(), 8);
+        assert!(split_source.is_empty());
+    }
+
+    #[test]
+    fn face_colors_split_only_shared_vertices_of_differing_faces() {
+        // Two triangles sharing the edge 1-2, plus a third of the first one's color
+        let indices = [0, 1, 2, 2, 1, 3, 0, 2, 4];
+        let (red, blue) = ([1.0, 0.0, 0.0], [0.0, 0.0, 1.0]);
+        let (tris, split_from, colors) = split_face_colors(&indices, 5, &[red, blue, red]);
+
+        assert_eq!(tris, [0, 1, 2, 5, 6, 3, 0, 2, 4]);
+        assert_eq!(split_from, [2, 1]);
+        assert_eq!(
+            colors,
+            [red, red, red, blue, red, blue, blue].map(Some).to_vec()
+        );
+    }
+
+    #[test]
+    fn pick_texels_decode_to_vertex_and_mesh() {
+        let texel = |vertex: u32, mesh: u32| [vertex.to_ne_bytes(), mesh.to_ne_bytes()].concat();
+        assert_eq!(decode_pick(&texel(0, 0)), None);
+        assert_eq!(decode_pick(&texel(1, 0)), Some(Pick { mesh: 0, vertex: 0 }));
+        assert_eq!(decode_pick(&texel(8, 3)), Some(Pick { mesh: 3, vertex: 7 }));
     }
 }
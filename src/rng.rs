@@ -0,0 +1,18 @@
+//! The random number generator used for field initialization, sweep orders and sampling.
+//!
+//! For a given seed, results are the same on every platform and in every build. The generator
+//! is PCG32 (`Lcg64Xsh32` from `rand_pcg`), whose output is fixed by the algorithm rather than
+//! by the `rand` version, and values are drawn through `rand` 0.8's distributions, which keep
+//! their output stable within the 0.8 series. Changing either changes seeded results, and is
+//! treated as a breaking change.
+
+use rand::SeedableRng;
+use rand_pcg::Pcg32;
+
+/// The generator behind every seeded random choice in this crate.
+pub type SeededRng = Pcg32;
+
+/// A [`SeededRng`] started from `seed`.
+pub fn seeded_rng(seed: u64) -> SeededRng {
+    SeededRng::seed_from_u64(seed)
+}
@@ -0,0 +1,214 @@
+//! Procedural meshes for tests and benchmarks.
+//!
+//! All shapes use counter-clockwise winding when viewed from outside, like a well-formed PLY
+//! file, and get their normals from [`InputMesh::from_triangles`].
+
+use std::{collections::HashMap, f32::consts::PI};
+
+use glam::{vec3, Vec3};
+
+use crate::mesh::InputMesh;
+
+/// Unit square in the XY plane centered on the origin, split into `n` by `n` quads.
+pub fn plane(n: usize) -> InputMesh {
+    let n = n.max(1);
+    let mut vertices = Vec::with_capacity((n + 1) * (n + 1));
+    for j in 0..=n {
+        for i in 0..=n {
+            vertices.push(vec3(
+                i as f32 / n as f32 - 0.5,
+                j as f32 / n as f32 - 0.5,
+                0.0,
+            ));
+        }
+    }
+
+    let mut tris = Vec::with_capacity(2 * n * n);
+    for j in 0..n {
+        for i in 0..n {
+            let a = j * (n + 1) + i;
+            let b = a + 1;
+            let c = a + n + 1;
+            let d = c + 1;
+            tris.push([a, b, d]);
+            tris.push([a, d, c]);
+        }
+    }
+
+    InputMesh::from_triangles(vertices, tris)
+}
+
+/// Unit cube centered on the origin, with its eight corners shared between two triangles per
+/// side, so that its edges are hard creases.
+pub fn cube() -> InputMesh {
+    let vertices = (0..8)
+        .map(|i| {
+            vec3(
+                (i & 1) as f32 - 0.5,
+                (i >> 1 & 1) as f32 - 0.5,
+                (i >> 2 & 1) as f32 - 0.5,
+            )
+        })
+        .collect();
+
+    // Corners of each side, counter-clockwise seen from outside
+    let sides = [
+        [0, 2, 3, 1],
+        [4, 5, 7, 6],
+        [0, 1, 5, 4],
+        [2, 6, 7, 3],
+        [0, 4, 6, 2],
+        [1, 3, 7, 5],
+    ];
+    let tris = sides
+        .iter()
+        .flat_map(|&[a, b, c, d]| [[a, b, c], [a, c, d]])
+        .collect();
+
+    InputMesh::from_triangles(vertices, tris)
+}
+
+/// Unit sphere with `rings` latitude bands and `segments` longitude slices.
+pub fn uv_sphere(rings: usize, segments: usize) -> InputMesh {
+    let rings = rings.max(2);
+    let segments = segments.max(3);
+
+    let mut vertices = vec![Vec3::Z];
+    for r in 1..rings {
+        let theta = PI * r as f32 / rings as f32;
+        for s in 0..segments {
+            let phi = 2.0 * PI * s as f32 / segments as f32;
+            vertices.push(vec3(
+                theta.sin() * phi.cos(),
+                theta.sin() * phi.sin(),
+                theta.cos(),
+            ));
+        }
+    }
+    vertices.push(-Vec3::Z);
+
+    let south = vertices.len() - 1;
+    let ring = |r: usize, s: usize| 1 + (r - 1) * segments + s % segments;
+
+    let mut tris = Vec::new();
+    for s in 0..segments {
+        tris.push([0, ring(1, s), ring(1, s + 1)]);
+    }
+    for r in 1..rings - 1 {
+        for s in 0..segments {
+            let a = ring(r, s);
+            let b = ring(r + 1, s);
+            let c = ring(r + 1, s + 1);
+            let d = ring(r, s + 1);
+            tris.push([a, b, c]);
+            tris.push([a, c, d]);
+        }
+    }
+    for s in 0..segments {
+        tris.push([south, ring(rings - 1, s + 1), ring(rings - 1, s)]);
+    }
+
+    InputMesh::from_triangles(vertices, tris)
+}
+
+/// Torus around the Z axis with `rings` segments along the tube and `segments` around it.
+pub fn torus(rings: usize, segments: usize, major_radius: f32, minor_radius: f32) -> InputMesh {
+    let rings = rings.max(3);
+    let segments = segments.max(3);
+
+    let mut vertices = Vec::with_capacity(rings * segments);
+    for r in 0..rings {
+        let u = 2.0 * PI * r as f32 / rings as f32;
+        for s in 0..segments {
+            let v = 2.0 * PI * s as f32 / segments as f32;
+            let radius = major_radius + minor_radius * v.cos();
+            vertices.push(vec3(
+                radius * u.cos(),
+                radius * u.sin(),
+                minor_radius * v.sin(),
+            ));
+        }
+    }
+
+    let index = |r: usize, s: usize| (r % rings) * segments + s % segments;
+
+    let mut tris = Vec::with_capacity(2 * rings * segments);
+    for r in 0..rings {
+        for s in 0..segments {
+            let a = index(r, s);
+            let b = index(r + 1, s);
+            let c = index(r + 1, s + 1);
+            let d = index(r, s + 1);
+            tris.push([a, b, c]);
+            tris.push([a, c, d]);
+        }
+    }
+
+    InputMesh::from_triangles(vertices, tris)
+}
+
+/// Unit sphere made by repeatedly subdividing an icosahedron.
+pub fn icosphere(subdivisions: usize) -> InputMesh {
+    let t = (1.0 + 5f32.sqrt()) / 2.0;
+    let mut vertices = [
+        [-1.0, t, 0.0],
+        [1.0, t, 0.0],
+        [-1.0, -t, 0.0],
+        [1.0, -t, 0.0],
+        [0.0, -1.0, t],
+        [0.0, 1.0, t],
+        [0.0, -1.0, -t],
+        [0.0, 1.0, -t],
+        [t, 0.0, -1.0],
+        [t, 0.0, 1.0],
+        [-t, 0.0, -1.0],
+        [-t, 0.0, 1.0],
+    ]
+    .into_iter()
+    .map(|v| Vec3::from(v).normalize())
+    .collect::<Vec<_>>();
+    let mut tris = vec![
+        [0, 11, 5],
+        [0, 5, 1],
+        [0, 1, 7],
+        [0, 7, 10],
+        [0, 10, 11],
+        [1, 5, 9],
+        [5, 11, 4],
+        [11, 10, 2],
+        [10, 7, 6],
+        [7, 1, 8],
+        [3, 9, 4],
+        [3, 4, 2],
+        [3, 2, 6],
+        [3, 6, 8],
+        [3, 8, 9],
+        [4, 9, 5],
+        [2, 4, 11],
+        [6, 2, 10],
+        [8, 6, 7],
+        [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        let mut midpoints = HashMap::new();
+        let mut midpoint = |a: usize, b: usize, vertices: &mut Vec<Vec3>| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                vertices.push((vertices[a] + vertices[b]).normalize());
+                vertices.len() - 1
+            })
+        };
+
+        tris = tris
+            .into_iter()
+            .flat_map(|[a, b, c]| {
+                let ab = midpoint(a, b, &mut vertices);
+                let bc = midpoint(b, c, &mut vertices);
+                let ca = midpoint(c, a, &mut vertices);
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    InputMesh::from_triangles(vertices, tris)
+}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Context, Result};
+use byteorder::{ByteOrder, LittleEndian};
+use glam::{vec3, Vec3};
+
+use crate::{format::LoadOptions, mesh::InputMesh, stream::AsyncStreamReader};
+
+/// Size of a binary STL header: 80 free-form bytes, then the facet count.
+pub(crate) const HEADER_SIZE: usize = 84;
+
+/// Size of a binary STL facet: normal, three corners, and a 2 byte attribute count.
+pub(crate) const FACET_SIZE: usize = 50;
+
+/// Most facets to reserve room for up front when the stream length is unknown.
+const UNCHECKED_CAPACITY: usize = 1 << 16;
+
+/// STL stores every triangle with its own copies of its corners, so merge bitwise identical
+/// positions back into shared vertices.
+fn weld(corners: Vec<Vec3>) -> InputMesh {
+    let mut vertices = Vec::new();
+    let mut indices = HashMap::new();
+    let mut tris = Vec::with_capacity(corners.len() / 3);
+
+    for tri in corners.chunks_exact(3) {
+        let mut t = [0; 3];
+        for (i, v) in tri.iter().enumerate() {
+            t[i] = *indices
+                .entry(v.to_array().map(f32::to_bits))
+                .or_insert_with(|| {
+                    vertices.push(*v);
+                    vertices.len() - 1
+                });
+        }
+        tris.push(t);
+    }
+
+    InputMesh::from_triangles(vertices, tris)
+}
+
+pub async fn load_ascii_stl(reader: &mut AsyncStreamReader) -> Result<InputMesh> {
+    let mut corners = Vec::new();
+
+    while let Ok(line) = reader.read_line_utf8().await {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("vertex") => {
+                let mut coord = || -> Result<f32> {
+                    Ok(tokens
+                        .next()
+                        .context("Missing vertex coordinate")?
+                        .parse()?)
+                };
+                corners.push(vec3(coord()?, coord()?, coord()?));
+            }
+            Some("endsolid") => break,
+            _ => (),
+        }
+    }
+
+    if corners.len() % 3 != 0 {
+        bail!("STL facet with a vertex count other than 3");
+    }
+    Ok(weld(corners))
+}
+
+/// Load a binary STL. If `options.strict`, anything but whitespace after the declared facets is
+/// an error.
+pub async fn load_binary_stl(
+    reader: &mut AsyncStreamReader,
+    options: &LoadOptions,
+) -> Result<InputMesh> {
+    reader.read_exact(HEADER_SIZE - 4).await?;
+    let count = LittleEndian::read_u32(reader.read_exact(4).await?) as usize;
+
+    // The count comes straight from the file, so don't trust it with an allocation until it is
+    // known to fit in what is left of the stream
+    let capacity = match reader.remaining() {
+        Some(remaining) if count > remaining / FACET_SIZE => bail!(
+            "Binary STL declares {} triangles but only has room for {}",
+            count,
+            remaining / FACET_SIZE
+        ),
+        Some(_) => count,
+        None => count.min(UNCHECKED_CAPACITY),
+    };
+    let mut corners = Vec::with_capacity(capacity * 3);
+    for _ in 0..count {
+        // Facet normal, three corners, and a 2 byte attribute count
+        let facet = reader.read_exact(FACET_SIZE).await?;
+        for i in 1..4 {
+            let mut v = [0f32; 3];
+            LittleEndian::read_f32_into(&facet[12 * i..12 * (i + 1)], &mut v);
+            corners.push(Vec3::from(v));
+        }
+    }
+
+    if options.strict && reader.has_trailing_data().await {
+        bail!("Binary STL has data after its {} declared triangles", count);
+    }
+    Ok(weld(corners))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_stl_count_past_the_end_is_rejected() {
+        let mut data = vec![0; 84 + 50];
+        data[80..84].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut reader = AsyncStreamReader::from_bytes(data);
+
+        let options = LoadOptions::default();
+        match futures::executor::block_on(load_binary_stl(&mut reader, &options)) {
+            Err(err) => assert!(err.to_string().contains("only has room for 1")),
+            Ok(_) => panic!("Loaded an STL with more triangles than bytes"),
+        }
+    }
+
+    #[test]
+    fn strict_rejects_data_after_the_declared_facets() {
+        let mut data = vec![0; HEADER_SIZE + FACET_SIZE];
+        data[80..84].copy_from_slice(&1u32.to_le_bytes());
+        data.extend(b"garbage");
+        let load = |options: &LoadOptions| {
+            let mut reader = AsyncStreamReader::from_bytes(data.clone());
+            futures::executor::block_on(load_binary_stl(&mut reader, options))
+        };
+
+        assert_eq!(load(&LoadOptions::default()).unwrap().tris.len(), 1);
+        let mut options = LoadOptions::default();
+        options.set_strict(true);
+        match load(&options) {
+            Err(err) => assert_eq!(
+                err.to_string(),
+                "Binary STL has data after its 1 declared triangles"
+            ),
+            Ok(_) => panic!("Loaded an STL with trailing data in strict mode"),
+        }
+    }
+}
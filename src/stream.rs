@@ -1,11 +1,23 @@
 use std::future::Future;
 
-use anyhow::{Error, Result};
+use anyhow::{bail, Result};
 use futures::future::LocalBoxFuture;
 
+/// Cap on how many bytes read past a mark are retained for replay.
+const MARK_LIMIT: usize = 1 << 20;
+
+/// Default for [`AsyncStreamReader::set_min_fill`].
+pub const DEFAULT_MIN_FILL: usize = 64 << 10;
+
 pub struct AsyncStreamReader {
     buf: Vec<u8>,
+    /// Stream position of the start of `buf`
+    offset: usize,
     last_end: usize,
+    len: Option<usize>,
+    mark: Option<usize>,
+    mark_overflowed: bool,
+    min_fill: usize,
     next_buffer: Box<dyn FnMut() -> LocalBoxFuture<'static, Option<Vec<u8>>>>,
 }
 
@@ -15,53 +27,276 @@ impl AsyncStreamReader {
     ) -> Self {
         Self {
             buf: Vec::new(),
+            offset: 0,
             last_end: 0,
+            len: None,
+            mark: None,
+            mark_overflowed: false,
+            min_fill: DEFAULT_MIN_FILL,
             next_buffer: Box::new(move || Box::pin(next_buffer())),
         }
     }
 
+    /// Create a reader over a buffer that is already fully in memory.
+    pub fn from_bytes(data: Vec<u8>) -> Self {
+        let len = data.len();
+        let mut data = Some(data);
+        let mut reader = Self::new(move || futures::future::ready(data.take()));
+        reader.set_len(len);
+        reader
+    }
+
+    /// Declare the total length of the stream, if known up front, so that parsers can check
+    /// declared counts against [`remaining`](Self::remaining) before allocating for them.
+    pub fn set_len(&mut self, len: usize) {
+        self.len = Some(len);
+    }
+
+    /// Number of bytes left after the current position, if the stream length is known.
+    pub fn remaining(&self) -> Option<usize> {
+        self.len
+            .map(|len| len.saturating_sub(self.offset + self.last_end))
+    }
+
+    /// Keep pulling chunks from the stream on each refill until at least `min_fill` bytes have
+    /// arrived, so that streams handing out tiny chunks don't cost an await per few bytes read.
+    pub fn set_min_fill(&mut self, min_fill: usize) {
+        self.min_fill = min_fill;
+    }
+
+    /// Remember the current position so it can be returned to with
+    /// [`reset_to_mark`](Self::reset_to_mark). Bytes read after the mark stay buffered until
+    /// it is cleared, up to the mark limit.
+    pub fn mark(&mut self) {
+        self.mark = Some(self.last_end);
+        self.mark_overflowed = false;
+    }
+
+    /// Rewind to the position saved by the last call to [`mark`](Self::mark). Fails if more
+    /// than the mark limit has been buffered since.
+    pub fn reset_to_mark(&mut self) -> Result<()> {
+        self.enforce_mark_limit();
+        match self.mark {
+            Some(mark) => {
+                self.last_end = mark;
+                Ok(())
+            }
+            None if self.mark_overflowed => {
+                bail!("Read more than {} bytes past the mark", MARK_LIMIT)
+            }
+            None => bail!("Reader has no mark to reset to"),
+        }
+    }
+
+    /// Forget the saved position, letting already read bytes be discarded.
+    pub fn clear_mark(&mut self) {
+        self.mark = None;
+        self.mark_overflowed = false;
+    }
+
+    /// Drop the mark once more than the mark limit has been read past it. Bytes that arrived in
+    /// the same chunk but haven't been read yet don't count, since they are buffered regardless.
+    fn enforce_mark_limit(&mut self) {
+        if let Some(mark) = self.mark {
+            if self.last_end - mark > MARK_LIMIT {
+                self.mark = None;
+                self.mark_overflowed = true;
+            }
+        }
+    }
+
+    /// Append the next chunks of the stream to the buffer, up to the minimum fill. Returns false
+    /// at the end of the stream.
+    async fn fill(&mut self) -> bool {
+        let start = self.buf.len();
+        let mut any = false;
+        while self.buf.len() - start < self.min_fill.max(1) {
+            let Some(mut next) = (self.next_buffer)().await else {
+                break;
+            };
+            self.buf.append(&mut next);
+            any = true;
+        }
+        any
+    }
+
     fn shift_leftovers(&mut self) {
-        self.buf = self.buf[self.last_end..].to_vec();
+        self.enforce_mark_limit();
+        let keep_from = self.mark.unwrap_or(self.last_end);
+        self.buf.drain(..keep_from);
+        self.offset += keep_from;
+        self.last_end -= keep_from;
+        if let Some(mark) = self.mark.as_mut() {
+            *mark -= keep_from;
+        }
     }
 
+    /// Read up to the next newline, which is not included. A last line without a trailing
+    /// newline is still returned; only reading at the very end of the stream fails.
     pub async fn read_line(&mut self) -> Result<&[u8]> {
         self.shift_leftovers();
 
-        let mut len = 0;
+        let start = self.last_end;
+        let mut len = start;
         loop {
             if let Some(idx) = self.buf[len..].iter().position(|b| *b == b'\n') {
                 len += idx + 1;
                 break;
             }
             len = self.buf.len();
-            let mut next = (self.next_buffer)()
-                .await
-                .ok_or_else(|| Error::msg("Reached EOF before a complete line"))?;
-            self.buf.append(&mut next);
+            if !self.fill().await {
+                if len == start {
+                    bail!("Reached EOF");
+                }
+
+                self.last_end = len;
+                return Ok(&self.buf[start..len]);
+            }
         }
         self.last_end = len;
-        Ok(&self.buf[..len - 1])
+        Ok(&self.buf[start..len - 1])
     }
 
     pub async fn read_line_utf8(&mut self) -> Result<&str> {
         Ok(std::str::from_utf8(self.read_line().await?)?)
     }
 
+    /// Whether anything but whitespace follows the current position, looking at what is already
+    /// buffered and at most one more refill. Nothing is consumed.
+    pub async fn has_trailing_data(&mut self) -> bool {
+        self.shift_leftovers();
+        let trailing = |buf: &[u8]| buf.iter().any(|b| !b.is_ascii_whitespace());
+        if trailing(&self.buf[self.last_end..]) {
+            return true;
+        }
+        let end = self.buf.len();
+        self.fill().await && trailing(&self.buf[end..])
+    }
+
+    /// Read `len` bytes, or whatever is left if the stream ends before that.
+    pub async fn read_up_to(&mut self, len: usize) -> &[u8] {
+        if self.buf.len() < self.last_end + len {
+            self.shift_leftovers();
+        }
+
+        while self.buf.len() < self.last_end + len && self.fill().await {}
+
+        let start = self.last_end;
+        self.last_end = self.buf.len().min(start + len);
+        &self.buf[start..self.last_end]
+    }
+
     pub async fn read_exact(&mut self, len: usize) -> Result<&[u8]> {
         if self.buf.len() < self.last_end + len {
             self.shift_leftovers();
-            self.last_end = 0;
         }
 
-        while self.buf.len() < len {
-            let mut next = (self.next_buffer)()
-                .await
-                .ok_or_else(|| Error::msg("Reached EOF before filling buffer"))?;
-            self.buf.append(&mut next);
+        while self.buf.len() < self.last_end + len {
+            if !self.fill().await {
+                bail!("Reached EOF before filling buffer");
+            }
         }
 
         let start = self.last_end;
         self.last_end += len;
-        return Ok(&self.buf[start..start + len]);
+        Ok(&self.buf[start..start + len])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use futures::executor::block_on;
+
+    use super::*;
+
+    /// A reader handing out `data` in chunks of `size` bytes, with no length declared.
+    fn chunked(data: &[u8], size: usize) -> AsyncStreamReader {
+        let mut chunks = data
+            .chunks(size)
+            .map(<[u8]>::to_vec)
+            .collect::<VecDeque<_>>();
+        let mut reader = AsyncStreamReader::new(move || futures::future::ready(chunks.pop_front()));
+        reader.set_min_fill(0);
+        reader
+    }
+
+    #[test]
+    fn reset_rereads_everything_since_the_mark() {
+        let mut reader = chunked(b"header\nfirst\nsecond\nrest", 3);
+        block_on(async {
+            assert_eq!(reader.read_line().await.unwrap(), b"header");
+            reader.mark();
+            assert_eq!(reader.read_line().await.unwrap(), b"first");
+            assert_eq!(reader.read_exact(4).await.unwrap(), b"seco");
+
+            reader.reset_to_mark().unwrap();
+            assert_eq!(reader.read_line().await.unwrap(), b"first");
+            assert_eq!(reader.read_line().await.unwrap(), b"second");
+            reader.clear_mark();
+            assert_eq!(reader.read_line().await.unwrap(), b"rest");
+        });
+    }
+
+    #[test]
+    fn reset_fails_once_past_the_mark_limit() {
+        let data = vec![0; MARK_LIMIT + 2];
+        block_on(async {
+            // Up to the limit can still be replayed, however it arrived
+            for mut reader in [
+                chunked(&data, 4096),
+                AsyncStreamReader::from_bytes(data.clone()),
+            ] {
+                reader.mark();
+                reader.read_exact(MARK_LIMIT).await.unwrap();
+                reader.reset_to_mark().unwrap();
+            }
+
+            let mut reader = chunked(&data, 4096);
+            reader.mark();
+            reader.read_exact(MARK_LIMIT + 1).await.unwrap();
+            let err = reader.reset_to_mark().unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "Read more than 1048576 bytes past the mark"
+            );
+        });
+    }
+
+    #[test]
+    fn unterminated_last_line_is_returned() {
+        let mut reader = chunked(b"ply\r\nend_header", 4);
+        block_on(async {
+            assert_eq!(reader.read_line().await.unwrap(), b"ply\r");
+            assert_eq!(reader.read_line().await.unwrap(), b"end_header");
+            let err = reader.read_line().await.unwrap_err();
+            assert_eq!(err.to_string(), "Reached EOF");
+        });
+    }
+
+    #[test]
+    fn min_fill_gathers_small_chunks_per_refill() {
+        use std::{cell::Cell, rc::Rc};
+
+        let pulls = Rc::new(Cell::new(0));
+        let counter = pulls.clone();
+        let mut bytes = (0..64u8).collect::<VecDeque<_>>();
+        let mut reader = AsyncStreamReader::new(move || {
+            counter.set(counter.get() + 1);
+            futures::future::ready(bytes.pop_front().map(|b| vec![b]))
+        });
+        reader.set_min_fill(16);
+
+        block_on(async {
+            // One byte a chunk, but the first refill takes 16 of them at once
+            assert_eq!(reader.read_exact(4).await.unwrap(), [0, 1, 2, 3]);
+            assert_eq!(pulls.get(), 16);
+            assert_eq!(reader.read_exact(12).await.unwrap()[11], 15);
+            assert_eq!(pulls.get(), 16);
+
+            assert_eq!(reader.read_exact(1).await.unwrap(), [16]);
+            assert_eq!(pulls.get(), 32);
+        });
     }
 }
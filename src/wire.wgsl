@@ -0,0 +1,48 @@
+struct Uniforms {
+    view_transform: mat4x4<f32>,
+    model_transform: mat4x4<f32>,
+    output: vec4<f32>,
+    normals: vec4<f32>,
+    // x: width of overlay lines in pixels
+    // yz: size of the viewport in pixels
+    lines: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    // Signed distance from the middle of the line, in pixels
+    @location(0) across: f32,
+};
+
+@group(0)
+@binding(0)
+var<uniform> uniforms: Uniforms;
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) index: u32,
+    @location(0) start: vec3<f32>,
+    @location(1) end: vec3<f32>,
+) -> VertexOutput {
+    let transform = uniforms.view_transform * uniforms.model_transform;
+    let corner = expand_line(
+        transform * vec4<f32>(start, 1.0),
+        transform * vec4<f32>(end, 1.0),
+        index,
+    );
+
+    var result: VertexOutput;
+    result.position = corner.position;
+    result.across = corner.across;
+    return result;
+}
+
+@fragment
+fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(0.05, 0.05, 0.05, line_coverage(vertex.across));
+}
+
+@fragment
+fn fs_streamline(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(0.9, 0.5, 0.1, line_coverage(vertex.across));
+}